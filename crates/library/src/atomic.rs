@@ -0,0 +1,97 @@
+//! Saving files so that a save that fails or is interrupted never leaves a half-written file
+//! behind: everything is written to a temporary file next to the destination first, which is
+//! then moved over it.
+
+use std::{
+	future::Future,
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+/// Replace `path` with the file that `write` writes to the temporary path it is given, once it
+/// is complete. `write` must make sure the file has reached the disk before it returns. If it
+/// fails, the temporary file is removed and any existing file is left as it was.
+pub(crate) async fn write_with<F, E>(path: &Path, write: impl FnOnce(PathBuf) -> F) -> Result<(), E>
+where
+	F: Future<Output = Result<(), E>>,
+	E: From<std::io::Error>,
+{
+	let temp = temp_path(path);
+	if let Err(e) = write(temp.clone()).await {
+		// Nothing else is using the temporary file, so it's fine if this fails too.
+		let _ = tokio::fs::remove_file(&temp).await;
+		return Err(e);
+	}
+	tokio::fs::rename(&temp, path).await?;
+	sync_parent(path).await?;
+	Ok(())
+}
+
+/// Replace `path` with `data`, as [`write_with`] does.
+pub(crate) async fn write(path: &Path, data: &[u8]) -> std::io::Result<()> {
+	write_with(path, |temp| async move {
+		let mut file = File::create(&temp).await?;
+		file.write_all(data).await?;
+		file.sync_all().await
+	})
+	.await
+}
+
+/// Where to write a file before moving it to `path`. It must be in the same directory so that
+/// the move can't cross file systems, and unique so that saves running at the same time, even
+/// from the same process, don't write over each other's files.
+fn temp_path(path: &Path) -> PathBuf {
+	static NEXT: AtomicUsize = AtomicUsize::new(0);
+	let name = path.file_name().unwrap_or_default().to_string_lossy();
+	let n = NEXT.fetch_add(1, Ordering::Relaxed);
+	path.with_file_name(format!(".{name}.{}.{n}.tmp", std::process::id()))
+}
+
+/// Make sure a rename into `path`'s directory has reached the disk.
+async fn sync_parent(path: &Path) -> std::io::Result<()> {
+	#[cfg(unix)]
+	{
+		let parent = match path.parent() {
+			Some(parent) if !parent.as_os_str().is_empty() => parent,
+			_ => Path::new("."),
+		};
+		File::open(parent).await?.sync_all().await?;
+	}
+	#[cfg(not(unix))]
+	let _ = path;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::temp_dir;
+	use test_log::test;
+
+	#[test]
+	fn temp_paths_are_unique() {
+		let path = Path::new("pieces/waltz.saug");
+		let (a, b) = (temp_path(path), temp_path(path));
+		assert_ne!(a, b);
+		assert_eq!(a.parent(), path.parent());
+	}
+
+	#[test(tokio::test)]
+	async fn failed_write_keeps_original() {
+		let dir = temp_dir("atomic");
+		let path = dir.join("index.ron");
+		write(&path, b"before").await.unwrap();
+		let failed = write_with(&path, |temp| async move {
+			std::fs::write(temp, "half")?;
+			Err(std::io::Error::other("interrupted"))
+		});
+		assert!(failed.await.is_err());
+
+		let contents = std::fs::read(&path).unwrap();
+		let leftovers = std::fs::read_dir(&dir).unwrap().count();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(contents, b"before");
+		assert_eq!(leftovers, 1, "temporary file was left behind");
+	}
+}
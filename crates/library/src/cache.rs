@@ -0,0 +1,412 @@
+//! Moving page images between [`ImageState`]s on demand while keeping memory use under a budget.
+//!
+//! Each image climbs the chain `Path → Encoded → Decoded → Rendered` when it is
+//! [loaded](ImageCache::load), and is demoted back down one stage at a time when the cache goes
+//! over its budget. Images far from the [viewport](ImageCache::set_viewport) go first, then the
+//! least recently used. Images in the viewport are never demoted.
+//!
+//! Pages of a [PDF](crate::pdf) start out only known by their page number, and go straight to
+//! `Decoded` when they are rasterized at the [width](ImageCache::set_pdf_width) they are shown at.
+
+use crate::{
+	dynamic_to_rendered, pdf::PdfPages, rendered_to_dynamic, ImageRef, ImageState, Pages, PieceError, SkiaImage,
+};
+use image::{DynamicImage, ImageOutputFormat};
+use log::debug;
+use std::{
+	cmp::Reverse,
+	io::Cursor,
+	ops::Range,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+/// How far along the chain an image is. Each stage can be made from the one before it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+	/// Only known by where to read it from: a file, or a page of a PDF.
+	Path,
+	Encoded,
+	Decoded,
+	Rendered,
+}
+
+#[derive(Debug)]
+pub struct ImageCache {
+	slots: Vec<Slot>,
+	/// Bytes of image data that may be held before images are demoted.
+	budget: usize,
+	viewport: Option<Range<usize>>,
+	/// Incremented every time an image is loaded, to tell which was used least recently.
+	clock: u64,
+	/// The PDF that the images are the pages of, if they come from one.
+	pdf: Option<Arc<PdfPages>>,
+	/// Width in pixels that PDF pages are rasterized at.
+	pdf_width: u32,
+}
+
+/// Every stage an image is currently held in. Lower stages are kept when higher ones are made
+/// (except decoded pixels once they have been rendered) so that demoting is cheap.
+#[derive(Debug, Default)]
+struct Slot {
+	path: Option<PathBuf>,
+	encoded: Option<Vec<u8>>,
+	decoded: Option<DynamicImage>,
+	rendered: Option<SkiaImage>,
+	last_used: u64,
+	/// Whether the image is a page of the cache's PDF, so it can always be rasterized again.
+	from_pdf: bool,
+	/// Width the page was last rasterized at, if it is from a PDF.
+	raster_width: u32,
+}
+
+impl ImageCache {
+	pub fn new(images: Vec<ImageState>, budget: usize) -> Self {
+		let slots = images.into_iter().map(Slot::from).collect();
+		Self { slots, budget, viewport: None, clock: 0, pdf: None, pdf_width: crate::pdf::DEFAULT_WIDTH }
+	}
+
+	/// A cache of a piece's pages, whether they are images or a PDF.
+	pub fn from_pages(pages: Pages, budget: usize) -> Self {
+		match pages {
+			Pages::Images(images) => Self::new(images, budget),
+			Pages::Pdf(pdf) => Self {
+				slots: (0..pdf.len()).map(|_| Slot { from_pdf: true, ..Slot::default() }).collect(),
+				pdf: Some(pdf),
+				..Self::new(Vec::new(), budget)
+			},
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.slots.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.slots.is_empty()
+	}
+
+	pub fn budget(&self) -> usize {
+		self.budget
+	}
+
+	pub fn pdf_width(&self) -> u32 {
+		self.pdf_width
+	}
+
+	/// Set the width in pixels that PDF pages should be shown at, such as after zooming. Pages
+	/// are rasterized again at the new width when they are next [loaded](Self::load).
+	pub fn set_pdf_width(&mut self, width: u32) {
+		self.pdf_width = width;
+	}
+
+	/// Change the budget, demoting images straight away if it went down.
+	pub fn set_budget(&mut self, budget: usize) {
+		self.budget = budget;
+		self.trim(None);
+	}
+
+	/// Bytes of image data currently held. Images only known by their path take nothing.
+	pub fn memory_used(&self) -> usize {
+		self.slots.iter().map(Slot::size).sum()
+	}
+
+	/// Set the images that are being shown, which are never demoted. Other images are demoted
+	/// in order of how far they are from these. `None` demotes least recently used images first.
+	pub fn set_viewport(&mut self, viewport: Option<Range<usize>>) {
+		self.viewport = viewport;
+		self.trim(None);
+	}
+
+	/// The highest stage image `i` is held in.
+	pub fn stage(&self, i: usize) -> Option<Stage> {
+		self.slots.get(i).map(Slot::stage)
+	}
+
+	/// Image `i` in the highest stage it is held in, without loading anything.
+	pub fn get(&self, i: usize) -> Option<ImageRef<'_>> {
+		self.slots.get(i).map(Slot::image_ref)
+	}
+
+	/// Promote image `i` to at least `stage` and mark it as just used, then demote other images
+	/// if that put the cache over budget.
+	///
+	/// Reading and decoding can take a while, so avoid calling this on an async or UI thread.
+	pub fn load(&mut self, i: usize, stage: Stage) -> Result<ImageRef<'_>, PieceError> {
+		self.clock += 1;
+		let slot = self.slots.get_mut(i).ok_or(PieceError::NoSuchImage(i))?;
+		slot.last_used = self.clock;
+		let pdf = self.pdf.as_deref().map(|pdf| (pdf, i, self.pdf_width));
+		slot.promote(stage, pdf)?;
+		self.trim(Some(i));
+		Ok(self.slots[i].image_ref())
+	}
+
+	/// Demote images until the cache is within budget or only images in use are left.
+	/// `keep` is never demoted.
+	fn trim(&mut self, keep: Option<usize>) {
+		let mut used = self.memory_used();
+		while used > self.budget {
+			let Some(i) = self.next_to_demote(keep) else {
+				debug!("{used} bytes of images are in use, over the budget of {}", self.budget);
+				return;
+			};
+			let slot = &mut self.slots[i];
+			let before = slot.size();
+			if !slot.demote() {
+				// Shouldn't happen since only demotable slots are picked, but don't loop forever.
+				return;
+			}
+			used = used - before + slot.size();
+		}
+	}
+
+	fn next_to_demote(&self, keep: Option<usize>) -> Option<usize> {
+		self.slots
+			.iter()
+			.enumerate()
+			.filter(|&(i, slot)| Some(i) != keep && self.distance(i) > 0 && slot.can_demote())
+			.max_by_key(|&(i, slot)| (self.distance(i), Reverse(slot.last_used)))
+			.map(|(i, _)| i)
+	}
+
+	/// How far image `i` is from the viewport. Every image is 1 away when there is no viewport.
+	fn distance(&self, i: usize) -> usize {
+		match &self.viewport {
+			None => 1,
+			Some(viewport) if i < viewport.start => viewport.start - i,
+			Some(viewport) if i >= viewport.end => i + 1 - viewport.end,
+			Some(_) => 0,
+		}
+	}
+
+	/// The pages back again, with each image in the lowest stage it is held in, which is the
+	/// cheapest to save.
+	pub fn into_pages(self) -> Pages {
+		match self.pdf {
+			Some(pdf) => Pages::Pdf(pdf),
+			None => Pages::Images(self.slots.into_iter().map(Slot::into_state).collect()),
+		}
+	}
+}
+
+impl From<ImageState> for Slot {
+	fn from(state: ImageState) -> Self {
+		let mut slot = Self::default();
+		match state {
+			ImageState::Path(path) => slot.path = Some(path),
+			ImageState::Encoded(data) => slot.encoded = Some(data),
+			ImageState::Decoded(img) => slot.decoded = Some(img),
+			ImageState::Rendered(img) => slot.rendered = Some(img),
+		}
+		slot
+	}
+}
+
+impl Slot {
+	fn stage(&self) -> Stage {
+		if self.rendered.is_some() {
+			Stage::Rendered
+		} else if self.decoded.is_some() {
+			Stage::Decoded
+		} else if self.encoded.is_some() {
+			Stage::Encoded
+		} else {
+			Stage::Path
+		}
+	}
+
+	fn image_ref(&self) -> ImageRef<'_> {
+		match (&self.rendered, &self.decoded, &self.encoded, &self.path) {
+			(Some(img), ..) => ImageRef::Rendered(img.clone()),
+			(None, Some(img), ..) => ImageRef::Decoded(img),
+			(None, None, Some(data), _) => ImageRef::Encoded(data),
+			(None, None, None, path) => ImageRef::Path(path.as_deref().unwrap_or(Path::new(""))),
+		}
+	}
+
+	fn size(&self) -> usize {
+		let encoded = self.encoded.as_ref().map_or(0, Vec::len);
+		let decoded = self.decoded.as_ref().map_or(0, |img| img.as_bytes().len());
+		// Rendered images are always uploaded as RGBA8888
+		let rendered = self.rendered.as_ref().map_or(0, |img| img.width() as usize * img.height() as usize * 4);
+		encoded + decoded + rendered
+	}
+
+	/// Can the image be made again if every stage is dropped?
+	fn has_source(&self) -> bool {
+		self.path.is_some() || self.from_pdf
+	}
+
+	/// `pdf` is the PDF, page number and width to rasterize at if the image is a page of a PDF.
+	fn promote(&mut self, stage: Stage, pdf: Option<(&PdfPages, usize, u32)>) -> Result<(), PieceError> {
+		if let Some((pdf, page, width)) = pdf {
+			if self.raster_width != width {
+				self.decoded = None;
+				self.rendered = None;
+			}
+			if stage > Stage::Path && self.decoded.is_none() && self.rendered.is_none() {
+				self.decoded = Some(pdf.render(page, width)?);
+				self.raster_width = width;
+			}
+		}
+		if stage <= self.stage() {
+			return Ok(());
+		}
+		if self.encoded.is_none() && self.decoded.is_none() {
+			let path = self.path.as_ref().ok_or_else(|| PieceError::Missing(PathBuf::new()))?;
+			self.encoded = Some(std::fs::read(path)?);
+		}
+		if stage >= Stage::Decoded && self.decoded.is_none() {
+			let data = self.encoded.as_deref().unwrap_or_default();
+			self.decoded = Some(image::load_from_memory(data).map_err(PieceError::Image)?);
+		}
+		if stage == Stage::Rendered {
+			let decoded = self.decoded.as_ref().ok_or(PieceError::Unrenderable)?;
+			self.rendered = Some(dynamic_to_rendered(decoded).ok_or(PieceError::Unrenderable)?);
+			// Decoded pixels take as much memory as rendered ones, and can be made again.
+			if self.encoded.is_some() || self.has_source() {
+				self.decoded = None;
+			}
+		}
+		Ok(())
+	}
+
+	/// Whether demoting would free anything. An image that is only held encoded has to stay that
+	/// way unless it can be read back from its path or PDF.
+	fn can_demote(&self) -> bool {
+		match self.stage() {
+			Stage::Path => false,
+			Stage::Encoded => self.has_source(),
+			Stage::Decoded | Stage::Rendered => true,
+		}
+	}
+
+	/// Drop the highest stage. If it is the only copy of the image, the stage below is made from
+	/// it first. Returns whether anything was demoted.
+	fn demote(&mut self) -> bool {
+		match self.stage() {
+			Stage::Path => false,
+			Stage::Encoded if !self.has_source() => false,
+			Stage::Encoded => {
+				self.encoded = None;
+				true
+			}
+			Stage::Decoded => {
+				if self.encoded.is_none() && !self.has_source() {
+					let Some(data) = self.decoded.as_ref().and_then(encode_png) else { return false };
+					self.encoded = Some(data);
+				}
+				self.decoded = None;
+				true
+			}
+			Stage::Rendered => {
+				if self.decoded.is_none() && self.encoded.is_none() && !self.has_source() {
+					let Some(img) = self.rendered.as_ref().and_then(rendered_to_dynamic) else { return false };
+					self.decoded = Some(img);
+				}
+				self.rendered = None;
+				true
+			}
+		}
+	}
+
+	fn into_state(self) -> ImageState {
+		match self {
+			Self { path: Some(path), .. } => ImageState::Path(path),
+			Self { encoded: Some(data), .. } => ImageState::Encoded(data),
+			Self { decoded: Some(img), .. } => ImageState::Decoded(img),
+			Self { rendered: Some(img), .. } => ImageState::Rendered(img),
+			Self { .. } => ImageState::Path(PathBuf::new()),
+		}
+	}
+}
+
+fn encode_png(img: &DynamicImage) -> Option<Vec<u8>> {
+	let mut data = Vec::new();
+	img.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png).ok()?;
+	Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::temp_dir;
+	use image::RgbaImage;
+	use test_log::test;
+
+	/// An encoded 4x4 image, which takes 64 bytes once decoded or rendered.
+	fn encoded() -> ImageState {
+		let img = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+		ImageState::Encoded(encode_png(&img).unwrap())
+	}
+
+	fn stages(cache: &ImageCache) -> Vec<Stage> {
+		(0..cache.len()).map(|i| cache.stage(i).unwrap()).collect()
+	}
+
+	#[test]
+	fn promotes_up_the_chain() {
+		let dir = temp_dir("cache");
+		let path = dir.join("page.png");
+		let ImageState::Encoded(data) = encoded() else { unreachable!() };
+		std::fs::write(&path, &data).unwrap();
+
+		let mut cache = ImageCache::new(vec![ImageState::Path(path)], usize::MAX);
+		assert!(matches!(cache.get(0), Some(ImageRef::Path(_))));
+		assert_eq!(cache.memory_used(), 0);
+		assert!(matches!(cache.load(0, Stage::Decoded).unwrap(), ImageRef::Decoded(_)));
+		assert!(matches!(cache.load(0, Stage::Rendered).unwrap(), ImageRef::Rendered(_)));
+		assert_eq!(cache.memory_used(), data.len() + 64);
+		// Asking for a lower stage doesn't demote
+		assert!(matches!(cache.load(0, Stage::Encoded).unwrap(), ImageRef::Rendered(_)));
+
+		cache.set_budget(0);
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(stages(&cache), [Stage::Path]);
+	}
+
+	#[test]
+	fn farthest_from_viewport_goes_first() {
+		let mut cache = ImageCache::new((0..5).map(|_| encoded()).collect(), usize::MAX);
+		let encoded_size = cache.memory_used() / 5;
+		for i in 0..5 {
+			cache.load(i, Stage::Rendered).unwrap();
+		}
+		cache.set_viewport(Some(1..2));
+		cache.set_budget(cache.memory_used() - 64);
+		assert_eq!(stages(&cache), [Stage::Rendered, Stage::Rendered, Stage::Rendered, Stage::Rendered, Stage::Encoded]);
+
+		// 0 and 2 are equally far, so the least recently used goes after 3
+		cache.load(0, Stage::Rendered).unwrap();
+		cache.set_budget(cache.memory_used() - 128);
+		assert_eq!(stages(&cache), [Stage::Rendered, Stage::Rendered, Stage::Encoded, Stage::Encoded, Stage::Encoded]);
+
+		// Images in the viewport stay even if that goes over budget
+		cache.set_budget(0);
+		assert_eq!(stages(&cache), [Stage::Encoded, Stage::Rendered, Stage::Encoded, Stage::Encoded, Stage::Encoded]);
+		assert_eq!(cache.memory_used(), encoded_size * 5 + 64);
+	}
+
+	#[test]
+	fn loaded_image_is_kept() {
+		let mut cache = ImageCache::new(vec![encoded(), encoded()], 0);
+		cache.load(0, Stage::Decoded).unwrap();
+		assert!(matches!(cache.load(1, Stage::Decoded).unwrap(), ImageRef::Decoded(_)));
+		assert_eq!(stages(&cache), [Stage::Encoded, Stage::Decoded]);
+		assert!(matches!(cache.load(2, Stage::Decoded), Err(PieceError::NoSuchImage(2))));
+	}
+
+	#[test]
+	fn only_copy_is_never_dropped() {
+		let img = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+		let mut cache = ImageCache::new(vec![ImageState::Decoded(img.clone())], 0);
+		cache.set_viewport(Some(1..1));
+		assert_eq!(stages(&cache), [Stage::Encoded]);
+		let ImageRef::Encoded(data) = cache.get(0).unwrap() else { panic!("not encoded") };
+		assert_eq!(image::load_from_memory(data).unwrap(), img);
+
+		let Pages::Images(images) = cache.into_pages() else { panic!("pages aren't images") };
+		assert!(matches!(images[..], [ImageState::Encoded(_)]));
+	}
+}
@@ -0,0 +1,510 @@
+//! A catalog of every piece under a set of folders, so that pieces can be found by what they are
+//! rather than by file name. Setlists are listed along with them, so that they can be found the
+//! same way.
+//!
+//! Only the metadata of each piece is read, never its pages. The catalog is kept in an index file
+//! along with each file's size and modification time, so that [refreshing](Library::refresh) it
+//! only reads pieces that were added or changed since it was last saved.
+
+use crate::{
+	atomic, mrs, native,
+	setlist::{self, Setlist, SetlistError},
+	Information, Meta,
+};
+use log::{debug, info, warn};
+use mr_imp::lazy::LazyMRSFile;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	fmt::{Display, Formatter},
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+/// Version of the index file written by [`Library::save`]. Indices of any other version are
+/// discarded and rebuilt, since everything in them can be read again from the pieces.
+pub const INDEX_VERSION: u32 = 1;
+
+/// Every piece found under the library's root folders.
+#[derive(Debug, Clone)]
+pub struct Library {
+	/// Where the index is saved
+	index: PathBuf,
+	roots: Vec<PathBuf>,
+	entries: BTreeMap<PathBuf, Entry>,
+}
+
+/// What the catalog knows about one piece.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+	pub path: PathBuf,
+	pub kind: PieceKind,
+	/// Size of the file in bytes, and when it was last modified, as of when it was read.
+	pub size: u64,
+	pub modified: SystemTime,
+	pub information: Information,
+	/// Names of the piece's bookmarks, in order.
+	#[serde(default)]
+	pub bookmarks: Vec<String>,
+	/// How many pages the piece has. Setlists have none of their own.
+	pub pages: usize,
+	/// Labels given to the piece in the library, such as the concerts it is played at. They are
+	/// only kept in the index, so they are kept when the file changes but lost if it is moved.
+	#[serde(default)]
+	pub tags: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PieceKind {
+	/// A piece [saved](crate::Piece::save) by See Augmented
+	Native,
+	/// A MusicReader (`.mrs`) file
+	MusicReader,
+	/// A [setlist](crate::setlist), listed by its name and notes
+	Setlist,
+}
+
+impl PieceKind {
+	/// The kind of piece at `path`, going by its extension.
+	pub fn of(path: &Path) -> Option<Self> {
+		let extension = path.extension()?.to_string_lossy().to_lowercase();
+		match &*extension {
+			native::EXTENSION => Some(Self::Native),
+			"mrs" => Some(Self::MusicReader),
+			setlist::EXTENSION => Some(Self::Setlist),
+			_ => None,
+		}
+	}
+}
+
+/// The index file's contents.
+#[derive(Serialize, Deserialize)]
+struct Index {
+	version: u32,
+	roots: Vec<PathBuf>,
+	entries: Vec<Entry>,
+}
+
+/// Pieces that a [refresh](Library::refresh) found to be new, changed or gone.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Changes {
+	pub added: Vec<PathBuf>,
+	pub updated: Vec<PathBuf>,
+	pub removed: Vec<PathBuf>,
+}
+
+impl Changes {
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+	}
+}
+
+impl Library {
+	/// An empty library, to be saved at `index`.
+	pub fn new(index: impl Into<PathBuf>) -> Self {
+		Self { index: index.into(), roots: Vec::new(), entries: BTreeMap::new() }
+	}
+
+	/// Open the library saved at `index`, or start an empty one if there is nothing there yet.
+	/// Call [`refresh`](Self::refresh) to bring it up to date with the files.
+	pub async fn open(index: impl Into<PathBuf>) -> Result<Self, CatalogError> {
+		let mut library = Self::new(index);
+		let text = match tokio::fs::read_to_string(&library.index).await {
+			Ok(text) => text,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(library),
+			Err(e) => return Err(e.into()),
+		};
+		let Index { version, roots, entries } = ron::from_str(&text)?;
+		library.roots = roots;
+		if version == INDEX_VERSION {
+			library.entries = entries.into_iter().map(|entry| (entry.path.clone(), entry)).collect();
+		} else {
+			info!("rebuilding library index `{}` from version {version}", library.index.display());
+		}
+		Ok(library)
+	}
+
+	/// Write the index, replacing the old one only once the new one is complete.
+	pub async fn save(&self) -> Result<(), CatalogError> {
+		let index = Index {
+			version: INDEX_VERSION,
+			roots: self.roots.clone(),
+			entries: self.entries.values().cloned().collect(),
+		};
+		let text = ron::ser::to_string_pretty(&index, ron::ser::PrettyConfig::default())?;
+		if let Some(parent) = self.index.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+			tokio::fs::create_dir_all(parent).await?;
+		}
+		atomic::write(&self.index, text.as_bytes()).await?;
+		Ok(())
+	}
+
+	/// Folders that are searched for pieces, including their subfolders.
+	pub fn roots(&self) -> &[PathBuf] {
+		&self.roots
+	}
+
+	/// Search `root` for pieces too. Its pieces are found by the next [refresh](Self::refresh).
+	pub fn add_root(&mut self, root: impl Into<PathBuf>) {
+		let root = root.into();
+		if !self.roots.contains(&root) {
+			self.roots.push(root);
+		}
+	}
+
+	/// Stop searching `root`, forgetting every piece under it that isn't under another root.
+	pub fn remove_root(&mut self, root: &Path) {
+		self.roots.retain(|r| r != root);
+		let roots = &self.roots;
+		self.entries.retain(|path, _| roots.iter().any(|root| path.starts_with(root)));
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	/// Every piece, in order of path.
+	pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+		self.entries.values()
+	}
+
+	pub fn get(&self, path: &Path) -> Option<&Entry> {
+		self.entries.get(path)
+	}
+
+	/// Replace the [tags](Entry::tags) of the piece at `path`, returning whether it is in the
+	/// library.
+	pub fn set_tags(&mut self, path: &Path, tags: Vec<String>) -> bool {
+		let Some(entry) = self.entries.get_mut(path) else { return false };
+		entry.tags = tags;
+		true
+	}
+
+	/// Search the roots for pieces, reading those that are new or whose size or modification
+	/// time changed, and forgetting those that are gone. Pieces that can't be read are logged and
+	/// tried again on the next refresh; until then they keep what was last read of them, if
+	/// anything. Pieces in folders that can't be searched, such as on a drive that isn't
+	/// connected, are kept as they are too.
+	pub async fn refresh(&mut self) -> Changes {
+		let mut found = Vec::new();
+		let mut unsearched = Vec::new();
+		for root in &self.roots {
+			if let Err(e) = find_pieces(root, &mut found, &mut unsearched).await {
+				warn!("couldn't search `{}` for pieces: {e}", root.display());
+				unsearched.push(root.clone());
+			}
+		}
+		found.sort();
+		found.dedup();
+
+		let mut changes = Changes::default();
+		let mut entries = BTreeMap::new();
+		for path in found {
+			let existing = self.entries.remove(&path);
+			let last_read = existing.clone();
+			match read_if_changed(&path, existing).await {
+				Ok(Some((entry, change))) => {
+					match change {
+						Change::Added => changes.added.push(path.clone()),
+						Change::Updated => changes.updated.push(path.clone()),
+						Change::Unchanged => {}
+					}
+					entries.insert(path, entry);
+				}
+				Ok(None) => {}
+				Err(e) => {
+					warn!("couldn't read `{}` for the library: {e}", path.display());
+					entries.extend(last_read.map(|entry| (path, entry)));
+				}
+			}
+		}
+		for (path, entry) in std::mem::replace(&mut self.entries, entries) {
+			if unsearched.iter().any(|dir| path.starts_with(dir)) {
+				self.entries.insert(path, entry);
+			} else {
+				changes.removed.push(path);
+			}
+		}
+		debug!(
+			"library refreshed: {} added, {} updated, {} removed",
+			changes.added.len(),
+			changes.updated.len(),
+			changes.removed.len()
+		);
+		changes
+	}
+
+	/// Bring one piece up to date, such as after it was saved or a file watcher reported that it
+	/// changed. Pieces outside the roots are ignored. Returns whether the catalog changed. If the
+	/// piece can't be read, what was last read of it is kept.
+	pub async fn update(&mut self, path: &Path) -> Result<bool, CatalogError> {
+		if !self.roots.iter().any(|root| path.starts_with(root)) || PieceKind::of(path).is_none() {
+			return Ok(false);
+		}
+		match read_if_changed(path, self.entries.get(path).cloned()).await? {
+			Some((entry, change)) => {
+				self.entries.insert(path.to_owned(), entry);
+				Ok(change != Change::Unchanged)
+			}
+			None => Ok(self.entries.remove(path).is_some()),
+		}
+	}
+}
+
+/// Read `path` unless `existing` is still up to date with it. `None` means it is gone.
+async fn read_if_changed(
+	path: &Path,
+	existing: Option<Entry>,
+) -> Result<Option<(Entry, Change)>, CatalogError> {
+	let metadata = match tokio::fs::metadata(path).await {
+		Ok(metadata) if metadata.is_file() => metadata,
+		Ok(_) => return Ok(None),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+		Err(e) => return Err(e.into()),
+	};
+	let size = metadata.len();
+	let modified = metadata.modified()?;
+	let (change, tags) = match existing {
+		Some(entry) if entry.size == size && entry.modified == modified => {
+			return Ok(Some((entry, Change::Unchanged)));
+		}
+		Some(entry) => (Change::Updated, entry.tags),
+		None => (Change::Added, Vec::new()),
+	};
+	let Some(kind) = PieceKind::of(path) else { return Ok(None) };
+	let (information, bookmarks, pages) = match kind {
+		PieceKind::Native => {
+			let meta = Meta::load(path).await.map_err(CatalogError::Piece)?;
+			let pages = meta.page_count(path).await.map_err(CatalogError::Piece)?;
+			(meta.information, meta.bookmarks.into_iter().map(|b| b.name).collect(), pages)
+		}
+		PieceKind::MusicReader => {
+			let file = LazyMRSFile::load(path).await?;
+			let information = match &file.info {
+				Ok(info) => mrs::information(info.information.clone()),
+				Err(_) => Information {
+					title: path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+					..Information::default()
+				},
+			};
+			let bookmarks = match &file.bookmarks {
+				Ok(bookmarks) => bookmarks.as_slice().iter().map(|b| b.name.clone()).collect(),
+				Err(_) => Vec::new(),
+			};
+			(information, bookmarks, file.page_count())
+		}
+		PieceKind::Setlist => {
+			let Setlist { name, notes, .. } = Setlist::load(path).await?;
+			let information = Information { title: name, description: notes, ..Information::default() };
+			(information, Vec::new(), 0)
+		}
+	};
+	let entry = Entry { path: path.to_owned(), kind, size, modified, information, bookmarks, pages, tags };
+	Ok(Some((entry, change)))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Change {
+	Added,
+	Updated,
+	Unchanged,
+}
+
+/// Add every piece under `dir` to `found`, skipping hidden files and folders, and every folder
+/// that couldn't be searched to `unsearched`. Symbolic links to folders aren't followed, so that
+/// links back up the tree can't loop.
+async fn find_pieces(dir: &Path, found: &mut Vec<PathBuf>, unsearched: &mut Vec<PathBuf>) -> std::io::Result<()> {
+	let mut dirs = vec![dir.to_owned()];
+	while let Some(dir) = dirs.pop() {
+		let mut entries = match tokio::fs::read_dir(&dir).await {
+			Ok(entries) => entries,
+			Err(e) => {
+				warn!("couldn't search `{}` for pieces: {e}", dir.display());
+				unsearched.push(dir);
+				continue;
+			}
+		};
+		while let Some(entry) = entries.next_entry().await? {
+			if entry.file_name().to_string_lossy().starts_with('.') {
+				continue;
+			}
+			let path = entry.path();
+			let file_type = entry.file_type().await?;
+			if file_type.is_dir() {
+				dirs.push(path);
+			} else if PieceKind::of(&path).is_some() {
+				found.push(path);
+			}
+		}
+	}
+	Ok(())
+}
+
+#[derive(Debug)]
+pub enum CatalogError {
+	Io(std::io::Error),
+	Ron(ron::Error),
+	/// The index isn't valid RON, or doesn't hold what an index should.
+	Index(ron::error::SpannedError),
+	/// A piece's manifest couldn't be read.
+	Piece(crate::PieceError),
+	/// A setlist couldn't be read.
+	Setlist(SetlistError),
+}
+
+impl Display for CatalogError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => e.fmt(f),
+			Self::Ron(e) => e.fmt(f),
+			Self::Index(e) => e.fmt(f),
+			Self::Piece(e) => e.fmt(f),
+			Self::Setlist(e) => e.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for CatalogError {}
+
+impl From<std::io::Error> for CatalogError {
+	fn from(e: std::io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl From<ron::Error> for CatalogError {
+	fn from(e: ron::Error) -> Self {
+		Self::Ron(e)
+	}
+}
+
+impl From<ron::error::SpannedError> for CatalogError {
+	fn from(e: ron::error::SpannedError) -> Self {
+		Self::Index(e)
+	}
+}
+
+impl From<SetlistError> for CatalogError {
+	fn from(e: SetlistError) -> Self {
+		Self::Setlist(e)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{test_util::temp_dir, AnnotationFormat, ImageState, PageFormat, Pages, Piece};
+	use image::{DynamicImage, RgbaImage};
+	use test_log::test;
+
+	fn piece(title: &str, creator: &str) -> Piece {
+		Piece {
+			meta: Meta {
+				format: PageFormat::Images { files: vec!["page-1.png".into()] },
+				annotations: AnnotationFormat::Image,
+				layers: Vec::new(),
+				information: Information {
+					title: title.into(),
+					creators: vec![creator.into()],
+					..Information::default()
+				},
+				reading_order: Vec::new(),
+				measures: Vec::new(),
+				bookmarks: vec![crate::Bookmark { name: "Trio".into(), page: 0, x: 0, y: 0, kind: None }],
+				musicreader: None,
+			},
+			pages: Pages::Images(vec![ImageState::Decoded(DynamicImage::ImageRgba8(RgbaImage::new(2, 2)))]),
+			annotations: Vec::new(),
+		}
+	}
+
+	#[test(tokio::test)]
+	async fn refresh_and_reopen() {
+		let dir = temp_dir("catalog");
+		let root = dir.join("parts");
+		std::fs::create_dir_all(root.join("horn/.trash")).unwrap();
+		let march = root.join("horn/march.saug");
+		let waltz = root.join("waltz.saug");
+		piece("March", "Sousa").save(&march).await.unwrap();
+		piece("Waltz", "Strauss").save(&waltz).await.unwrap();
+		piece("Deleted", "Nobody").save(root.join("horn/.trash/old.saug")).await.unwrap();
+		std::fs::write(root.join("notes.txt"), "not a piece").unwrap();
+
+		let index = dir.join("index.ron");
+		let mut library = Library::new(&index);
+		library.add_root(&root);
+		let changes = library.refresh().await;
+		assert_eq!(changes.added, [march.clone(), waltz.clone()]);
+		let entry = library.get(&march).unwrap();
+		assert_eq!(entry.kind, PieceKind::Native);
+		assert_eq!(entry.information.creators, ["Sousa"]);
+		assert_eq!(entry.bookmarks, ["Trio"]);
+		assert_eq!(entry.pages, 1);
+		library.save().await.unwrap();
+
+		let mut library = Library::open(&index).await.unwrap();
+		assert_eq!(library.roots(), std::slice::from_ref(&root));
+		assert_eq!(library.len(), 2);
+		assert!(library.refresh().await.is_empty());
+
+		assert!(library.set_tags(&waltz, vec!["Spring concert".into()]));
+		piece("Waltz of the Flowers", "Tchaikovsky").save(&waltz).await.unwrap();
+		std::fs::remove_file(&march).unwrap();
+		let changes = library.refresh().await;
+		assert_eq!(changes, Changes { added: vec![], updated: vec![waltz.clone()], removed: vec![march] });
+		assert_eq!(library.get(&waltz).unwrap().information.title, "Waltz of the Flowers");
+		assert_eq!(library.get(&waltz).unwrap().tags, ["Spring concert"]);
+
+		library.remove_root(&root);
+		assert!(library.is_empty());
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test(tokio::test)]
+	async fn setlists() {
+		let dir = temp_dir("catalog-setlists");
+		let concert = dir.join(format!("spring.{}", setlist::EXTENSION));
+		let setlist = Setlist { notes: Some("Bring the mute".into()), ..Setlist::new("Spring concert") };
+		setlist.save(&concert).await.unwrap();
+		let mut library = Library::new(dir.join("index.ron"));
+		library.add_root(&dir);
+		assert_eq!(library.refresh().await.added, std::slice::from_ref(&concert));
+
+		let entry = library.get(&concert).unwrap();
+		assert_eq!(entry.kind, PieceKind::Setlist);
+		assert_eq!(entry.information.title, "Spring concert");
+		assert_eq!(entry.information.description.as_deref(), Some("Bring the mute"));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test(tokio::test)]
+	async fn unreadable_pieces_are_kept() {
+		let dir = temp_dir("catalog-unreadable");
+		let (root, away) = (dir.join("parts"), dir.join("away"));
+		std::fs::create_dir_all(&root).unwrap();
+		let march = root.join("march.saug");
+		piece("March", "Sousa").save(&march).await.unwrap();
+		let mut library = Library::new(dir.join("index.ron"));
+		library.add_root(&root);
+		library.refresh().await;
+
+		std::fs::write(&march, "damaged").unwrap();
+		assert!(library.refresh().await.is_empty());
+		assert!(library.update(&march).await.is_err());
+		assert_eq!(library.get(&march).unwrap().information.title, "March");
+
+		// As if the drive the folder is on was disconnected
+		std::fs::rename(&root, &away).unwrap();
+		assert!(library.refresh().await.is_empty());
+		assert_eq!(library.len(), 1);
+
+		std::fs::write(dir.join("index.ron"), "(version: 1,\n\troots: 3)").unwrap();
+		let err = Library::open(dir.join("index.ron")).await.unwrap_err();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert!(matches!(&err, CatalogError::Index(e) if e.position.line == 2), "{err}");
+	}
+}
@@ -1,8 +1,28 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Cursor;
 use std::path::{Path, PathBuf};
-use image::DynamicImage;
+use std::sync::Arc;
+use image::{DynamicImage, ImageOutputFormat, RgbaImage};
 use serde::{Deserialize, Serialize};
+use skia_safe::{AlphaType, ColorType, ImageInfo};
+
+mod atomic;
+pub mod cache;
+pub mod catalog;
+mod manifest;
+pub use manifest::CURRENT_VERSION;
+pub mod mrs;
+pub mod native;
+pub mod pdf;
+pub mod scans;
+pub mod search;
+pub mod setlist;
+pub mod strokes;
+pub mod svg;
+#[cfg(test)]
+mod test_util;
 
 pub type SkiaImage = skia_safe::Image;
 
@@ -10,24 +30,162 @@ pub type SkiaImage = skia_safe::Image;
 pub struct Piece {
 	pub meta: Meta,
 	pub pages: Pages,
+	/// Files of each of the [annotation layers](Meta::layers), in the same order, by page index.
+	/// Pages without annotations on a layer have none. Layers in formats other than
+	/// [`AnnotationFormat::Image`] hold the raw contents of their files as [`ImageState::Encoded`].
+	pub annotations: Vec<Vec<Option<ImageState>>>,
 }
 
 #[derive(Debug)]
 pub enum Pages {
 	Images(Vec<ImageState>),
+	Pdf(Arc<pdf::PdfPages>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Pages {
+	pub fn len(&self) -> usize {
+		match self {
+			Self::Images(images) => images.len(),
+			Self::Pdf(pdf) => pdf.len(),
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Meta {
 	pub format: PageFormat,
+	/// Format of annotations made in See Augmented.
 	pub annotations: AnnotationFormat,
+	/// Annotations that were made elsewhere, drawn in order on top of the pages.
+	#[serde(default)]
+	pub layers: Vec<AnnotationLayer>,
+	#[serde(default)]
+	pub information: Information,
+	/// The order pages are shown in while playing. Empty means every page in order, turned whole.
+	#[serde(default)]
+	pub reading_order: Vec<ShownPage>,
+	#[serde(default)]
+	pub measures: Vec<Measure>,
+	#[serde(default)]
+	pub bookmarks: Vec<Bookmark>,
+	/// What the MusicReader file the piece was [imported](mrs::import) from held that See Augmented
+	/// doesn't use.
+	#[serde(default)]
+	pub musicreader: Option<MusicReaderExtras>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Meta {
+	/// Every annotation file, along with its format.
+	pub fn annotation_files(&self) -> Annotations<'_> {
+		let files = self
+			.layers
+			.iter()
+			.flat_map(|layer| layer.files.iter().flatten().map(|file| (Cow::Borrowed(file.as_path()), layer.format)))
+			.collect();
+		Annotations { files }
+	}
+}
+
+/// Descriptive metadata about a piece.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Information {
+	pub title: String,
+	/// Composers, arrangers, lyricists and so on, with their role in brackets if it is known,
+	/// e.g. `Bach, Johann Sebastian [composer]`.
+	#[serde(default)]
+	pub creators: Vec<String>,
+	#[serde(default)]
+	pub description: Option<String>,
+	#[serde(default)]
+	pub subjects: Vec<String>,
+	#[serde(default)]
+	pub publisher: Option<String>,
+	#[serde(default)]
+	pub copyright: Option<String>,
+	/// Identifier of the piece in the program it was imported from.
+	#[serde(default)]
+	pub source_identifier: Option<String>,
+}
+
+/// Parts of a MusicReader file that See Augmented doesn't use, kept as they were read so that
+/// [exporting](mrs::export) the piece again gives them back.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MusicReaderExtras {
+	/// The `<recordings>` section of `info.xml`.
+	#[serde(default)]
+	pub recordings: Option<String>,
+	/// The `<parts>` section of `info.xml`.
+	#[serde(default)]
+	pub parts: Option<String>,
+}
+
+/// A set of annotations made outside See Augmented, such as a MusicReader annotation layer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnnotationLayer {
+	pub name: String,
+	pub format: AnnotationFormat,
+	/// The layer's file for each page, by page index.
+	pub files: Vec<Option<PathBuf>>,
+}
+
+/// One step of turning through a piece.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShownPage {
+	/// Index of the page
+	pub page: usize,
+	/// How to turn away from this page to the next one.
+	pub turn: PageTurn,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PageTurn {
+	Whole,
+	/// Replace the part of the page above `split` (a fraction of its height) with the next page
+	/// first, then the rest.
+	Half { split: f32 },
+}
+
+/// The bounding box of a measure. Coordinates are in page image pixels.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Measure {
+	pub movement: usize,
+	pub number: usize,
+	/// How many measures the box covers, which is more than one for multi-measure rests.
+	pub count: usize,
+	/// Index of the page
+	pub page: usize,
+	pub left: u32,
+	pub top: u32,
+	pub right: u32,
+	pub bottom: u32,
+}
+
+/// A named point in a piece. Coordinates are in page image pixels.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+	pub name: String,
+	/// Index of the page
+	pub page: usize,
+	pub x: u32,
+	pub y: u32,
+	/// The kind of bookmark in the program it was imported from, such as MusicReader's `type`.
+	#[serde(default)]
+	pub kind: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PageFormat {
 	Images {
 		files: Vec<PathBuf>,
 	},
+	/// Every page of a PDF, which is [rasterized](pdf::PdfPages::render) as it is shown.
+	Pdf {
+		file: PathBuf,
+	},
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,10 +193,12 @@ pub struct Annotations<'meta> {
 	pub files: HashMap<Cow<'meta, Path>, AnnotationFormat>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AnnotationFormat {
 	Image,
+	/// Strokes [exported as SVG](svg::export).
 	Svg,
+	/// [Strokes](strokes::Strokes) in their binary format.
 	Strokes,
 }
 
@@ -50,6 +210,126 @@ pub enum ImageState {
 	Rendered(SkiaImage),
 }
 
+impl ImageState {
+	/// The encoded contents of this image, encoding it as PNG if it is only held decoded. Images
+	/// stored at a path are read synchronously.
+	pub fn to_encoded(&self) -> Result<Cow<'_, [u8]>, PieceError> {
+		let png = |img: &DynamicImage| {
+			let mut data = Vec::new();
+			img.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+				.map_err(PieceError::Image)?;
+			Ok(Cow::Owned(data))
+		};
+		match self {
+			Self::Path(path) => Ok(Cow::Owned(std::fs::read(path)?)),
+			Self::Encoded(data) => Ok(Cow::Borrowed(data)),
+			Self::Decoded(img) => png(img),
+			Self::Rendered(img) => png(&rendered_to_dynamic(img).ok_or(PieceError::Unreadable)?),
+		}
+	}
+}
+
+/// Copy the pixels of a Skia image back into an [`image`] buffer.
+pub(crate) fn rendered_to_dynamic(img: &SkiaImage) -> Option<DynamicImage> {
+	let (w, h) = (img.width(), img.height());
+	let info = ImageInfo::new((w, h), ColorType::RGBA8888, AlphaType::Unpremul, None);
+	let mut pixels = vec![0u8; w as usize * h as usize * 4];
+	if !img.read_pixels(&info, &mut pixels, w as usize * 4, (0, 0), skia_safe::image::CachingHint::Disallow) {
+		return None;
+	}
+	RgbaImage::from_raw(w as u32, h as u32, pixels).map(DynamicImage::ImageRgba8)
+}
+
+/// Copy decoded pixels into a Skia raster image so they can be drawn without decoding again.
+pub fn dynamic_to_rendered(img: &DynamicImage) -> Option<SkiaImage> {
+	let img = img.to_rgba8();
+	let (w, h) = img.dimensions();
+	let info = ImageInfo::new((w as i32, h as i32), ColorType::RGBA8888, AlphaType::Unpremul, None);
+	skia_safe::images::raster_from_data(&info, skia_safe::Data::new_copy(&img), w as usize * 4)
+}
+
+#[derive(Debug)]
+pub enum PieceError {
+	Io(std::io::Error),
+	Zip(async_zip::error::ZipError),
+	Ron(ron::Error),
+	/// The manifest isn't valid RON, or doesn't hold what a manifest should.
+	Manifest(ron::error::SpannedError),
+	Image(image::ImageError),
+	/// A file listed in the manifest is missing from the container, or has no data to be saved.
+	Missing(PathBuf),
+	/// The pixels of a rendered image couldn't be read back.
+	Unreadable,
+	/// A decoded image couldn't be copied into a Skia image.
+	Unrenderable,
+	/// There is no image with this index.
+	NoSuchImage(usize),
+	/// The pages are held in a different form than the manifest says they are stored in.
+	FormatMismatch,
+	Pdf(pdfium_render::prelude::PdfiumError),
+	/// Pdfium, which renders PDFs, couldn't be loaded.
+	NoPdfium(String),
+	/// The piece was saved by a newer version of See Augmented, in a format this version doesn't
+	/// understand.
+	TooNew { version: u32 },
+}
+
+impl Display for PieceError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => e.fmt(f),
+			Self::Zip(e) => e.fmt(f),
+			Self::Ron(e) => e.fmt(f),
+			Self::Manifest(e) => write!(f, "invalid manifest: {e}"),
+			Self::Image(e) => e.fmt(f),
+			Self::Missing(path) => write!(f, "`{}` is missing", path.display()),
+			Self::Unreadable => f.write_str("couldn't read the pixels of a rendered image"),
+			Self::Unrenderable => f.write_str("couldn't copy a decoded image into a Skia image"),
+			Self::NoSuchImage(i) => write!(f, "there is no image {i}"),
+			Self::FormatMismatch => f.write_str("the pages don't match the page format"),
+			Self::Pdf(e) => e.fmt(f),
+			Self::NoPdfium(e) => write!(f, "couldn't load Pdfium to open PDFs: {e}"),
+			Self::TooNew { version } => write!(
+				f,
+				"saved by a newer version of See Augmented (format version {version}, but only versions up to \
+				 {CURRENT_VERSION} are supported); please update to open it"
+			),
+		}
+	}
+}
+
+impl std::error::Error for PieceError {}
+
+impl From<std::io::Error> for PieceError {
+	fn from(e: std::io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl From<async_zip::error::ZipError> for PieceError {
+	fn from(e: async_zip::error::ZipError) -> Self {
+		Self::Zip(e)
+	}
+}
+
+impl From<pdfium_render::prelude::PdfiumError> for PieceError {
+	fn from(e: pdfium_render::prelude::PdfiumError) -> Self {
+		Self::Pdf(e)
+	}
+}
+
+impl From<ron::Error> for PieceError {
+	fn from(e: ron::Error) -> Self {
+		Self::Ron(e)
+	}
+}
+
+impl From<ron::error::SpannedError> for PieceError {
+	fn from(e: ron::error::SpannedError) -> Self {
+		Self::Manifest(e)
+	}
+}
+
 pub enum ImageRef<'a> {
 	Path(&'a Path),
 	Encoded(&'a [u8]),
@@ -0,0 +1,358 @@
+//! Versioning of the `meta.ron` manifest.
+//!
+//! Every manifest records the version of the format it was written in. Older manifests are
+//! upgraded one version at a time until they reach [`CURRENT_VERSION`], so each change to the
+//! format only needs a migration from the version just before it. Manifests from newer versions
+//! are refused rather than guessed at.
+//!
+//! To change the format: copy the current definitions into a new `vN` module below, bump
+//! [`CURRENT_VERSION`], and add a step to [`migrate`] that converts `vN` to the next version.
+
+use crate::{Meta, PieceError};
+use serde::{Deserialize, Serialize};
+
+/// The manifest format version written by this version of See Augmented.
+pub const CURRENT_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest<M> {
+	version: u32,
+	meta: M,
+}
+
+/// Just enough of a manifest to tell which version it is. Version 0 had no version number.
+#[derive(Debug, Deserialize)]
+struct VersionOnly {
+	#[serde(default)]
+	version: u32,
+}
+
+/// Manifest formats that are no longer written, exactly as they were, so that changes to the
+/// current definitions can't change how old manifests are read.
+mod v0 {
+	use serde::Deserialize;
+	use std::path::PathBuf;
+
+	/// Version 0 was a bare `Meta` with no version number.
+	#[derive(Debug, Deserialize)]
+	pub struct Meta {
+		pub format: PageFormat,
+		pub annotations: AnnotationFormat,
+		#[serde(default)]
+		pub layers: Vec<AnnotationLayer>,
+		#[serde(default)]
+		pub information: Information,
+		#[serde(default)]
+		pub reading_order: Vec<ShownPage>,
+		#[serde(default)]
+		pub measures: Vec<Measure>,
+		#[serde(default)]
+		pub bookmarks: Vec<Bookmark>,
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub enum PageFormat {
+		Images { files: Vec<PathBuf> },
+	}
+
+	#[derive(Debug, Copy, Clone, Deserialize)]
+	pub enum AnnotationFormat {
+		Image,
+		Svg,
+		Strokes,
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub struct AnnotationLayer {
+		pub name: String,
+		pub format: AnnotationFormat,
+		pub files: Vec<Option<PathBuf>>,
+	}
+
+	#[derive(Debug, Default, Deserialize)]
+	pub struct Information {
+		pub title: String,
+		#[serde(default)]
+		pub creators: Vec<String>,
+		#[serde(default)]
+		pub description: Option<String>,
+		#[serde(default)]
+		pub subjects: Vec<String>,
+		#[serde(default)]
+		pub publisher: Option<String>,
+		#[serde(default)]
+		pub copyright: Option<String>,
+		#[serde(default)]
+		pub source_identifier: Option<String>,
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub struct ShownPage {
+		pub page: usize,
+		pub turn: PageTurn,
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub enum PageTurn {
+		Whole,
+		Half { split: f32 },
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub struct Measure {
+		pub movement: usize,
+		pub number: usize,
+		pub count: usize,
+		pub page: usize,
+		pub left: u32,
+		pub top: u32,
+		pub right: u32,
+		pub bottom: u32,
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub struct Bookmark {
+		pub name: String,
+		pub page: usize,
+		pub x: u32,
+		pub y: u32,
+	}
+}
+
+/// Version 1 added the version number, what MusicReader files held that isn't used, and the kind
+/// of each bookmark. Everything else is as it was in version 0.
+mod v1 {
+	use super::v0::{AnnotationFormat, AnnotationLayer, Information, Measure, PageFormat, ShownPage};
+	use serde::Deserialize;
+
+	#[derive(Debug, Deserialize)]
+	pub struct Meta {
+		pub format: PageFormat,
+		pub annotations: AnnotationFormat,
+		#[serde(default)]
+		pub layers: Vec<AnnotationLayer>,
+		#[serde(default)]
+		pub information: Information,
+		#[serde(default)]
+		pub reading_order: Vec<ShownPage>,
+		#[serde(default)]
+		pub measures: Vec<Measure>,
+		#[serde(default)]
+		pub bookmarks: Vec<Bookmark>,
+		#[serde(default)]
+		pub musicreader: Option<MusicReaderExtras>,
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub struct MusicReaderExtras {
+		#[serde(default)]
+		pub recordings: Option<String>,
+		#[serde(default)]
+		pub parts: Option<String>,
+	}
+
+	#[derive(Debug, Deserialize)]
+	pub struct Bookmark {
+		pub name: String,
+		pub page: usize,
+		pub x: u32,
+		pub y: u32,
+		#[serde(default)]
+		pub kind: Option<String>,
+	}
+}
+
+/// Parse a manifest written by this or any earlier version.
+pub(crate) fn read(text: &str) -> Result<Meta, PieceError> {
+	let VersionOnly { version } = ron::from_str(text)?;
+	migrate(version, text)
+}
+
+/// Serialize `meta` as a manifest of the [current version](CURRENT_VERSION).
+pub(crate) fn write(meta: &Meta) -> Result<String, PieceError> {
+	let manifest = Manifest { version: CURRENT_VERSION, meta };
+	Ok(ron::ser::to_string_pretty(&manifest, ron::ser::PrettyConfig::default())?)
+}
+
+/// Parse `text` as a manifest of `version` and upgrade it to the current version.
+fn migrate(version: u32, text: &str) -> Result<Meta, PieceError> {
+	let v1 = match version {
+		0 => v0_to_v1(ron::from_str::<v0::Meta>(text)?),
+		1 => ron::from_str::<Manifest<v1::Meta>>(text)?.meta,
+		2 => return Ok(ron::from_str::<Manifest<Meta>>(text)?.meta),
+		newer => return Err(PieceError::TooNew { version: newer }),
+	};
+	Ok(v1_to_v2(v1))
+}
+
+fn v0_to_v1(meta: v0::Meta) -> v1::Meta {
+	let v0::Meta { format, annotations, layers, information, reading_order, measures, bookmarks } = meta;
+	v1::Meta {
+		format,
+		annotations,
+		layers,
+		information,
+		reading_order,
+		measures,
+		bookmarks: bookmarks
+			.into_iter()
+			.map(|v0::Bookmark { name, page, x, y }| v1::Bookmark { name, page, x, y, kind: None })
+			.collect(),
+		musicreader: None,
+	}
+}
+
+/// Version 2 added pages from a PDF.
+fn v1_to_v2(meta: v1::Meta) -> Meta {
+	use crate::{
+		AnnotationFormat, AnnotationLayer, Bookmark, Information, Measure, MusicReaderExtras, PageFormat, PageTurn,
+		ShownPage,
+	};
+	let v1::Meta { format, annotations, layers, information, reading_order, measures, bookmarks, musicreader } = meta;
+	let annotation_format = |format| match format {
+		v0::AnnotationFormat::Image => AnnotationFormat::Image,
+		v0::AnnotationFormat::Svg => AnnotationFormat::Svg,
+		v0::AnnotationFormat::Strokes => AnnotationFormat::Strokes,
+	};
+	let v0::Information { title, creators, description, subjects, publisher, copyright, source_identifier } =
+		information;
+	Meta {
+		format: match format {
+			v0::PageFormat::Images { files } => PageFormat::Images { files },
+		},
+		annotations: annotation_format(annotations),
+		layers: layers
+			.into_iter()
+			.map(|v0::AnnotationLayer { name, format, files }| AnnotationLayer {
+				name,
+				format: annotation_format(format),
+				files,
+			})
+			.collect(),
+		information: Information { title, creators, description, subjects, publisher, copyright, source_identifier },
+		reading_order: reading_order
+			.into_iter()
+			.map(|v0::ShownPage { page, turn }| ShownPage {
+				page,
+				turn: match turn {
+					v0::PageTurn::Whole => PageTurn::Whole,
+					v0::PageTurn::Half { split } => PageTurn::Half { split },
+				},
+			})
+			.collect(),
+		measures: measures
+			.into_iter()
+			.map(|v0::Measure { movement, number, count, page, left, top, right, bottom }| Measure {
+				movement,
+				number,
+				count,
+				page,
+				left,
+				top,
+				right,
+				bottom,
+			})
+			.collect(),
+		bookmarks: bookmarks
+			.into_iter()
+			.map(|v1::Bookmark { name, page, x, y, kind }| Bookmark { name, page, x, y, kind })
+			.collect(),
+		musicreader: musicreader
+			.map(|v1::MusicReaderExtras { recordings, parts }| MusicReaderExtras { recordings, parts }),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{AnnotationFormat, PageFormat};
+
+	fn check(meta: &Meta) {
+		assert_eq!(meta.format, PageFormat::Images { files: vec!["page-1.png".into()] });
+		assert_eq!(meta.annotations, AnnotationFormat::Image);
+		assert_eq!(meta.information.title, "Old");
+	}
+
+	#[test]
+	fn version_0() {
+		let text = r#"(
+			format: Images(files: ["page-1.png"]),
+			annotations: Image,
+			information: (title: "Old"),
+		)"#;
+		check(&read(text).unwrap());
+
+		let text = r#"(
+			format: Images(files: ["page-1.png"]),
+			annotations: Image,
+			layers: [(name: "Pencil", format: Strokes, files: [Some("pencil-1.bin")])],
+			information: (title: "Old", creators: ["Someone [composer]"]),
+			reading_order: [(page: 0, turn: Half(split: 0.5))],
+			measures: [(movement: 1, number: 1, count: 1, page: 0, left: 1, top: 2, right: 3, bottom: 4)],
+			bookmarks: [(name: "Trio", page: 0, x: 5, y: 6)],
+		)"#;
+		let meta = read(text).unwrap();
+		check(&meta);
+		assert_eq!(meta.layers[0].format, AnnotationFormat::Strokes);
+		assert_eq!(meta.layers[0].files, [Some("pencil-1.bin".into())]);
+		assert_eq!(meta.information.creators, ["Someone [composer]"]);
+		assert_eq!(meta.reading_order, [crate::ShownPage { page: 0, turn: crate::PageTurn::Half { split: 0.5 } }]);
+		assert_eq!((meta.measures[0].left, meta.measures[0].bottom), (1, 4));
+		assert_eq!(meta.bookmarks, [crate::Bookmark { name: "Trio".into(), page: 0, x: 5, y: 6, kind: None }]);
+	}
+
+	#[test]
+	fn version_1() {
+		let text = r#"(
+			version: 1,
+			meta: (
+				format: Images(files: ["page-1.png"]),
+				annotations: Image,
+				layers: [],
+				information: (title: "Old", creators: [], subjects: []),
+				reading_order: [],
+				measures: [],
+				bookmarks: [(name: "Coda", page: 0, x: 1, y: 2, kind: Some("0"))],
+				musicreader: Some((parts: Some("<parts/>"))),
+			),
+		)"#;
+		let meta = read(text).unwrap();
+		check(&meta);
+		assert_eq!(meta.bookmarks[0].kind.as_deref(), Some("0"));
+		assert_eq!(meta.musicreader.unwrap().parts.as_deref(), Some("<parts/>"));
+
+		// PDF pages are only in version 2.
+		let text = r#"(version: 1, meta: (format: Pdf(file: "score.pdf"), annotations: Image))"#;
+		assert!(matches!(read(text), Err(PieceError::Manifest(_))));
+	}
+
+	#[test]
+	fn version_2() {
+		let text = r#"(version: 2, meta: (format: Pdf(file: "score.pdf"), annotations: Strokes))"#;
+		assert_eq!(read(text).unwrap().format, PageFormat::Pdf { file: "score.pdf".into() });
+	}
+
+	#[test]
+	fn current_version_round_trip() {
+		let meta = read(r#"(format: Images(files: ["page-1.png"]), annotations: Image, information: (title: "Old"))"#)
+			.unwrap();
+		let text = write(&meta).unwrap();
+		assert!(matches!(ron::from_str(&text).unwrap(), VersionOnly { version: CURRENT_VERSION }));
+		assert_eq!(read(&text).unwrap(), meta);
+	}
+
+	#[test]
+	fn newer_versions_are_refused() {
+		let text = format!("(version: {}, meta: (future: true))", CURRENT_VERSION + 1);
+		let err = read(&text).unwrap_err();
+		assert!(matches!(err, PieceError::TooNew { version } if version == CURRENT_VERSION + 1));
+		assert!(err.to_string().contains("newer version"), "{err}");
+	}
+
+	#[test]
+	fn errors_keep_their_position() {
+		let err = read("(\n\tversion: 1,\n\tmeta: (format: Scroll),\n)").unwrap_err();
+		let PieceError::Manifest(err) = err else { panic!("{err:?}") };
+		assert_eq!(err.position.line, 3);
+	}
+}
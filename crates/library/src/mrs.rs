@@ -0,0 +1,741 @@
+//! Importing and exporting MusicReader (`.mrs`) files.
+
+use crate::{
+	pdf::draw_strokes, strokes::Strokes, svg, AnnotationFormat, AnnotationLayer, Bookmark, ImageState, Information,
+	Measure, Meta, MusicReaderExtras, PageFormat, PageTurn, Pages, Piece, PieceError, ShownPage,
+};
+use image::{GenericImageView, ImageFormat};
+use log::warn;
+use mr_imp::{thumbnails::thumbnail_size, ImageResult, MRSError, MRSError::Missing, MRSFile, PageImage, PageImages};
+use skia_safe::Color;
+use std::{
+	fmt::{Display, Formatter},
+	io::Cursor,
+	path::{Path, PathBuf},
+};
+
+/// MusicReader's annotation layers, as the name each is imported under and the file stem it is
+/// stored under in the archive.
+pub const MRS_LAYERS: [(&str, &str); 2] = [
+	("MusicReader annotations", "annotations-local"),
+	("MusicReader shared annotations", "annotations-remote"),
+];
+
+#[derive(Debug)]
+pub enum ImportError {
+	/// The file has no pages.
+	NoPages,
+	/// A page image (numbered from 1, as in the archive) is missing or couldn't be read.
+	Page { image: usize, error: MRSError },
+}
+
+impl Display for ImportError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NoPages => f.write_str("file has no pages"),
+			Self::Page { image, error } => write!(f, "page image {image}: {error}"),
+		}
+	}
+}
+
+impl std::error::Error for ImportError {}
+
+/// Convert a loaded MusicReader file into a [`Piece`].
+///
+/// Page images and annotation layers are moved over without being decoded or re-encoded. Every
+/// page image must be present, but damaged metadata only loses what it held, which is logged.
+/// Thumbnails are not imported, since they are regenerated on export. Recordings and parts, which
+/// See Augmented doesn't use, are kept [as they were](MusicReaderExtras).
+pub fn import(file: MRSFile) -> Result<Piece, ImportError> {
+	let MRSFile { path, pages, bookmarks, info } = file;
+	if pages.is_empty() {
+		return Err(ImportError::NoPages);
+	}
+	let page_count = pages.len();
+	let page_index = |image: usize| image.checked_sub(1).filter(|&i| i < page_count);
+
+	let mut files = Vec::with_capacity(page_count);
+	let mut images = Vec::with_capacity(page_count);
+	let mut layers = MRS_LAYERS.map(|(name, stem)| {
+		let layer = AnnotationLayer { name: name.into(), format: AnnotationFormat::Image, files: Vec::new() };
+		(stem, layer, Vec::new())
+	});
+	for (i, mut page) in pages.into_iter().enumerate() {
+		let image = i + 1;
+		let state = image_state(std::mem::replace(&mut page.page, Err(Missing)))
+			.map_err(|error| ImportError::Page { image, error })?;
+		files.push(PathBuf::from(format!("page-{image}.png")));
+		images.push(state);
+
+		for (stem, layer, layer_images) in &mut layers {
+			let state = match image_state(std::mem::replace(page.layer_mut(stem).unwrap(), Err(Missing))) {
+				Ok(state) => Some(state),
+				Err(Missing) => None,
+				Err(e) => {
+					warn!("dropping {stem} image for page {image} of `{}`: {e}", path.display());
+					None
+				}
+			};
+			layer.files.push(state.is_some().then(|| PathBuf::from(format!("{stem}-{image}.png"))));
+			layer_images.push(state);
+		}
+	}
+	let (layers, annotations) = layers
+		.into_iter()
+		.filter(|(_, layer, _)| layer.files.iter().any(Option::is_some))
+		.map(|(_, layer, images)| (layer, images))
+		.unzip();
+
+	let mut meta = Meta {
+		format: PageFormat::Images { files },
+		annotations: AnnotationFormat::Image,
+		layers,
+		information: Information::default(),
+		reading_order: Vec::new(),
+		measures: Vec::new(),
+		bookmarks: Vec::new(),
+		musicreader: None,
+	};
+
+	match info {
+		Ok(info) => {
+			meta.information = information(info.information.clone());
+
+			for page in info.pages_in_order() {
+				let Some(i) = page_index(page.image) else {
+					warn!("page {} of `{}` shows missing image {}", page.number, path.display(), page.image);
+					continue;
+				};
+				let turn = match page.pageturn {
+					mr_imp::PageTurn::Whole => PageTurn::Whole,
+					mr_imp::PageTurn::Half => PageTurn::Half { split: page.split() },
+				};
+				meta.reading_order.push(ShownPage { page: i, turn });
+			}
+
+			for m in info.all_measures() {
+				let Some(page) = page_index(m.image) else {
+					warn!("measure {} of `{}` is on missing image {}", m.number, path.display(), m.image);
+					continue;
+				};
+				let px = |v: usize| u32::try_from(v).unwrap_or(u32::MAX);
+				meta.measures.push(Measure {
+					movement: m.movement,
+					number: m.number,
+					count: m.number_of_measures.max(1),
+					page,
+					left: px(m.x_left),
+					top: px(m.y_top),
+					right: px(m.x_right),
+					bottom: px(m.y_bottom),
+				});
+			}
+
+			let parts = info.parts.as_ref().map(mr_imp::Parts::to_xml).transpose().unwrap_or_else(|e| {
+				warn!("couldn't keep the parts of `{}`: {e}", path.display());
+				None
+			});
+			let recordings = info.recordings.as_ref().map(|recordings| recordings.as_xml().to_owned());
+			if parts.is_some() || recordings.is_some() {
+				meta.musicreader = Some(MusicReaderExtras { recordings, parts });
+			}
+		}
+		Err(e) => {
+			if !matches!(e, Missing) {
+				warn!("couldn't import info.xml of `{}`: {e}", path.display());
+			}
+			meta.information.title = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+		}
+	}
+
+	match bookmarks {
+		Ok(bookmarks) => {
+			for b in bookmarks.as_slice() {
+				let Some(page) = page_index(b.pageimage) else {
+					warn!("bookmark `{}` of `{}` is on missing image {}", b.name, path.display(), b.pageimage);
+					continue;
+				};
+				let px = |v: usize| u32::try_from(v).unwrap_or(u32::MAX);
+				meta.bookmarks.push(Bookmark {
+					name: b.name.clone(),
+					page,
+					x: px(b.location.x),
+					y: px(b.location.y),
+					kind: Some(b.r#type.clone()),
+				});
+			}
+		}
+		Err(Missing) => {}
+		Err(e) => warn!("couldn't import bookmarks.xml of `{}`: {e}", path.display()),
+	}
+
+	Ok(Piece { meta, pages: Pages::Images(images), annotations })
+}
+
+/// Convert the information in a MusicReader file's `info.xml`.
+pub(crate) fn information(info: mr_imp::Information) -> Information {
+	let mr_imp::Information { identifier, title, creator, description, subject, publisher, copyright } = info;
+	Information {
+		title,
+		creators: creator.unwrap_or_default(),
+		description,
+		subjects: subject.unwrap_or_default(),
+		publisher,
+		copyright,
+		source_identifier: Some(identifier),
+	}
+}
+
+fn image_state(image: ImageResult) -> Result<ImageState, MRSError> {
+	Ok(match image? {
+		PageImage::Png(data) => ImageState::Encoded(data),
+		PageImage::DynImg(img) => ImageState::Decoded(img),
+	})
+}
+
+/// Something in a piece that can't be stored in a MusicReader file, so was left out of an export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unrepresentable {
+	/// MusicReader files only have room for two annotation layers.
+	ExtraLayer { name: String },
+	/// The vector annotations of a layer on page `page` (an index) couldn't be read, so there was
+	/// nothing to draw.
+	DamagedAnnotations { name: String, page: usize },
+	/// The manifest lists a layer whose files weren't loaded with the piece, so there was nothing
+	/// to draw.
+	MissingLayer { name: String },
+}
+
+impl Display for Unrepresentable {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::ExtraLayer { name } => write!(f, "no room for annotation layer `{name}`"),
+			Self::DamagedAnnotations { name, page } => {
+				write!(f, "annotations of layer `{name}` on page {} are damaged", page + 1)
+			}
+			Self::MissingLayer { name } => write!(f, "the files of annotation layer `{name}` are missing"),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+	/// An image of page `page` (an index) couldn't be converted.
+	Page { page: usize, error: MRSError },
+	/// Page `page` (an index) or its annotations couldn't be drawn.
+	Render { page: usize, error: PieceError },
+	/// The [recordings or parts](MusicReaderExtras) kept from an imported file aren't valid XML.
+	Extras(MRSError),
+	Save(MRSError),
+}
+
+impl Display for ExportError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Page { page, error } => write!(f, "page {}: {error}", page + 1),
+			Self::Render { page, error } => write!(f, "page {}: {error}", page + 1),
+			Self::Extras(e) => write!(f, "recordings or parts: {e}"),
+			Self::Save(e) => e.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for ExportError {}
+
+/// Convert a [`Piece`] into a MusicReader file, along with everything that had to be left out.
+///
+/// Annotation layers become MusicReader's local and remote annotations, preferring the layers that
+/// were [imported](import) as each of them. Vector annotations are drawn onto images the size of
+/// the page image, as they are when [exporting to PDF](crate::pdf::export). Thumbnails are
+/// generated for every page.
+/// Pages stored as [paths](ImageState::Path) are read synchronously, and pages of a PDF are
+/// rasterized at the [default width](crate::pdf::DEFAULT_WIDTH).
+pub fn export(piece: &Piece) -> Result<(MRSFile, Vec<Unrepresentable>), ExportError> {
+	let page_images = |page| -> Result<_, ExportError> {
+		let page_image = match &piece.pages {
+			Pages::Images(images) => page_image(&images[page], page)?,
+			Pages::Pdf(pdf) => pdf
+				.render(page, crate::pdf::DEFAULT_WIDTH)
+				.map(PageImage::DynImg)
+				.map_err(|error| ExportError::Render { page, error })?,
+		};
+		Ok(PageImages { page: Ok(page_image), ..PageImages::new() })
+	};
+	let pages = (0..piece.pages.len()).map(page_images).collect::<Result<Vec<_>, _>>()?;
+	let size = |images: &PageImages, page| {
+		let size = images.page.as_ref().map_err(MRSError::summarize).and_then(dimensions);
+		size.map_err(|error| ExportError::Page { page, error })
+	};
+	let (mut file, dropped) = convert(piece, pages, size)?;
+
+	let size = thumbnail_size(&file.pages);
+	for (page, images) in file.pages.iter_mut().enumerate() {
+		images.regenerate_thumbnail(size).map_err(|error| ExportError::Page { page, error })?;
+	}
+	Ok((file, dropped))
+}
+
+/// What [`export`] makes of `piece`, apart from its pages and thumbnails, to view the piece as a
+/// MusicReader file without rasterizing or decoding every page first. The page images only hold
+/// the annotation layers; the pages themselves are left to be read as they are shown, such as
+/// through an [`ImageCache`](crate::cache::ImageCache).
+///
+/// Vector annotations are drawn at the size of the page image, which is only read from the
+/// image's header, or for pages of a PDF, the size it is rasterized at by default.
+pub fn view(piece: &Piece) -> Result<(MRSFile, Vec<Unrepresentable>), ExportError> {
+	let pages = (0..piece.pages.len()).map(|_| PageImages::new()).collect();
+	let size = |_: &PageImages, page| {
+		page_size(&piece.pages, page).map_err(|error| ExportError::Render { page, error })
+	};
+	convert(piece, pages, size)
+}
+
+/// Fill in the annotation layers of `pages` and the rest of the file from `piece`. `size` gives
+/// the size in pixels of each page, to draw vector annotations at.
+fn convert(
+	piece: &Piece,
+	mut pages: Vec<PageImages>,
+	size: impl Fn(&PageImages, usize) -> Result<[u32; 2], ExportError>,
+) -> Result<(MRSFile, Vec<Unrepresentable>), ExportError> {
+	let Piece { meta, annotations, .. } = piece;
+	let mut dropped = Vec::new();
+
+	// Layers that were imported from a MusicReader file go back where they came from, then any
+	// others fill the remaining slots in order.
+	let mut slots = [None; MRS_LAYERS.len()];
+	let mut unplaced = Vec::new();
+	for (i, layer) in meta.layers.iter().enumerate() {
+		match MRS_LAYERS.iter().position(|(name, _)| *name == layer.name) {
+			Some(slot) if slots[slot].is_none() => slots[slot] = Some(i),
+			_ => unplaced.push(i),
+		}
+	}
+	for i in unplaced {
+		match slots.iter_mut().find(|slot| slot.is_none()) {
+			Some(slot) => *slot = Some(i),
+			None => dropped.push(Unrepresentable::ExtraLayer { name: meta.layers[i].name.clone() }),
+		}
+	}
+	for (slot, layer) in slots.into_iter().enumerate() {
+		let Some(layer) = layer else { continue };
+		let (_, stem) = MRS_LAYERS[slot];
+		let AnnotationLayer { name, format, .. } = &meta.layers[layer];
+		let Some(files) = annotations.get(layer) else {
+			warn!("leaving out annotation layer `{name}`, whose files weren't loaded");
+			dropped.push(Unrepresentable::MissingLayer { name: name.clone() });
+			continue;
+		};
+		for (page, state) in files.iter().enumerate().take(pages.len()) {
+			let Some(state) = state else { continue };
+			let strokes = match format {
+				AnnotationFormat::Image => {
+					let image = page_image(state, page)?;
+					*pages[page].layer_mut(stem).unwrap() = Ok(image);
+					continue;
+				}
+				AnnotationFormat::Strokes => {
+					let data = state.to_encoded().map_err(|error| ExportError::Render { page, error })?;
+					Strokes::from_bytes(&data).map_err(|e| e.to_string())
+				}
+				AnnotationFormat::Svg => {
+					let data = state.to_encoded().map_err(|error| ExportError::Render { page, error })?;
+					svg::import(&String::from_utf8_lossy(&data)).map_err(|e| e.to_string())
+				}
+			};
+			let strokes = match strokes {
+				Ok(strokes) => strokes,
+				Err(e) => {
+					warn!("leaving out damaged annotations on page {page} of layer `{name}`: {e}");
+					dropped.push(Unrepresentable::DamagedAnnotations { name: name.clone(), page });
+					continue;
+				}
+			};
+			let size = size(&pages[page], page)?;
+			let image = rasterize(&strokes, size).map_err(|error| ExportError::Render { page, error })?;
+			*pages[page].layer_mut(stem).unwrap() = Ok(image);
+		}
+	}
+
+	let image_of = |page: usize| page + 1;
+	let px = |v: u32| v as usize;
+	let reading_order = if meta.reading_order.is_empty() {
+		(0..pages.len()).map(|page| ShownPage { page, turn: PageTurn::Whole }).collect()
+	} else {
+		meta.reading_order.clone()
+	};
+	let info = &meta.information;
+	let extras = meta.musicreader.as_ref();
+	let info = mr_imp::Piece {
+		information: mr_imp::Information {
+			identifier: info.source_identifier.clone().unwrap_or_else(|| info.title.clone()),
+			title: info.title.clone(),
+			creator: (!info.creators.is_empty()).then(|| info.creators.clone()),
+			description: info.description.clone(),
+			subject: (!info.subjects.is_empty()).then(|| info.subjects.clone()),
+			publisher: info.publisher.clone(),
+			copyright: info.copyright.clone(),
+		},
+		pages: mr_imp::Pages {
+			page: reading_order
+				.iter()
+				.enumerate()
+				.map(|(i, shown)| {
+					let (pageturn, halfpage) = match shown.turn {
+						PageTurn::Whole => (mr_imp::PageTurn::Whole, None),
+						PageTurn::Half { split } => (mr_imp::PageTurn::Half, Some(split)),
+					};
+					mr_imp::Page { number: i + 1, image: image_of(shown.page), pageturn, halfpage }
+				})
+				.collect(),
+		},
+		measures: (!meta.measures.is_empty()).then(|| mr_imp::Measures {
+			measure: Some(
+				meta.measures
+					.iter()
+					.map(|m| mr_imp::Measure {
+						number: m.number,
+						number_of_measures: m.count,
+						movement: m.movement,
+						image: image_of(m.page),
+						x_left: px(m.left),
+						x_right: px(m.right),
+						y_top: px(m.top),
+						y_bottom: px(m.bottom),
+					})
+					.collect(),
+			),
+		}),
+		parts: extras
+			.and_then(|extras| extras.parts.as_deref())
+			.map(mr_imp::Parts::from_xml)
+			.transpose()
+			.map_err(ExportError::Extras)?,
+		recordings: extras
+			.and_then(|extras| extras.recordings.as_deref())
+			.map(mr_imp::Recordings::from_xml)
+			.transpose()
+			.map_err(ExportError::Extras)?,
+	};
+
+	let mut bookmarks = mr_imp::Bookmarks::new();
+	for b in &meta.bookmarks {
+		let mut bookmark = mr_imp::Bookmark::new(b.name.clone(), image_of(b.page), px(b.x), px(b.y));
+		if let Some(kind) = &b.kind {
+			bookmark.r#type = kind.clone();
+		}
+		bookmarks.add(bookmark);
+	}
+
+	let file = MRSFile { path: PathBuf::new(), pages, bookmarks: Ok(bookmarks), info: Ok(info) };
+	Ok((file, dropped))
+}
+
+/// [Export](export) `piece` and save it to `path`, returning everything that had to be left out.
+pub async fn export_to(piece: &Piece, path: impl AsRef<Path>) -> Result<Vec<Unrepresentable>, ExportError> {
+	let (mut file, dropped) = export(piece)?;
+	file.path = path.as_ref().to_owned();
+	file.save(&file.path).await.map_err(ExportError::Save)?;
+	Ok(dropped)
+}
+
+/// `[width, height]` of an image, only reading the header of PNG data.
+fn dimensions(image: &PageImage) -> Result<[u32; 2], MRSError> {
+	let (width, height) = match image {
+		PageImage::Png(data) => image::io::Reader::with_format(Cursor::new(data), ImageFormat::Png)
+			.into_dimensions()
+			.map_err(MRSError::ImageErr)?,
+		PageImage::DynImg(img) => img.dimensions(),
+	};
+	Ok([width, height])
+}
+
+/// `[width, height]` of page `page` (an index) of `pages`, only reading the header of encoded
+/// images. Pages of a PDF are measured as rasterized at the [default width](crate::pdf::DEFAULT_WIDTH).
+fn page_size(pages: &Pages, page: usize) -> Result<[u32; 2], PieceError> {
+	let encoded = |data: &[u8]| -> Result<_, PieceError> {
+		let reader = image::io::Reader::new(Cursor::new(data)).with_guessed_format()?;
+		reader.into_dimensions().map_err(PieceError::Image)
+	};
+	let (width, height) = match pages {
+		Pages::Images(images) => match images.get(page).ok_or(PieceError::NoSuchImage(page))? {
+			ImageState::Path(path) => image::image_dimensions(path).map_err(PieceError::Image)?,
+			ImageState::Encoded(data) => encoded(data)?,
+			ImageState::Decoded(img) => img.dimensions(),
+			ImageState::Rendered(img) => (img.width() as u32, img.height() as u32),
+		},
+		Pages::Pdf(pdf) => {
+			let [width, height] = pdf.size(page).ok_or(PieceError::NoSuchImage(page))?;
+			let default = crate::pdf::DEFAULT_WIDTH;
+			(default, (default as f32 * height / width).round() as u32)
+		}
+	};
+	Ok([width, height])
+}
+
+/// Draw `strokes` onto a transparent image of `[width, height]` pixels.
+fn rasterize(strokes: &Strokes, [width, height]: [u32; 2]) -> Result<PageImage, PieceError> {
+	let mut surface =
+		skia_safe::surfaces::raster_n32_premul((width as i32, height as i32)).ok_or(PieceError::Unrenderable)?;
+	surface.canvas().clear(Color::TRANSPARENT);
+	draw_strokes(surface.canvas(), strokes, 1.0);
+	let image = crate::rendered_to_dynamic(&surface.image_snapshot()).ok_or(PieceError::Unreadable)?;
+	Ok(PageImage::DynImg(image))
+}
+
+/// Convert an image on page `page` (an index) to one that can be stored in a MusicReader file,
+/// keeping PNG data as it is.
+fn page_image(state: &ImageState, page: usize) -> Result<PageImage, ExportError> {
+	let encoded = |data: Vec<u8>| match image::guess_format(&data) {
+		Ok(ImageFormat::Png) => Ok(PageImage::Png(data)),
+		_ => image::load_from_memory(&data).map(PageImage::DynImg).map_err(MRSError::ImageErr),
+	};
+	let image = match state {
+		ImageState::Path(path) => std::fs::read(path).map_err(MRSError::IoErr).and_then(encoded),
+		ImageState::Encoded(data) => encoded(data.clone()),
+		ImageState::Decoded(img) => Ok(PageImage::DynImg(img.clone())),
+		ImageState::Rendered(img) => {
+			let img = crate::rendered_to_dynamic(img).ok_or(ExportError::Render { page, error: PieceError::Unreadable })?;
+			Ok(PageImage::DynImg(img))
+		}
+	};
+	image.map_err(|error| ExportError::Page { page, error })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::strokes::{Stroke, StrokeSample, Tool};
+	use image::{DynamicImage, RgbaImage};
+	use mr_imp::{Bookmarks, Pages as MRSPages};
+	use test_log::test;
+
+	fn img() -> ImageResult {
+		Ok(PageImage::DynImg(DynamicImage::ImageRgba8(RgbaImage::new(8, 4))))
+	}
+
+	fn png() -> Vec<u8> {
+		img().unwrap().to_png().unwrap().into_owned()
+	}
+
+	fn mrs_file() -> MRSFile {
+		MRSFile {
+			path: PathBuf::from("Gavotte.mrs"),
+			pages: vec![
+				PageImages { page: img(), thumbnail: img(), annotations_local: img(), ..PageImages::new() },
+				PageImages { page: Ok(PageImage::Png(png())), ..PageImages::new() },
+			],
+			bookmarks: Ok(Bookmarks {
+				bookmark: Some(vec![
+					mr_imp::Bookmark { r#type: "custom".into(), ..mr_imp::Bookmark::new("Trio", 2, 1, 3) },
+					mr_imp::Bookmark::new("Nowhere", 3, 0, 0),
+				]),
+			}),
+			info: Ok(mr_imp::Piece {
+				information: mr_imp::Information {
+					identifier: "MR1".into(),
+					title: "Gavotte".into(),
+					creator: Some(vec!["Gossec, François-Joseph [composer]".into()]),
+					description: None,
+					subject: None,
+					publisher: None,
+					copyright: Some("Public domain".into()),
+				},
+				pages: MRSPages {
+					page: vec![
+						mr_imp::Page { number: 2, image: 2, pageturn: mr_imp::PageTurn::Whole, halfpage: None },
+						mr_imp::Page { number: 1, image: 1, pageturn: mr_imp::PageTurn::Half, halfpage: Some(0.4) },
+					],
+				},
+				measures: Some(mr_imp::Measures {
+					measure: Some(vec![mr_imp::Measure {
+						number: 5,
+						number_of_measures: 1,
+						movement: 1,
+						image: 2,
+						x_left: 1,
+						x_right: 4,
+						y_top: 0,
+						y_bottom: 3,
+					}]),
+				}),
+				parts: Some(mr_imp::Parts {
+					image: vec![
+						mr_imp::ImageInfo { part: Some(vec![mr_imp::Part { x: 0, y: 1, width: 8, height: 2 }]) },
+						mr_imp::ImageInfo { part: None },
+					],
+				}),
+				recordings: Some(
+					mr_imp::Recordings::from_xml("<recordings><recording><file>gavotte.m4a</file></recording></recordings>")
+						.unwrap(),
+				),
+			}),
+		}
+	}
+
+	#[test]
+	fn import_file() {
+		let piece = import(mrs_file()).unwrap();
+		let meta = &piece.meta;
+
+		let Pages::Images(pages) = &piece.pages else { panic!("pages aren't images") };
+		assert!(matches!(pages[..], [ImageState::Decoded(_), ImageState::Encoded(_)]));
+		assert_eq!(
+			meta.format,
+			PageFormat::Images { files: vec!["page-1.png".into(), "page-2.png".into()] }
+		);
+
+		assert_eq!(meta.layers.len(), 1, "empty layers are left out");
+		assert_eq!(meta.layers[0].format, AnnotationFormat::Image);
+		assert_eq!(meta.layers[0].files, [Some("annotations-local-1.png".into()), None]);
+		assert!(matches!(piece.annotations[0][..], [Some(ImageState::Decoded(_)), None]));
+		assert_eq!(meta.annotation_files().files.len(), 1);
+
+		assert_eq!(meta.information.title, "Gavotte");
+		assert_eq!(meta.information.creators, ["Gossec, François-Joseph [composer]"]);
+		assert_eq!(meta.information.source_identifier.as_deref(), Some("MR1"));
+		assert_eq!(
+			meta.reading_order,
+			[
+				ShownPage { page: 0, turn: PageTurn::Half { split: 0.4 } },
+				ShownPage { page: 1, turn: PageTurn::Whole },
+			]
+		);
+		assert_eq!(
+			meta.measures,
+			[Measure { movement: 1, number: 5, count: 1, page: 1, left: 1, top: 0, right: 4, bottom: 3 }]
+		);
+		assert_eq!(
+			meta.bookmarks,
+			[Bookmark { name: "Trio".into(), page: 1, x: 1, y: 3, kind: Some("custom".into()) }]
+		);
+
+		let extras = meta.musicreader.as_ref().unwrap();
+		assert!(extras.recordings.as_ref().unwrap().contains("<file>gavotte.m4a</file>"));
+		assert!(extras.parts.as_ref().unwrap().starts_with("<parts>"));
+	}
+
+	#[test]
+	fn damaged_metadata() {
+		let mut file = mrs_file();
+		file.info = Err(Missing);
+		file.bookmarks = Bookmarks::from_xml("<bookmarks><bookmark>");
+		let piece = import(file).unwrap();
+		assert_eq!(piece.meta.information.title, "Gavotte");
+		assert_eq!(piece.meta.information.source_identifier, None);
+		assert!(piece.meta.reading_order.is_empty());
+		assert!(piece.meta.bookmarks.is_empty());
+	}
+
+	#[test]
+	fn export_imported() {
+		let original = mrs_file();
+		let (exported, dropped) = export(&import(mrs_file()).unwrap()).unwrap();
+		assert!(dropped.is_empty(), "{dropped:?}");
+
+		assert_eq!(exported.pages.len(), 2);
+		assert!(matches!(exported.pages[0].annotations_local, Ok(PageImage::DynImg(_))));
+		assert!(matches!(exported.pages[1].page, Ok(PageImage::Png(_))));
+		assert!(exported.pages.iter().all(|page| page.thumbnail.is_ok()));
+
+		let (original_info, info) = (original.info.unwrap(), exported.info.unwrap());
+		assert_eq!(info.information, original_info.information);
+		assert_eq!(info.measures, original_info.measures);
+		assert_eq!(info.page_views(), original_info.page_views());
+		assert_eq!(info.parts, original_info.parts);
+		assert_eq!(info.recordings, original_info.recordings);
+		let bookmarks = exported.bookmarks.unwrap();
+		assert_eq!(bookmarks.as_slice(), &original.bookmarks.unwrap().as_slice()[..1]);
+	}
+
+	#[test]
+	fn unrepresentable_layers() {
+		let mut piece = import(mrs_file()).unwrap();
+		let layer = |name: &str, format| AnnotationLayer { name: name.into(), format, files: vec![None, None] };
+		for (name, format) in [
+			("Strokes", AnnotationFormat::Strokes),
+			("Scan", AnnotationFormat::Image),
+			("Teacher", AnnotationFormat::Image),
+		] {
+			piece.meta.layers.push(layer(name, format));
+			piece.annotations.push(vec![None, None]);
+		}
+		let sample = |x, y| StrokeSample { x, y, pressure: 1.0, time: 0 };
+		let stroke = Stroke {
+			tool: Tool::Pen,
+			color: [0, 0, 0, 255],
+			width: 2.0,
+			started: 0,
+			samples: vec![sample(1.0, 1.0), sample(6.0, 2.0)],
+		};
+		let strokes = Strokes { strokes: vec![stroke] };
+		let damaged = b"SAst".to_vec();
+		piece.annotations[1] = vec![Some(ImageState::Encoded(strokes.to_bytes())), Some(ImageState::Encoded(damaged))];
+		piece.annotations[2][1] = Some(ImageState::Decoded(DynamicImage::ImageRgba8(RgbaImage::new(8, 4))));
+
+		let (exported, dropped) = export(&piece).unwrap();
+		assert_eq!(
+			dropped,
+			[
+				Unrepresentable::ExtraLayer { name: "Scan".into() },
+				Unrepresentable::ExtraLayer { name: "Teacher".into() },
+				Unrepresentable::DamagedAnnotations { name: "Strokes".into(), page: 1 },
+			]
+		);
+		// The imported layer keeps its slot, and the strokes take the free one, drawn at the size
+		// of the page image.
+		assert!(exported.pages[0].annotations_local.is_ok());
+		let png = exported.pages[0].annotations_remote.as_ref().unwrap().to_png().unwrap();
+		assert!(!png.is_empty());
+		let drawn = image::load_from_memory(&png).unwrap();
+		assert_eq!(drawn.dimensions(), (8, 4));
+		assert!(drawn.pixels().any(|(_, _, pixel)| pixel[3] > 0), "nothing was drawn");
+		assert!(matches!(exported.pages[1].annotations_remote, Err(Missing)));
+	}
+
+	#[test]
+	fn view_leaves_pages_unread() {
+		let mut piece = import(mrs_file()).unwrap();
+		let layer = AnnotationLayer { name: "Strokes".into(), format: AnnotationFormat::Strokes, files: vec![None, None] };
+		piece.meta.layers.push(layer);
+		let strokes = Strokes { strokes: Vec::new() };
+		piece.annotations.push(vec![None, Some(ImageState::Encoded(strokes.to_bytes()))]);
+
+		let (viewed, dropped) = view(&piece).unwrap();
+		let (exported, _) = export(&piece).unwrap();
+		assert!(dropped.is_empty(), "{dropped:?}");
+		assert_eq!(viewed.info.ok(), exported.info.ok());
+		assert_eq!(viewed.bookmarks.ok(), exported.bookmarks.ok());
+		assert!(viewed.pages.iter().all(|page| matches!((&page.page, &page.thumbnail), (Err(Missing), Err(Missing)))));
+		assert!(viewed.pages[0].annotations_local.is_ok());
+		// The strokes are drawn at the size in the header of the encoded page.
+		let png = viewed.pages[1].annotations_remote.as_ref().unwrap().to_png().unwrap();
+		assert_eq!(image::load_from_memory(&png).unwrap().dimensions(), (8, 4));
+	}
+
+	#[test(tokio::test)]
+	async fn export_to_file() {
+		let path = std::env::temp_dir().join(format!("see-aug-library-{}-export.mrs", std::process::id()));
+		export_to(&import(mrs_file()).unwrap(), &path).await.unwrap();
+		let saved = MRSFile::load(&path).await.unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(saved.pages.len(), 2);
+		assert_eq!(saved.info.unwrap().information.title, "Gavotte");
+	}
+
+	#[test]
+	fn missing_layer_files() {
+		let mut piece = import(mrs_file()).unwrap();
+		piece.annotations.clear();
+		let (exported, dropped) = export(&piece).unwrap();
+		assert_eq!(dropped, [Unrepresentable::MissingLayer { name: piece.meta.layers[0].name.clone() }]);
+		assert!(matches!(exported.pages[0].annotations_local, Err(Missing)));
+	}
+
+	#[test]
+	fn missing_page() {
+		let mut file = mrs_file();
+		file.pages[1].page = Err(Missing);
+		assert!(matches!(import(file), Err(ImportError::Page { image: 2, error: Missing })));
+		assert!(matches!(import(MRSFile { pages: Vec::new(), ..mrs_file() }), Err(ImportError::NoPages)));
+	}
+}
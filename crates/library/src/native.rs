@@ -0,0 +1,272 @@
+//! See Augmented's own piece format: a zip container holding a [versioned](crate::CURRENT_VERSION)
+//! `meta.ron` manifest along with every page and annotation file it lists.
+
+use crate::{atomic, manifest, pdf::PdfPages, ImageState, Meta, PageFormat, Pages, Piece, PieceError};
+use async_zip::{
+	tokio::{read::fs::ZipFileReader, write::ZipFileWriter},
+	Compression, ZipEntryBuilder,
+};
+use std::{
+	collections::HashMap,
+	path::{Component, Path},
+	sync::Arc,
+};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+/// Name of the manifest in the container.
+pub const MANIFEST: &str = "meta.ron";
+/// File extension of saved pieces.
+pub const EXTENSION: &str = "saug";
+
+impl Meta {
+	/// Read only the manifest of a piece saved with [`Piece::save`], without reading any of its
+	/// pages or annotations.
+	pub async fn load(path: impl AsRef<Path>) -> Result<Self, PieceError> {
+		let reader = ZipFileReader::new(path.as_ref()).await?;
+		read_meta(&reader, &entry_indices(&reader)).await
+	}
+
+	/// How many pages the piece saved at `path`, whose manifest this is, has. A PDF is opened to
+	/// count them, on a blocking thread, but none of its pages are rendered.
+	pub async fn page_count(&self, path: impl AsRef<Path>) -> Result<usize, PieceError> {
+		match &self.format {
+			PageFormat::Images { files } => Ok(files.len()),
+			PageFormat::Pdf { file } => {
+				let reader = ZipFileReader::new(path.as_ref()).await?;
+				let data = read_file(&reader, &entry_indices(&reader), file).await?;
+				Ok(open_pdf(data).await?.len())
+			}
+		}
+	}
+}
+
+impl Piece {
+	/// Load a piece saved with [`save`](Self::save). Page and annotation files are read but not
+	/// decoded.
+	pub async fn load(path: impl AsRef<Path>) -> Result<Self, PieceError> {
+		let reader = ZipFileReader::new(path.as_ref()).await?;
+		let entries = entry_indices(&reader);
+
+		let meta = read_meta(&reader, &entries).await?;
+
+		let pages = match &meta.format {
+			PageFormat::Images { files } => {
+				let mut images = Vec::with_capacity(files.len());
+				for file in files {
+					images.push(ImageState::Encoded(read_file(&reader, &entries, file).await?));
+				}
+				Pages::Images(images)
+			}
+			PageFormat::Pdf { file } => Pages::Pdf(Arc::new(open_pdf(read_file(&reader, &entries, file).await?).await?)),
+		};
+
+		let mut annotations = Vec::with_capacity(meta.layers.len());
+		for layer in &meta.layers {
+			let mut layer_files = Vec::with_capacity(layer.files.len());
+			for file in &layer.files {
+				layer_files.push(match file {
+					Some(file) => Some(ImageState::Encoded(read_file(&reader, &entries, file).await?)),
+					None => None,
+				});
+			}
+			annotations.push(layer_files);
+		}
+
+		Ok(Self { meta, pages, annotations })
+	}
+
+	/// Save the piece to `path`, replacing whatever is there only once the new file has been
+	/// completely written to disk. If saving fails, any existing file is left as it was.
+	///
+	/// Images that are only held decoded are encoded as PNG, and images stored at a path are
+	/// read synchronously.
+	pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), PieceError> {
+		atomic::write_with(path.as_ref(), |temp| async move { self.write_to(&temp).await }).await
+	}
+
+	async fn write_to(&self, path: &Path) -> Result<(), PieceError> {
+		let mut writer = ZipFileWriter::with_tokio(File::create(path).await?);
+
+		let manifest = manifest::write(&self.meta)?;
+		write_file(&mut writer, Path::new(MANIFEST), manifest.as_bytes(), Compression::Deflate).await?;
+
+		// Images and PDFs are already compressed, so deflating them again only costs time.
+		match (&self.meta.format, &self.pages) {
+			(PageFormat::Images { files }, Pages::Images(images)) => {
+				for (i, file) in files.iter().enumerate() {
+					let image = images.get(i).ok_or_else(|| PieceError::Missing(file.clone()))?;
+					write_file(&mut writer, file, &image.to_encoded()?, Compression::Stored).await?;
+				}
+			}
+			(PageFormat::Pdf { file }, Pages::Pdf(pdf)) => {
+				write_file(&mut writer, file, pdf.data(), Compression::Stored).await?;
+			}
+			_ => return Err(PieceError::FormatMismatch),
+		}
+
+		for (l, layer) in self.meta.layers.iter().enumerate() {
+			for (page, file) in layer.files.iter().enumerate() {
+				let Some(file) = file else { continue };
+				let data = self
+					.annotations
+					.get(l)
+					.and_then(|files| files.get(page)?.as_ref())
+					.ok_or_else(|| PieceError::Missing(file.clone()))?;
+				write_file(&mut writer, file, &data.to_encoded()?, Compression::Deflate).await?;
+			}
+		}
+
+		let mut file = writer.close().await?.into_inner();
+		file.flush().await?;
+		file.sync_all().await?;
+		Ok(())
+	}
+}
+
+/// Name of the zip entry for `path`, which is always relative and separated by `/`.
+fn entry_name(path: &Path) -> String {
+	path.components()
+		.filter_map(|c| match c {
+			Component::Normal(c) => Some(c.to_string_lossy()),
+			_ => None,
+		})
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+/// Index of each entry in the container, by name.
+fn entry_indices(reader: &ZipFileReader) -> HashMap<String, usize> {
+	reader
+		.file()
+		.entries()
+		.iter()
+		.enumerate()
+		.map(|(i, entry)| (String::from_utf8_lossy(entry.filename().as_bytes()).into_owned(), i))
+		.collect()
+}
+
+async fn read_meta(reader: &ZipFileReader, entries: &HashMap<String, usize>) -> Result<Meta, PieceError> {
+	let manifest = read_file(reader, entries, Path::new(MANIFEST)).await?;
+	let manifest = String::from_utf8(manifest)
+		.map_err(|e| PieceError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+	manifest::read(&manifest)
+}
+
+async fn read_file(
+	reader: &ZipFileReader,
+	entries: &HashMap<String, usize>,
+	path: &Path,
+) -> Result<Vec<u8>, PieceError> {
+	let i = *entries.get(&entry_name(path)).ok_or_else(|| PieceError::Missing(path.to_owned()))?;
+	let mut buf = Vec::new();
+	reader.reader_with_entry(i).await?.read_to_end_checked(&mut buf).await?;
+	Ok(buf)
+}
+
+async fn write_file(
+	writer: &mut ZipFileWriter<File>,
+	path: &Path,
+	data: &[u8],
+	compression: Compression,
+) -> Result<(), PieceError> {
+	let entry = ZipEntryBuilder::new(entry_name(path).into(), compression);
+	writer.write_entry_whole(entry, data).await?;
+	Ok(())
+}
+
+/// Open the PDF in `data` on a blocking thread, since Pdfium parses the document as it opens it.
+async fn open_pdf(data: Vec<u8>) -> Result<PdfPages, PieceError> {
+	let pdf = tokio::task::spawn_blocking(move || PdfPages::new(data));
+	pdf.await.map_err(std::io::Error::from)?
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{test_util::temp_dir, AnnotationFormat, AnnotationLayer, Bookmark, Information};
+	use image::{DynamicImage, RgbaImage};
+	use test_log::test;
+
+	fn piece() -> Piece {
+		let mut img = RgbaImage::new(8, 4);
+		img.put_pixel(3, 2, image::Rgba([255, 0, 0, 255]));
+		let img = DynamicImage::ImageRgba8(img);
+		Piece {
+			meta: Meta {
+				format: PageFormat::Images { files: vec!["pages/1.png".into(), "pages/2.png".into()] },
+				annotations: AnnotationFormat::Image,
+				layers: vec![AnnotationLayer {
+					name: "Scan".into(),
+					format: AnnotationFormat::Image,
+					files: vec![None, Some("layers/scan/2.png".into())],
+				}],
+				information: Information { title: "Native".into(), ..Information::default() },
+				reading_order: Vec::new(),
+				measures: Vec::new(),
+				bookmarks: vec![Bookmark { name: "B".into(), page: 1, x: 2, y: 3, kind: None }],
+				musicreader: None,
+			},
+			pages: Pages::Images(vec![
+				ImageState::Decoded(img.clone()),
+				ImageState::Encoded(ImageState::Decoded(img.clone()).to_encoded().unwrap().into_owned()),
+			]),
+			annotations: vec![vec![None, Some(ImageState::Decoded(img))]],
+		}
+	}
+
+	fn decoded(state: &ImageState) -> DynamicImage {
+		image::load_from_memory(&state.to_encoded().unwrap()).unwrap()
+	}
+
+	#[test(tokio::test)]
+	async fn round_trip() {
+		let dir = temp_dir("round-trip");
+		let path = dir.join("piece.saug");
+		let original = piece();
+		original.save(&path).await.unwrap();
+		let loaded = Piece::load(&path).await.unwrap();
+
+		assert_eq!(loaded.meta, original.meta);
+		let (Pages::Images(a), Pages::Images(b)) = (&original.pages, &loaded.pages) else {
+			panic!("pages aren't images");
+		};
+		assert_eq!(a.len(), b.len());
+		for (a, b) in a.iter().zip(b) {
+			assert!(matches!(b, ImageState::Encoded(_)));
+			assert_eq!(decoded(a), decoded(b));
+		}
+		assert!(loaded.annotations[0][0].is_none());
+		assert_eq!(
+			decoded(loaded.annotations[0][1].as_ref().unwrap()),
+			decoded(original.annotations[0][1].as_ref().unwrap())
+		);
+
+		let leftovers = std::fs::read_dir(&dir).unwrap().count();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(leftovers, 1, "temporary file was left behind");
+	}
+
+	#[test(tokio::test)]
+	async fn failed_save_keeps_original() {
+		let dir = temp_dir("failed-save");
+		let path = dir.join("piece.saug");
+		piece().save(&path).await.unwrap();
+		let before = std::fs::read(&path).unwrap();
+
+		let mut broken = piece();
+		broken.annotations[0][1] = None;
+		assert!(matches!(broken.save(&path).await, Err(PieceError::Missing(_))));
+
+		let after = std::fs::read(&path).unwrap();
+		let leftovers = std::fs::read_dir(&dir).unwrap().count();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(before, after);
+		assert_eq!(leftovers, 1, "temporary file was left behind");
+	}
+
+	#[test]
+	fn entry_names() {
+		assert_eq!(entry_name(Path::new("pages/1.png")), "pages/1.png");
+		assert_eq!(entry_name(Path::new("/abs/../x.png")), "abs/x.png");
+	}
+}
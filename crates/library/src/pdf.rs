@@ -0,0 +1,417 @@
+//! Pages that come from a PDF ([`PageFormat::Pdf`](crate::PageFormat)), rasterized on demand
+//! at whatever size they are shown, and [exporting](export) annotated pieces to PDF for printing.
+//!
+//! PDFs are rendered with [Pdfium](https://pdfium.googlesource.com/pdfium/), which is linked into
+//! the executable with the `static-pdfium` feature (the default). Without it, Pdfium is loaded when
+//! the first PDF is opened: from next to the executable if it is bundled there, otherwise from the
+//! system's library path. It doesn't need a display, so it works on headless machines too.
+//! Exported PDFs are written with Skia's PDF backend, which doesn't need one either.
+
+use crate::{
+	dynamic_to_rendered,
+	strokes::{Strokes, Tool},
+	svg, AnnotationFormat, ImageState, Pages, Piece, PieceError, SkiaImage,
+};
+use image::DynamicImage;
+use log::{debug, info, warn};
+use pdfium_render::prelude::{PdfPageIndex, PdfRenderConfig, Pdfium, PdfiumError, PdfiumLibraryBindings};
+use skia_safe::{images, pdf::Metadata, BlendMode, Canvas, Color, Data, Paint, Path, Rect};
+use std::sync::OnceLock;
+
+/// Width that pages are rasterized at when they aren't being shown, such as when converting
+/// them to images: an A4 page at 300 DPI.
+pub const DEFAULT_WIDTH: u32 = 2480;
+/// Width that pages made from images are [exported](export) at, in points: the width of an A4
+/// page. Their height follows from the image.
+pub const EXPORT_WIDTH: f32 = 595.0;
+
+/// The contents of a PDF, along with the size of each of its pages.
+#[derive(Debug)]
+pub struct PdfPages {
+	data: Vec<u8>,
+	/// `[width, height]` of each page in points
+	sizes: Vec<[f32; 2]>,
+}
+
+impl PdfPages {
+	/// Open a PDF from its contents.
+	pub fn new(data: Vec<u8>) -> Result<Self, PieceError> {
+		let document = pdfium()?.load_pdf_from_byte_slice(&data, None)?;
+		let sizes = document.pages().iter().map(|page| [page.width().value, page.height().value]).collect();
+		drop(document);
+		Ok(Self { data, sizes })
+	}
+
+	/// The contents of the PDF file.
+	pub fn data(&self) -> &[u8] {
+		&self.data
+	}
+
+	pub fn len(&self) -> usize {
+		self.sizes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.sizes.is_empty()
+	}
+
+	/// `[width, height]` of `page` in points, for laying it out before it is rendered.
+	pub fn size(&self, page: usize) -> Option<[f32; 2]> {
+		self.sizes.get(page).copied()
+	}
+
+	/// Rasterize `page` at `width` pixels wide, keeping its aspect ratio.
+	///
+	/// This can take a while, so avoid calling it on an async or UI thread.
+	pub fn render(&self, page: usize, width: u32) -> Result<DynamicImage, PieceError> {
+		let document = pdfium()?.load_pdf_from_byte_slice(&self.data, None)?;
+		// Pdfium reports pages past the end, including ones too far to index.
+		let index = PdfPageIndex::try_from(page).unwrap_or(PdfPageIndex::MAX);
+		let config = PdfRenderConfig::new().set_target_width(width.min(i32::MAX as u32) as i32);
+		let pdf_page = document.pages().get(index)?;
+		let image = pdf_page.render_with_config(&config)?.as_image();
+		debug!("rendered PDF page {page} at {width} pixels wide");
+		Ok(image)
+	}
+}
+
+/// Print `piece` to a PDF, with its annotation layers drawn over each page.
+///
+/// `layer_opacity` is the opacity of each of the piece's [layers](crate::Meta::layers), in the
+/// same order; layers at 0 are left out, and layers past the end of it are drawn fully opaque.
+/// Strokes stay vector paths, and page images are embedded as they are encoded where Skia can read
+/// them. Pages of a PDF are rasterized again at the [default width](DEFAULT_WIDTH) of 2480 pixels
+/// rather than copied, so their text and vector graphics become an image. Damaged stroke
+/// annotations are left out, which is logged. A MusicReader file can be exported by
+/// [importing](crate::mrs::import) it first.
+///
+/// This decodes every page, so avoid calling it on an async or UI thread.
+pub fn export(piece: &Piece, layer_opacity: &[f32]) -> Result<Vec<u8>, PieceError> {
+	let Piece { meta, pages, annotations } = piece;
+	let info = &meta.information;
+	// PDF's document information has no field for copyright, so it follows the description.
+	let subject = [info.description.as_deref(), info.copyright.as_deref()];
+	let metadata = Metadata {
+		title: info.title.clone(),
+		author: info.creators.join("; "),
+		subject: subject.into_iter().flatten().collect::<Vec<_>>().join("\n"),
+		keywords: info.subjects.join(", "),
+		creator: "See Augmented".into(),
+		..Metadata::default()
+	};
+
+	let mut data = Vec::new();
+	let mut document = skia_safe::pdf::new_document(&mut data, Some(&metadata));
+	for page in 0..pages.len() {
+		let (image, width, height) = match pages {
+			Pages::Images(images) => {
+				let image = skia_image(&images[page])?;
+				(image.clone(), EXPORT_WIDTH, EXPORT_WIDTH * image.height() as f32 / image.width() as f32)
+			}
+			Pages::Pdf(pdf) => {
+				let image = dynamic_to_rendered(&pdf.render(page, DEFAULT_WIDTH)?).ok_or(PieceError::Unrenderable)?;
+				let [width, height] = pdf.size(page).unwrap_or_default();
+				(image, width, height)
+			}
+		};
+		let mut on_page = document.begin_page((width, height), None);
+		let canvas = on_page.canvas();
+		// Annotations are in page image pixels.
+		let scale = width / image.width() as f32;
+		canvas.scale((scale, scale));
+		canvas.draw_image(&image, (0, 0), None);
+		let page_rect = Rect::from_wh(image.width() as f32, image.height() as f32);
+
+		for (l, layer) in meta.layers.iter().enumerate() {
+			let opacity = layer_opacity.get(l).copied().unwrap_or(1.0).clamp(0.0, 1.0);
+			let Some(Some(file)) = annotations.get(l).and_then(|files| files.get(page)) else { continue };
+			if opacity == 0.0 {
+				continue;
+			}
+			let strokes = match layer.format {
+				AnnotationFormat::Image => {
+					let mut paint = Paint::default();
+					paint.set_alpha_f(opacity);
+					canvas.draw_image_rect(skia_image(file)?, None, page_rect, &paint);
+					continue;
+				}
+				AnnotationFormat::Strokes => Strokes::from_bytes(&file.to_encoded()?).map_err(|e| e.to_string()),
+				AnnotationFormat::Svg => {
+					let svg = String::from_utf8_lossy(&file.to_encoded()?).into_owned();
+					svg::import(&svg).map_err(|e| e.to_string())
+				}
+			};
+			match strokes {
+				Ok(strokes) => draw_strokes(canvas, &strokes, opacity),
+				Err(e) => warn!("leaving out damaged annotations on page {page} of layer `{}`: {e}", layer.name),
+			}
+		}
+		document = on_page.end_page();
+	}
+	document.close();
+	Ok(data)
+}
+
+/// Draw each stroke as a filled outline. Highlighters multiply with what is under them, so that
+/// they color the paper without hiding the notes.
+pub(crate) fn draw_strokes(canvas: &Canvas, strokes: &Strokes, opacity: f32) {
+	for stroke in strokes.strokes.iter().filter(|stroke| !stroke.samples.is_empty()) {
+		let mut path = Path::new();
+		for (i, [x, y]) in stroke.outline().into_iter().enumerate() {
+			if i == 0 {
+				path.move_to((x, y));
+			} else {
+				path.line_to((x, y));
+			}
+		}
+		path.close();
+
+		let [r, g, b, a] = stroke.color;
+		let mut paint = Paint::default();
+		paint.set_anti_alias(true).set_color(Color::from_argb(a, r, g, b));
+		paint.set_alpha_f(paint.alpha_f() * opacity);
+		if stroke.tool == Tool::Highlighter {
+			paint.set_blend_mode(BlendMode::Multiply);
+		}
+		canvas.draw_path(&path, &paint);
+	}
+}
+
+/// A Skia image of a page or annotation. Encoded images are handed to Skia without decoding
+/// them, so that formats PDF can hold, like JPEG, are embedded as they are.
+fn skia_image(state: &ImageState) -> Result<SkiaImage, PieceError> {
+	match state {
+		ImageState::Rendered(image) => Ok(image.clone()),
+		ImageState::Decoded(image) => dynamic_to_rendered(image).ok_or(PieceError::Unrenderable),
+		ImageState::Path(_) | ImageState::Encoded(_) => {
+			let data = state.to_encoded()?;
+			if let Some(image) = images::deferred_from_encoded_data(Data::new_copy(&data), None) {
+				return Ok(image);
+			}
+			// Skia can't read every format that `image` can, such as TIFF.
+			let image = image::load_from_memory(&data).map_err(PieceError::Image)?;
+			dynamic_to_rendered(&image).ok_or(PieceError::Unrenderable)
+		}
+	}
+}
+
+/// Pdfium, loaded the first time it is needed.
+fn pdfium() -> Result<&'static Pdfium, PieceError> {
+	static PDFIUM: OnceLock<Result<Pdfium, String>> = OnceLock::new();
+	let pdfium = PDFIUM.get_or_init(|| match bindings() {
+		Ok(bindings) => {
+			info!("loaded Pdfium");
+			Ok(Pdfium::new(bindings))
+		}
+		Err(e) => Err(e.to_string()),
+	});
+	pdfium.as_ref().map_err(|e| PieceError::NoPdfium(e.clone()))
+}
+
+/// Pdfium, linked into the executable.
+#[cfg(feature = "static-pdfium")]
+fn bindings() -> Result<Box<dyn PdfiumLibraryBindings>, PdfiumError> {
+	Pdfium::bind_to_statically_linked_library()
+}
+
+/// Pdfium from next to the executable if it is bundled there, otherwise from the system's library path.
+#[cfg(not(feature = "static-pdfium"))]
+fn bindings() -> Result<Box<dyn PdfiumLibraryBindings>, PdfiumError> {
+	let bundled = std::env::current_exe()
+		.ok()
+		.and_then(|exe| exe.parent().map(Pdfium::pdfium_platform_library_name_at_path));
+	match bundled.map(Pdfium::bind_to_library) {
+		Some(Ok(bindings)) => Ok(bindings),
+		_ => Pdfium::bind_to_system_library(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		strokes::{Stroke, StrokeSample},
+		AnnotationLayer, Information, Meta, PageFormat,
+	};
+	use image::{GenericImageView, RgbaImage};
+	use lopdf::Object;
+	use test_log::test;
+
+	/// A PDF with one 200 by 100 point page that is black on the left half.
+	fn pdf() -> Vec<u8> {
+		let content = "0 0 0 rg 0 0 100 100 re f";
+		let objects = [
+			"<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+			"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_owned(),
+			"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 100] /Contents 4 0 R >>".to_owned(),
+			format!("<< /Length {} >>\nstream\n{content}\nendstream", content.len()),
+		];
+		let mut pdf = b"%PDF-1.4\n".to_vec();
+		let mut offsets = Vec::new();
+		for (i, object) in objects.iter().enumerate() {
+			offsets.push(pdf.len());
+			pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+		}
+		let xref = pdf.len();
+		pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+		for offset in offsets {
+			pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+		}
+		pdf.extend_from_slice(
+			format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n", objects.len() + 1).as_bytes(),
+		);
+		pdf
+	}
+
+	#[test]
+	#[cfg_attr(not(feature = "static-pdfium"), ignore = "needs the Pdfium library")]
+	fn render() {
+		let pdf = PdfPages::new(pdf()).unwrap();
+		assert_eq!(pdf.len(), 1);
+		assert_eq!(pdf.size(0), Some([200.0, 100.0]));
+
+		let page = pdf.render(0, 400).unwrap();
+		assert_eq!(page.dimensions(), (400, 200));
+		assert_eq!(page.to_rgba8().get_pixel(10, 100).0, [0, 0, 0, 255]);
+		assert_eq!(page.to_rgba8().get_pixel(390, 100).0, [255, 255, 255, 255]);
+		assert!(pdf.render(1, 400).is_err());
+	}
+
+	/// A piece of two 40 by 60 pixel pages, with `layers` annotating the first.
+	fn annotated(layers: Vec<(&str, AnnotationFormat, ImageState)>) -> Piece {
+		let page = || ImageState::Decoded(DynamicImage::ImageRgba8(RgbaImage::new(40, 60)));
+		let (layers, annotations) = layers
+			.into_iter()
+			.map(|(name, format, file)| {
+				let files = vec![Some(format!("{name}-1").into()), None];
+				(AnnotationLayer { name: name.into(), format, files }, vec![Some(file), None])
+			})
+			.unzip();
+		Piece {
+			meta: Meta {
+				format: PageFormat::Images { files: vec!["page-1.png".into(), "page-2.png".into()] },
+				annotations: AnnotationFormat::Strokes,
+				layers,
+				information: Information {
+					title: "Gavotte".into(),
+					creators: vec!["Gossec, François-Joseph [composer]".into()],
+					description: Some("From the Suzuki books".into()),
+					subjects: vec!["Violin".into(), "Baroque".into()],
+					copyright: Some("Public domain".into()),
+					..Information::default()
+				},
+				reading_order: Vec::new(),
+				measures: Vec::new(),
+				bookmarks: Vec::new(),
+				musicreader: None,
+			},
+			pages: Pages::Images(vec![page(), page()]),
+			annotations,
+		}
+	}
+
+	/// Entry `key` of the document information, which is either UTF-16 or PDFDocEncoding. The
+	/// latter matches Latin-1 for the text used here.
+	fn info(doc: &lopdf::Document, key: &[u8]) -> String {
+		let info = doc.trailer.get(b"Info").and_then(Object::as_reference).and_then(|id| doc.get_dictionary(id));
+		let text = info.unwrap().get(key).and_then(Object::as_str).unwrap();
+		match text.strip_prefix(&[0xfe, 0xff]) {
+			Some(utf16) => {
+				String::from_utf16(&utf16.chunks(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>())
+					.unwrap()
+			}
+			None => text.iter().map(|&b| b as char).collect(),
+		}
+	}
+
+	/// The media box of each page of `doc`, which may be inherited from the page tree.
+	fn media_boxes(doc: &lopdf::Document) -> Vec<[f32; 4]> {
+		doc.get_pages()
+			.into_values()
+			.map(|page| {
+				let mut node = doc.get_dictionary(page).unwrap();
+				let media_box = loop {
+					match node.get(b"MediaBox") {
+						Ok(media_box) => break media_box.as_array().unwrap(),
+						Err(_) => {
+							let parent = node.get(b"Parent").and_then(Object::as_reference).unwrap();
+							node = doc.get_dictionary(parent).unwrap();
+						}
+					}
+				};
+				let corners = media_box.iter().map(|v| v.as_float().unwrap()).collect::<Vec<_>>();
+				corners.try_into().unwrap()
+			})
+			.collect()
+	}
+
+	/// How many images each page of `doc` draws as XObjects.
+	fn page_images(doc: &lopdf::Document) -> Vec<usize> {
+		let resolve = |object: &Object| match object {
+			Object::Reference(id) => doc.get_object(*id).unwrap().clone(),
+			object => object.clone(),
+		};
+		doc.get_pages()
+			.into_values()
+			.map(|page| {
+				let (resources, inherited) = doc.get_page_resources(page);
+				let inherited = inherited.into_iter().map(|id| doc.get_dictionary(id).unwrap());
+				resources
+					.into_iter()
+					.chain(inherited)
+					.filter_map(|resources| resources.get(b"XObject").ok().map(resolve))
+					.flat_map(|xobjects| {
+						xobjects.as_dict().unwrap().iter().map(|(_, xobject)| resolve(xobject)).collect::<Vec<_>>()
+					})
+					.filter(|xobject| {
+						let dict = &xobject.as_stream().unwrap().dict;
+						dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(&b"Image"[..])
+					})
+					.count()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn export_annotated() {
+		let stroke = Stroke {
+			tool: Tool::Highlighter,
+			color: [255, 220, 0, 128],
+			width: 4.0,
+			started: 0,
+			samples: vec![
+				StrokeSample { x: 2.0, y: 2.0, pressure: 1.0, time: 0 },
+				StrokeSample { x: 30.0, y: 40.0, pressure: 0.5, time: 10 },
+			],
+		};
+		let mut scan = RgbaImage::new(40, 60);
+		scan.put_pixel(3, 4, image::Rgba([255, 0, 0, 255]));
+		let scan = || ("scan", AnnotationFormat::Image, ImageState::Decoded(DynamicImage::ImageRgba8(scan.clone())));
+		let pen = || {
+			let strokes = Strokes { strokes: vec![stroke.clone()] };
+			("pen", AnnotationFormat::Strokes, ImageState::Encoded(strokes.to_bytes()))
+		};
+		let broken = || ("broken", AnnotationFormat::Strokes, ImageState::Encoded(vec![0xff]));
+		let piece = annotated(vec![scan(), pen(), broken()]);
+
+		let pdf = export(&piece, &[0.5]).unwrap();
+		let doc = lopdf::Document::load_mem(&pdf).unwrap();
+		assert_eq!(doc.get_pages().len(), 2);
+		// Pages are EXPORT_WIDTH wide, with the aspect ratio of the page images.
+		assert_eq!(media_boxes(&doc), [[0.0, 0.0, EXPORT_WIDTH, EXPORT_WIDTH * 1.5]; 2]);
+		assert!(page_images(&doc).iter().all(|&images| images > 0), "{:?}", page_images(&doc));
+		assert_eq!(info(&doc, b"Title"), "Gavotte");
+		assert_eq!(info(&doc, b"Author"), "Gossec, François-Joseph [composer]");
+		assert_eq!(info(&doc, b"Subject"), "From the Suzuki books\nPublic domain");
+		assert_eq!(info(&doc, b"Keywords"), "Violin, Baroque");
+		assert_eq!(info(&doc, b"Creator"), "See Augmented");
+
+		// The scan is drawn on the first page as an image of its own, unless its opacity is 0.
+		let images = |piece: &Piece, opacity: &[f32]| {
+			page_images(&lopdf::Document::load_mem(&export(piece, opacity).unwrap()).unwrap())
+		};
+		let without = images(&annotated(vec![pen()]), &[]);
+		assert!(images(&piece, &[0.5])[0] > without[0]);
+		assert_eq!(images(&piece, &[0.0]), without);
+	}
+}
@@ -0,0 +1,404 @@
+//! Importing pages that are plain images: a multi-page TIFF, a zip or CBZ archive of images, or
+//! a directory of images.
+//!
+//! Every importer builds the same kind of [`Piece`]: pages in [`PageFormat::Images`] named
+//! `page-1.png`, `page-2.jpg` and so on, image annotations with no layers, and the name of the
+//! file or directory as the title. Archives and directories are read in natural order, so
+//! `page-2.png` comes before `page-10.png`.
+
+use crate::{AnnotationFormat, ImageState, Information, Meta, PageFormat, Pages, Piece, PieceError};
+use async_zip::tokio::read::fs::ZipFileReader;
+use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, ImageFormat, RgbImage, RgbaImage};
+use log::warn;
+use std::{
+	cmp::Ordering,
+	fmt::{Display, Formatter},
+	io::Cursor,
+	path::{Path, PathBuf},
+};
+use tiff::{
+	decoder::{Decoder, DecodingResult},
+	TiffError, TiffUnsupportedError,
+};
+
+#[derive(Debug)]
+pub enum ImportError {
+	Io(std::io::Error),
+	Zip(async_zip::error::ZipError),
+	Tiff(TiffError),
+	/// A TIFF page couldn't be encoded as PNG.
+	Image(PieceError),
+	/// There are no images to import.
+	NoPages,
+	/// The path is not a TIFF, a zip or CBZ archive, or a directory.
+	UnknownFormat(PathBuf),
+}
+
+impl Display for ImportError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => e.fmt(f),
+			Self::Zip(e) => e.fmt(f),
+			Self::Tiff(e) => e.fmt(f),
+			Self::Image(e) => e.fmt(f),
+			Self::NoPages => f.write_str("no page images found"),
+			Self::UnknownFormat(path) => {
+				write!(f, "`{}` is not a TIFF, a zip or CBZ archive, or a directory", path.display())
+			}
+		}
+	}
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<std::io::Error> for ImportError {
+	fn from(e: std::io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl From<async_zip::error::ZipError> for ImportError {
+	fn from(e: async_zip::error::ZipError) -> Self {
+		Self::Zip(e)
+	}
+}
+
+impl From<TiffError> for ImportError {
+	fn from(e: TiffError) -> Self {
+		Self::Tiff(e)
+	}
+}
+
+/// Import `path` with whichever importer suits it: [`import_dir`] for a directory,
+/// [`import_tiff`] for a `.tif` or `.tiff` file, and [`import_zip`] for a `.zip` or `.cbz` file.
+///
+/// TIFF pages are decoded and encoded again as PNG on a blocking thread, which can take a while
+/// for long scans.
+pub async fn import(path: impl AsRef<Path>) -> Result<Piece, ImportError> {
+	let path = path.as_ref();
+	if tokio::fs::metadata(path).await?.is_dir() {
+		return import_dir(path).await;
+	}
+	let extension = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+	match &*extension {
+		"tif" | "tiff" => {
+			let data = tokio::fs::read(path).await?;
+			let title = title(path);
+			blocking(move || import_tiff(&data, title)).await
+		}
+		"zip" | "cbz" => import_zip(path).await,
+		_ => Err(ImportError::UnknownFormat(path.to_owned())),
+	}
+}
+
+/// Split a multi-page TIFF into pages, each encoded as PNG. `title` is used as the piece's title.
+///
+/// Every page is decoded, so this can take a while; avoid calling it on an async or UI thread.
+pub fn import_tiff(data: &[u8], title: impl Into<String>) -> Result<Piece, ImportError> {
+	let mut pages = PageBuilder::default();
+	pages.push_tiff(data)?;
+	pages.finish_titled(title.into())
+}
+
+/// Import every image in a zip or CBZ archive, including those in folders inside it. Images are
+/// kept as they are stored, except for TIFFs, which are split into their pages.
+pub async fn import_zip(path: impl AsRef<Path>) -> Result<Piece, ImportError> {
+	let path = path.as_ref();
+	let reader = ZipFileReader::new(path).await?;
+	let mut entries = Vec::new();
+	for (i, entry) in reader.file().entries().iter().enumerate() {
+		let name = String::from_utf8_lossy(entry.filename().as_bytes()).into_owned();
+		if entry.dir()? {
+			continue;
+		}
+		if let Some(format) = page_format(Path::new(&name)) {
+			entries.push((name, format, i));
+		}
+	}
+	entries.sort_by(|(a, ..), (b, ..)| natural_cmp(a, b));
+
+	let mut pages = PageBuilder::default();
+	for (_, format, i) in entries {
+		let mut data = Vec::new();
+		reader.reader_with_entry(i).await?.read_to_end_checked(&mut data).await?;
+		pages.push_data(data, format)?;
+	}
+	pages.finish(path)
+}
+
+/// Import every image directly inside a directory. Images are read from where they are when they
+/// are needed, except for TIFFs, which are split into their pages right away on a blocking thread.
+pub async fn import_dir(path: impl AsRef<Path>) -> Result<Piece, ImportError> {
+	let path = path.as_ref();
+	let mut files = Vec::new();
+	let mut dir = tokio::fs::read_dir(path).await?;
+	while let Some(entry) = dir.next_entry().await? {
+		let file = entry.path();
+		if !entry.file_type().await?.is_file() {
+			continue;
+		}
+		if let Some(format) = page_format(&file) {
+			files.push((entry.file_name().to_string_lossy().into_owned(), format, file));
+		}
+	}
+	files.sort_by(|(a, ..), (b, ..)| natural_cmp(a, b));
+
+	let mut pages = PageBuilder::default();
+	for (_, format, file) in files {
+		if format == ImageFormat::Tiff {
+			let data = tokio::fs::read(&file).await?;
+			pages = blocking(move || pages.push_tiff(&data).map(|()| pages)).await?;
+		} else {
+			pages.push(ImageState::Path(file), format);
+		}
+	}
+	pages.finish(path)
+}
+
+/// Run `f`, which decodes images, on a thread where blocking is fine.
+async fn blocking<T: Send + 'static>(
+	f: impl FnOnce() -> Result<T, ImportError> + Send + 'static,
+) -> Result<T, ImportError> {
+	tokio::task::spawn_blocking(f).await.map_err(std::io::Error::from)?
+}
+
+/// The name of the file or directory at `path`, without its extension, as the title of a piece.
+fn title(path: &Path) -> String {
+	path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
+/// The format of the image at `path` if it looks like a page, going by its extension. Hidden
+/// files, and anything in a hidden folder such as the `__MACOSX` folder that macOS adds to
+/// archives, are skipped.
+fn page_format(path: &Path) -> Option<ImageFormat> {
+	let hidden = path.iter().any(|part| {
+		let part = part.to_string_lossy();
+		part.starts_with('.') || part == "__MACOSX"
+	});
+	if hidden {
+		return None;
+	}
+	ImageFormat::from_path(path).ok().filter(ImageFormat::can_read)
+}
+
+/// Collects pages in order and names the files they will be saved as.
+#[derive(Default)]
+struct PageBuilder {
+	files: Vec<PathBuf>,
+	images: Vec<ImageState>,
+}
+
+impl PageBuilder {
+	fn push(&mut self, image: ImageState, format: ImageFormat) {
+		let number = self.images.len() + 1;
+		let extension = format.extensions_str().first().copied().unwrap_or("img");
+		self.files.push(PathBuf::from(format!("page-{number}.{extension}")));
+		self.images.push(image);
+	}
+
+	fn push_data(&mut self, data: Vec<u8>, format: ImageFormat) -> Result<(), ImportError> {
+		if format == ImageFormat::Tiff {
+			self.push_tiff(&data)
+		} else {
+			self.push(ImageState::Encoded(data), format);
+			Ok(())
+		}
+	}
+
+	/// Add each page of a TIFF, encoded as PNG.
+	fn push_tiff(&mut self, data: &[u8]) -> Result<(), ImportError> {
+		let mut decoder = Decoder::new(Cursor::new(data))?;
+		loop {
+			let page = ImageState::Decoded(tiff_page(&mut decoder)?);
+			let png = page.to_encoded().map_err(ImportError::Image)?.into_owned();
+			self.push(ImageState::Encoded(png), ImageFormat::Png);
+			if !decoder.more_images() {
+				return Ok(());
+			}
+			decoder.next_image()?;
+		}
+	}
+
+	/// Finish with the name of the file or directory the pages came from as the title.
+	fn finish(self, path: &Path) -> Result<Piece, ImportError> {
+		self.finish_titled(title(path))
+	}
+
+	fn finish_titled(self, title: String) -> Result<Piece, ImportError> {
+		if self.images.is_empty() {
+			return Err(ImportError::NoPages);
+		}
+		let meta = Meta {
+			format: PageFormat::Images { files: self.files },
+			annotations: AnnotationFormat::Image,
+			layers: Vec::new(),
+			information: Information { title, ..Information::default() },
+			reading_order: Vec::new(),
+			measures: Vec::new(),
+			bookmarks: Vec::new(),
+			musicreader: None,
+		};
+		Ok(Piece { meta, pages: Pages::Images(self.images), annotations: Vec::new() })
+	}
+}
+
+/// Decode the page `decoder` is on.
+fn tiff_page(decoder: &mut Decoder<Cursor<&[u8]>>) -> Result<DynamicImage, TiffError> {
+	use tiff::ColorType::*;
+
+	let (w, h) = decoder.dimensions()?;
+	let color = decoder.colortype()?;
+	let unsupported = || TiffError::UnsupportedError(TiffUnsupportedError::UnsupportedColorType(color));
+	let image = match (color, decoder.read_image()?) {
+		// Bilevel scans are packed eight pixels to a byte, with each row starting on a new byte.
+		(Gray(1), DecodingResult::U8(data)) => {
+			let stride = (w as usize).div_ceil(8);
+			if data.len() < stride * h as usize {
+				warn!("bilevel TIFF page has {} bytes, expected {}", data.len(), stride * h as usize);
+				return Err(unsupported());
+			}
+			DynamicImage::ImageLuma8(GrayImage::from_fn(w, h, |x, y| {
+				let byte = data[y as usize * stride + x as usize / 8];
+				image::Luma([if byte & (0x80 >> (x % 8)) != 0 { 255 } else { 0 }])
+			}))
+		}
+		(Gray(8), DecodingResult::U8(data)) => {
+			GrayImage::from_raw(w, h, data).map(DynamicImage::ImageLuma8).ok_or_else(unsupported)?
+		}
+		(GrayA(8), DecodingResult::U8(data)) => {
+			GrayAlphaImage::from_raw(w, h, data).map(DynamicImage::ImageLumaA8).ok_or_else(unsupported)?
+		}
+		(RGB(8), DecodingResult::U8(data)) => {
+			RgbImage::from_raw(w, h, data).map(DynamicImage::ImageRgb8).ok_or_else(unsupported)?
+		}
+		(RGBA(8), DecodingResult::U8(data)) => {
+			RgbaImage::from_raw(w, h, data).map(DynamicImage::ImageRgba8).ok_or_else(unsupported)?
+		}
+		(Gray(16), DecodingResult::U16(data)) => {
+			ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageLuma16).ok_or_else(unsupported)?
+		}
+		(RGB(16), DecodingResult::U16(data)) => {
+			ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgb16).ok_or_else(unsupported)?
+		}
+		(RGBA(16), DecodingResult::U16(data)) => {
+			ImageBuffer::from_raw(w, h, data).map(DynamicImage::ImageRgba16).ok_or_else(unsupported)?
+		}
+		_ => return Err(unsupported()),
+	};
+	Ok(image)
+}
+
+/// Compare file names so that runs of digits are ordered by their value, and letters regardless
+/// of case: `page-2.png` comes before `page-10.png`, and `B` after `a`. Names that only differ in
+/// case or leading zeros are then ordered as they are spelled, so that the order is always the
+/// same.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+	let (mut x, mut y) = (a, b);
+	loop {
+		let (Some(cx), Some(cy)) = (x.chars().next(), y.chars().next()) else {
+			return x.len().cmp(&y.len()).then_with(|| a.cmp(b));
+		};
+		let order = if cx.is_ascii_digit() && cy.is_ascii_digit() {
+			let (dx, rx) = x.split_at(x.find(|c: char| !c.is_ascii_digit()).unwrap_or(x.len()));
+			let (dy, ry) = y.split_at(y.find(|c: char| !c.is_ascii_digit()).unwrap_or(y.len()));
+			(x, y) = (rx, ry);
+			let (dx, dy) = (dx.trim_start_matches('0'), dy.trim_start_matches('0'));
+			dx.len().cmp(&dy.len()).then_with(|| dx.cmp(dy))
+		} else {
+			(x, y) = (&x[cx.len_utf8()..], &y[cy.len_utf8()..]);
+			cx.to_lowercase().cmp(cy.to_lowercase())
+		};
+		if order != Ordering::Equal {
+			return order;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::temp_dir;
+	use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+	use test_log::test;
+	use tiff::encoder::{colortype, TiffEncoder};
+
+	fn png(width: u32) -> Vec<u8> {
+		let img = DynamicImage::ImageRgba8(RgbaImage::new(width, 1));
+		ImageState::Decoded(img).to_encoded().unwrap().into_owned()
+	}
+
+	fn widths(piece: &Piece) -> Vec<u32> {
+		let Pages::Images(images) = &piece.pages else { panic!("expected images") };
+		images
+			.iter()
+			.map(|image| image::load_from_memory(&image.to_encoded().unwrap()).unwrap().width())
+			.collect()
+	}
+
+	fn files(piece: &Piece) -> Vec<String> {
+		let PageFormat::Images { files } = &piece.meta.format else { panic!("expected images") };
+		files.iter().map(|file| file.display().to_string()).collect()
+	}
+
+	#[test]
+	fn natural_order() {
+		let mut names = vec!["page-10.png", "Page-2.png", "page-1.png", "page-01.png", "cover.png", "page-2b.png"];
+		names.sort_by(|a, b| natural_cmp(a, b));
+		assert_eq!(names, ["cover.png", "page-01.png", "page-1.png", "Page-2.png", "page-2b.png", "page-10.png"]);
+	}
+
+	#[test]
+	fn tiff_pages() {
+		let mut data = Cursor::new(Vec::new());
+		let mut encoder = TiffEncoder::new(&mut data).unwrap();
+		encoder.write_image::<colortype::RGB8>(2, 1, &[255, 0, 0, 0, 0, 255]).unwrap();
+		encoder.write_image::<colortype::Gray8>(3, 1, &[0, 128, 255]).unwrap();
+		let data = data.into_inner();
+
+		let piece = import_tiff(&data, "Scan").unwrap();
+		assert_eq!(piece.meta.information.title, "Scan");
+		assert_eq!(files(&piece), ["page-1.png", "page-2.png"]);
+		assert_eq!(widths(&piece), [2, 3]);
+		assert!(matches!(import_tiff(b"not a tiff", "Scan"), Err(ImportError::Tiff(_))));
+	}
+
+	#[test(tokio::test)]
+	async fn zip_and_dir() {
+		let dir = temp_dir("scans");
+		let pages = dir.join("Etude");
+		std::fs::create_dir_all(&pages).unwrap();
+		for width in [1, 2, 10] {
+			std::fs::write(pages.join(format!("page-{width}.png")), png(width)).unwrap();
+		}
+		std::fs::write(pages.join("notes.txt"), "not a page").unwrap();
+		std::fs::write(pages.join(".hidden.png"), png(5)).unwrap();
+
+		let piece = import(&pages).await.unwrap();
+		assert_eq!(piece.meta.information.title, "Etude");
+		assert_eq!(widths(&piece), [1, 2, 10]);
+
+		let cbz = dir.join("Etude.cbz");
+		let mut writer = ZipFileWriter::with_tokio(tokio::fs::File::create(&cbz).await.unwrap());
+		for (name, data) in [
+			("b/page-10.png", png(10)),
+			("b/page-2.png", png(2)),
+			("a/page-3.png", png(3)),
+			("__MACOSX/b/._page-2.png", png(4)),
+		] {
+			let entry = ZipEntryBuilder::new(name.to_owned().into(), Compression::Stored);
+			writer.write_entry_whole(entry, &data).await.unwrap();
+		}
+		writer.close().await.unwrap();
+
+		let piece = import(&cbz).await.unwrap();
+		assert_eq!(piece.meta.information.title, "Etude");
+		assert_eq!(files(&piece), ["page-1.png", "page-2.png", "page-3.png"]);
+		assert_eq!(widths(&piece), [3, 2, 10]);
+
+		let empty = dir.join("empty");
+		std::fs::create_dir_all(&empty).unwrap();
+		assert!(matches!(import(&empty).await, Err(ImportError::NoPages)));
+		assert!(matches!(import(pages.join("notes.txt")).await, Err(ImportError::UnknownFormat(_))));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,282 @@
+//! Finding pieces in a [`Library`] by what they are: their title, creators, subjects, tags,
+//! bookmarks and description.
+//!
+//! Matching ignores case and accents, accepts the start of a word so that results can be shown
+//! while typing, and tolerates a typo or two in longer words, which covers the many spellings of
+//! composers' names ("Tchaikovsky", "Chaikovsky", "Tschaikowsky").
+
+use crate::{
+	catalog::{Entry, Library},
+	scans::natural_cmp,
+};
+use std::cmp::Ordering;
+
+/// How much a match in each part of a piece's metadata counts towards its rank.
+const TITLE_WEIGHT: f32 = 10.0;
+const CREATOR_WEIGHT: f32 = 8.0;
+const TAG_WEIGHT: f32 = 6.0;
+const SUBJECT_WEIGHT: f32 = 4.0;
+const BOOKMARK_WEIGHT: f32 = 3.0;
+const DESCRIPTION_WEIGHT: f32 = 1.0;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Query {
+	/// Words to look for. Every word must match somewhere in a piece for it to be found.
+	pub text: String,
+	/// Only find pieces with a creator (usually the composer) matching this.
+	pub creator: Option<String>,
+	/// Only find pieces with a subject matching this.
+	pub subject: Option<String>,
+}
+
+impl Query {
+	/// Read a query as it was typed, where `composer:`, `creator:` or `by:` followed by a word
+	/// filters by creator, and `subject:` followed by a word filters by subject.
+	pub fn parse(input: &str) -> Self {
+		let mut query = Self::default();
+		let mut text = Vec::new();
+		for word in input.split_whitespace() {
+			let filter = word.split_once(':').filter(|(_, value)| !value.is_empty());
+			match filter {
+				Some(("composer" | "creator" | "by", value)) => query.creator = Some(value.into()),
+				Some(("subject", value)) => query.subject = Some(value.into()),
+				_ => text.push(word),
+			}
+		}
+		query.text = text.join(" ");
+		query
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.text.trim().is_empty() && self.creator.is_none() && self.subject.is_none()
+	}
+}
+
+/// A piece that matches a [`Query`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit<'a> {
+	pub entry: &'a Entry,
+	/// How well the piece matches; higher is better.
+	pub score: f32,
+}
+
+impl Library {
+	/// Every piece matching `query`, best matches first. See [`search`].
+	pub fn search(&self, query: &Query) -> Vec<Hit<'_>> {
+		search(self.entries(), query)
+	}
+}
+
+/// Every entry matching `query`, best matches first. Matches in the title count the most, then
+/// creators, tags, subjects, bookmarks and the description. An empty query finds everything, in
+/// order of title.
+pub fn search<'a>(entries: impl IntoIterator<Item = &'a Entry>, query: &Query) -> Vec<Hit<'a>> {
+	let terms = words(&query.text).collect::<Vec<_>>();
+	let creator = query.creator.as_deref().map(|creator| words(creator).collect::<Vec<_>>());
+	let subject = query.subject.as_deref().map(|subject| words(subject).collect::<Vec<_>>());
+
+	let mut hits = entries
+		.into_iter()
+		.filter(|entry| {
+			let info = &entry.information;
+			let creator = creator.as_ref().is_none_or(|filter| info.creators.iter().any(|c| matches_all(filter, c)));
+			let subject = subject.as_ref().is_none_or(|filter| info.subjects.iter().any(|s| matches_all(filter, s)));
+			creator && subject
+		})
+		.filter_map(|entry| Some(Hit { entry, score: score(entry, &terms)? }))
+		.collect::<Vec<_>>();
+	hits.sort_by(|a, b| {
+		b.score
+			.partial_cmp(&a.score)
+			.unwrap_or(Ordering::Equal)
+			.then_with(|| natural_cmp(&a.entry.information.title, &b.entry.information.title))
+			.then_with(|| a.entry.path.cmp(&b.entry.path))
+	});
+	hits
+}
+
+/// The sum of how well each of `terms` matches `entry`, or `None` if any of them doesn't.
+fn score(entry: &Entry, terms: &[String]) -> Option<f32> {
+	let info = &entry.information;
+	let fields = [
+		(TITLE_WEIGHT, vec![info.title.as_str()]),
+		(CREATOR_WEIGHT, info.creators.iter().map(String::as_str).collect()),
+		(TAG_WEIGHT, entry.tags.iter().map(String::as_str).collect()),
+		(SUBJECT_WEIGHT, info.subjects.iter().map(String::as_str).collect()),
+		(BOOKMARK_WEIGHT, entry.bookmarks.iter().map(String::as_str).collect()),
+		(DESCRIPTION_WEIGHT, info.description.as_deref().into_iter().collect()),
+	];
+	let fields = fields
+		.into_iter()
+		.map(|(weight, texts)| (weight, texts.into_iter().flat_map(words).collect::<Vec<_>>()))
+		.collect::<Vec<_>>();
+
+	terms.iter().try_fold(0.0, |total, term| {
+		let best = fields
+			.iter()
+			.flat_map(|(weight, words)| words.iter().map(move |word| weight * quality(term, word)))
+			.fold(0.0, f32::max);
+		(best > 0.0).then_some(total + best)
+	})
+}
+
+/// Whether every one of `terms` matches some word of `text`.
+fn matches_all(terms: &[String], text: &str) -> bool {
+	let text = words(text).collect::<Vec<_>>();
+	terms.iter().all(|term| text.iter().any(|word| quality(term, word) > 0.0))
+}
+
+/// How well a query term matches a word, from 0 (not at all) to 1 (exactly).
+fn quality(term: &str, word: &str) -> f32 {
+	if term == word {
+		return 1.0;
+	}
+	let len = term.chars().count();
+	if len >= 2 && word.starts_with(term) {
+		return 0.8;
+	}
+	let allowed = match len {
+		0..=3 => 0,
+		4..=7 => 1,
+		_ => 2,
+	};
+	match typos(term, word, allowed) {
+		Some(0) => 1.0,
+		Some(typos) => 0.7 - 0.1 * typos as f32,
+		None => 0.0,
+	}
+}
+
+/// The number of letters that were added, left out, changed or swapped to get from `a` to `b`,
+/// if it is no more than `max`.
+fn typos(a: &str, b: &str, max: usize) -> Option<usize> {
+	let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+	if a.len().abs_diff(b.len()) > max {
+		return None;
+	}
+	// Optimal string alignment distance, keeping the last two rows.
+	let mut before = vec![0; b.len() + 1];
+	let mut previous = (0..=b.len()).collect::<Vec<_>>();
+	let mut current = vec![0; b.len() + 1];
+	for i in 1..=a.len() {
+		current[0] = i;
+		for j in 1..=b.len() {
+			let cost = usize::from(a[i - 1] != b[j - 1]);
+			current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+			if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+				current[j] = current[j].min(before[j - 2] + 1);
+			}
+		}
+		if current.iter().min().is_some_and(|&min| min > max) {
+			return None;
+		}
+		std::mem::swap(&mut before, &mut previous);
+		std::mem::swap(&mut previous, &mut current);
+	}
+	Some(previous[b.len()]).filter(|&typos| typos <= max)
+}
+
+/// The words of `text`, in lowercase and without accents.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+	text.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(|word| word.chars().flat_map(char::to_lowercase).map(unaccented).collect::<String>())
+		.map(|word| word.replace('ß', "ss"))
+}
+
+/// `c` without any accent, for the accented letters that are common in the names of composers.
+fn unaccented(c: char) -> char {
+	match c {
+		'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+		'ç' | 'ć' | 'č' => 'c',
+		'ď' | 'đ' => 'd',
+		'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ė' | 'ę' | 'ě' => 'e',
+		'ğ' => 'g',
+		'ì' | 'í' | 'î' | 'ï' | 'ī' | 'ı' => 'i',
+		'ł' | 'ľ' => 'l',
+		'ñ' | 'ń' | 'ň' => 'n',
+		'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ő' => 'o',
+		'ř' => 'r',
+		'ś' | 'š' | 'ş' => 's',
+		'ť' | 'ţ' => 't',
+		'ù' | 'ú' | 'û' | 'ü' | 'ū' | 'ů' | 'ű' => 'u',
+		'ý' | 'ÿ' => 'y',
+		'ź' | 'ż' | 'ž' => 'z',
+		c => c,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{catalog::PieceKind, Information};
+	use std::time::SystemTime;
+	use test_log::test;
+
+	fn entry(title: &str, creator: &str, subjects: &[&str], bookmarks: &[&str]) -> Entry {
+		Entry {
+			path: format!("{title}.mrs").into(),
+			kind: PieceKind::MusicReader,
+			size: 0,
+			modified: SystemTime::UNIX_EPOCH,
+			information: Information {
+				title: title.into(),
+				creators: vec![creator.into()],
+				subjects: subjects.iter().map(|&s| s.into()).collect(),
+				..Information::default()
+			},
+			bookmarks: bookmarks.iter().map(|&b| b.into()).collect(),
+			pages: 1,
+			tags: Vec::new(),
+		}
+	}
+
+	fn entries() -> Vec<Entry> {
+		vec![
+			entry("Symphony No. 6", "Tchaikovsky, Pyotr Ilyich [composer]", &["Symphony"], &["Finale"]),
+			entry("Slavonic Dances", "Dvořák, Antonín [composer]", &["Dance"], &[]),
+			entry("The Nutcracker", "Tchaikovsky, Pyotr Ilyich [composer]", &["Ballet", "Dance"], &[]),
+			entry("Finale from Symphony No. 9", "Dvořák, Antonín [composer]", &["Symphony"], &[]),
+			entry("Waltz", "Strauss, Johann [composer]", &["Dance"], &["Trio"]),
+		]
+	}
+
+	fn titles(hits: Vec<Hit<'_>>) -> Vec<&str> {
+		hits.into_iter().map(|hit| hit.entry.information.title.as_str()).collect()
+	}
+
+	#[test]
+	fn fuzzy_and_ranked() {
+		let entries = entries();
+		let find = |text: &str| titles(search(&entries, &Query::parse(text)));
+		assert_eq!(find("chaikovsky"), ["Symphony No. 6", "The Nutcracker"]);
+		assert_eq!(find("Tschaikowsky nutcracker"), ["The Nutcracker"]);
+		assert_eq!(find("dvorak"), ["Finale from Symphony No. 9", "Slavonic Dances"]);
+		assert_eq!(find("nutc"), ["The Nutcracker"]);
+		// A title match ranks above a bookmark.
+		assert_eq!(find("finale"), ["Finale from Symphony No. 9", "Symphony No. 6"]);
+		assert!(find("mahler").is_empty());
+		assert_eq!(find("").len(), entries.len());
+	}
+
+	#[test]
+	fn filters() {
+		let entries = entries();
+		let find = |text: &str| titles(search(&entries, &Query::parse(text)));
+		assert_eq!(
+			Query::parse("composer:dvorak  finale"),
+			Query { text: "finale".into(), creator: Some("dvorak".into()), subject: None }
+		);
+		assert_eq!(find("composer:dvorak finale"), ["Finale from Symphony No. 9"]);
+		assert_eq!(find("subject:dance"), ["Slavonic Dances", "The Nutcracker", "Waltz"]);
+		assert_eq!(find("by:tchaikovsky subject:dance"), ["The Nutcracker"]);
+	}
+
+	#[test]
+	fn typo_counts() {
+		assert_eq!(typos("chaikovsky", "tchaikovsky", 2), Some(1));
+		assert_eq!(typos("tschaikowsky", "tchaikovsky", 2), Some(2));
+		assert_eq!(typos("mozrat", "mozart", 1), Some(1));
+		assert_eq!(typos("bach", "brahms", 2), None);
+	}
+}
@@ -0,0 +1,236 @@
+//! Setlists: pieces to be played one after another, such as at a concert, so that they can be
+//! shown as one continuous run of pages.
+//!
+//! Setlists are saved as RON. Pieces in the same folder as the setlist, or below it, are saved
+//! relative to it, so that a folder holding a setlist and its pieces can be moved or copied to
+//! another computer as a whole.
+
+use crate::atomic;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+	fmt::{Display, Formatter},
+	path::{Path, PathBuf},
+};
+
+/// File extension of saved setlists.
+pub const EXTENSION: &str = "setlist";
+/// Version of the format written by [`Setlist::save`].
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Setlist {
+	pub name: String,
+	pub entries: Vec<SetlistEntry>,
+	#[serde(default)]
+	pub notes: Option<String>,
+}
+
+/// One piece in a setlist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SetlistEntry {
+	/// Where the piece is saved
+	pub piece: PathBuf,
+	/// Name of the bookmark to start at, to play only part of the piece. The piece starts at the
+	/// top of the bookmarked page.
+	#[serde(default)]
+	pub start: Option<String>,
+	/// Name of the bookmark to stop at. The piece ends at the bottom of the bookmarked page.
+	#[serde(default)]
+	pub end: Option<String>,
+	/// Shown with the piece, such as who plays a solo or where the cuts are.
+	#[serde(default)]
+	pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SetlistFile {
+	version: u32,
+	setlist: Setlist,
+}
+
+impl SetlistEntry {
+	/// The whole of the piece at `piece`.
+	pub fn new(piece: impl Into<PathBuf>) -> Self {
+		Self { piece: piece.into(), start: None, end: None, notes: None }
+	}
+
+	/// The indices of the page images to show, in the order they are shown. `order` holds the
+	/// indices of all of the piece's page images in the order they are played, such as from
+	/// [`pages_in_order`](mr_imp::Piece::pages_in_order), and `bookmark_page` finds the index of
+	/// the page image each bookmark is on. A page image that is played more than once starts the
+	/// piece where it is first played and ends it where it is last played. Bookmarks that can't be
+	/// found are ignored, which is logged, so that the whole piece is shown rather than none of it.
+	pub fn pages(&self, order: &[usize], bookmark_page: impl Fn(&str) -> Option<usize>) -> Vec<usize> {
+		let find = |name: &Option<String>, position: fn(&[usize], usize) -> Option<usize>| {
+			let name = name.as_deref()?;
+			let found = bookmark_page(name).and_then(|page| position(order, page));
+			if found.is_none() {
+				warn!("no bookmark `{name}` in `{}`", self.piece.display());
+			}
+			found
+		};
+		let start = find(&self.start, |order, page| order.iter().position(|&image| image == page)).unwrap_or(0);
+		let end = find(&self.end, |order, page| order.iter().rposition(|&image| image == page))
+			.map_or(order.len(), |position| position + 1);
+		if end <= start {
+			warn!("setlist entry for `{}` ends before it starts", self.piece.display());
+			return order[start..].to_vec();
+		}
+		order[start..end].to_vec()
+	}
+}
+
+impl Setlist {
+	pub fn new(name: impl Into<String>) -> Self {
+		Self { name: name.into(), ..Self::default() }
+	}
+
+	/// Read a setlist saved with [`save`](Self::save).
+	pub async fn load(path: impl AsRef<Path>) -> Result<Self, SetlistError> {
+		let path = path.as_ref();
+		let text = tokio::fs::read_to_string(path).await?;
+		#[derive(Deserialize)]
+		struct VersionOnly {
+			version: u32,
+		}
+		let VersionOnly { version } = ron::from_str(&text)?;
+		if version > VERSION {
+			return Err(SetlistError::TooNew { version });
+		}
+		let SetlistFile { setlist: mut this, .. } = ron::from_str(&text)?;
+		let dir = path.parent().unwrap_or(Path::new(""));
+		for entry in &mut this.entries {
+			if entry.piece.is_relative() {
+				entry.piece = dir.join(&entry.piece);
+			}
+		}
+		Ok(this)
+	}
+
+	/// Save the setlist to `path`, replacing whatever is there only once it has been completely
+	/// written.
+	pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), SetlistError> {
+		let path = path.as_ref();
+		let dir = path.parent().unwrap_or(Path::new(""));
+		let mut setlist = self.clone();
+		for entry in &mut setlist.entries {
+			if let Ok(relative) = entry.piece.strip_prefix(dir) {
+				entry.piece = relative.to_owned();
+			}
+		}
+		let file = SetlistFile { version: VERSION, setlist };
+		let text = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())?;
+		atomic::write(path, text.as_bytes()).await?;
+		Ok(())
+	}
+}
+
+#[derive(Debug)]
+pub enum SetlistError {
+	Io(std::io::Error),
+	Ron(ron::Error),
+	/// The setlist isn't valid RON, or doesn't hold what a setlist should.
+	Invalid(ron::error::SpannedError),
+	/// The setlist was saved by a newer version of See Augmented.
+	TooNew { version: u32 },
+}
+
+impl Display for SetlistError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Io(e) => e.fmt(f),
+			Self::Ron(e) => e.fmt(f),
+			Self::Invalid(e) => write!(f, "invalid setlist: {e}"),
+			Self::TooNew { version } => write!(
+				f,
+				"saved by a newer version of See Augmented (setlist version {version}, but only versions up to \
+				 {VERSION} are supported); please update to open it"
+			),
+		}
+	}
+}
+
+impl std::error::Error for SetlistError {}
+
+impl From<std::io::Error> for SetlistError {
+	fn from(e: std::io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl From<ron::Error> for SetlistError {
+	fn from(e: ron::Error) -> Self {
+		Self::Ron(e)
+	}
+}
+
+impl From<ron::error::SpannedError> for SetlistError {
+	fn from(e: ron::error::SpannedError) -> Self {
+		Self::Invalid(e)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_util::temp_dir;
+	use test_log::test;
+
+	#[test(tokio::test)]
+	async fn round_trip() {
+		let dir = temp_dir("setlist");
+		let elsewhere = std::env::temp_dir().join("elsewhere/march.mrs");
+		let setlist = Setlist {
+			name: "Spring concert".into(),
+			entries: vec![
+				SetlistEntry {
+					start: Some("Trio".into()),
+					notes: Some("Take the repeat".into()),
+					..SetlistEntry::new(dir.join("parts/waltz.saug"))
+				},
+				SetlistEntry::new(elsewhere),
+			],
+			notes: None,
+		};
+		let path = dir.join(format!("spring.{EXTENSION}"));
+		setlist.save(&path).await.unwrap();
+		let text = std::fs::read_to_string(&path).unwrap();
+		assert!(text.contains("\"parts/waltz.saug\""), "pieces next to the setlist are saved relative to it");
+		assert_eq!(Setlist::load(&path).await.unwrap(), setlist);
+
+		std::fs::write(&path, text.replacen("version: 1", "version: 2", 1)).unwrap();
+		assert!(matches!(Setlist::load(&path).await, Err(SetlistError::TooNew { version: 2 })));
+		std::fs::write(&path, "(version: 1,\n\tsetlist: 3)").unwrap();
+		let err = Setlist::load(&path).await.unwrap_err();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert!(matches!(&err, SetlistError::Invalid(e) if e.position.line == 2), "{err}");
+	}
+
+	#[test]
+	fn bookmarked_pages() {
+		let bookmarks = |name: &str| match name {
+			"Trio" => Some(3),
+			"Coda" => Some(5),
+			_ => None,
+		};
+		let entry = |start: Option<&str>, end: Option<&str>| SetlistEntry {
+			start: start.map(Into::into),
+			end: end.map(Into::into),
+			..SetlistEntry::new("piece.mrs")
+		};
+		let in_order = (0..8).collect::<Vec<_>>();
+		assert_eq!(entry(None, None).pages(&in_order, bookmarks), in_order);
+		assert_eq!(entry(Some("Trio"), None).pages(&in_order, bookmarks), [3, 4, 5, 6, 7]);
+		assert_eq!(entry(Some("Trio"), Some("Coda")).pages(&in_order, bookmarks), [3, 4, 5]);
+		assert_eq!(entry(Some("Missing"), Some("Trio")).pages(&in_order, bookmarks), [0, 1, 2, 3]);
+		assert_eq!(entry(Some("Coda"), Some("Trio")).pages(&in_order, bookmarks), [5, 6, 7]);
+
+		// The trio is played again after the coda's page, and the last image is a cover that
+		// comes first.
+		let reordered = [7, 0, 1, 2, 3, 4, 5, 3, 6];
+		assert_eq!(entry(Some("Trio"), None).pages(&reordered, bookmarks), [3, 4, 5, 3, 6]);
+		assert_eq!(entry(None, Some("Trio")).pages(&reordered, bookmarks), [7, 0, 1, 2, 3, 4, 5, 3]);
+		assert_eq!(entry(Some("Coda"), Some("Coda")).pages(&reordered, bookmarks), [5]);
+	}
+}
@@ -0,0 +1,382 @@
+//! Annotations drawn in See Augmented ([`AnnotationFormat::Strokes`](crate::AnnotationFormat)),
+//! kept as the pen strokes that made them so that they can be drawn again at any size.
+//!
+//! Strokes are saved in a compact binary format, and can also be written as RON to make them
+//! easier to inspect. [`Strokes::from_bytes`] reads either.
+//!
+//! The binary format is little-endian: [`MAGIC`], a version byte, and the number of strokes as a
+//! `u32`, then each stroke as its tool (`u8`), color (`[u8; 4]`), width (`f32`), start time
+//! (`u64`) and number of samples (`u32`), followed by that many samples of `x` and `y` (`f32`),
+//! pressure scaled to a `u16`, and time (`u32`).
+
+use crate::{AnnotationFormat, AnnotationLayer, ImageState, Piece};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+	fmt::{Display, Formatter},
+	path::PathBuf,
+};
+
+/// The first bytes of a binary strokes file.
+pub const MAGIC: [u8; 4] = *b"SAst";
+/// The version of the binary format written by [`Strokes::to_bytes`].
+pub const VERSION: u8 = 1;
+/// Name of the [annotation layer](crate::Meta::layers) that strokes drawn in See Augmented are
+/// saved in.
+pub const LAYER_NAME: &str = "See Augmented annotations";
+
+/// Every stroke drawn on one page, in the order they were drawn.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Strokes {
+	pub strokes: Vec<Stroke>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stroke {
+	pub tool: Tool,
+	/// `[red, green, blue, alpha]`
+	pub color: [u8; 4],
+	/// Width at full pressure, in page image pixels.
+	pub width: f32,
+	/// When the stroke was started, in milliseconds since the Unix epoch.
+	pub started: u64,
+	pub samples: Vec<StrokeSample>,
+}
+
+/// Where the pen was at one moment during a stroke.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StrokeSample {
+	/// Position in page image pixels
+	pub x: f32,
+	pub y: f32,
+	/// Pen pressure, from 0 to 1. Only 16 bits of precision are kept in the binary format.
+	pub pressure: f32,
+	/// Milliseconds since the stroke was started
+	pub time: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tool {
+	Pen,
+	/// Multiplied with the page under it, so that it colors the paper without hiding the notes.
+	Highlighter,
+}
+
+impl Tool {
+	fn to_byte(self) -> u8 {
+		match self {
+			Self::Pen => 0,
+			Self::Highlighter => 1,
+		}
+	}
+
+	fn from_byte(byte: u8) -> Option<Self> {
+		match byte {
+			0 => Some(Self::Pen),
+			1 => Some(Self::Highlighter),
+			_ => None,
+		}
+	}
+}
+
+impl Strokes {
+	/// Encode in the compact binary format.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let samples = self.strokes.iter().map(|stroke| stroke.samples.len()).sum::<usize>();
+		let mut buf = Vec::with_capacity(9 + self.strokes.len() * 21 + samples * 14);
+		buf.extend_from_slice(&MAGIC);
+		buf.push(VERSION);
+		buf.extend_from_slice(&(self.strokes.len() as u32).to_le_bytes());
+		for stroke in &self.strokes {
+			buf.push(stroke.tool.to_byte());
+			buf.extend_from_slice(&stroke.color);
+			buf.extend_from_slice(&stroke.width.to_le_bytes());
+			buf.extend_from_slice(&stroke.started.to_le_bytes());
+			buf.extend_from_slice(&(stroke.samples.len() as u32).to_le_bytes());
+			for sample in &stroke.samples {
+				buf.extend_from_slice(&sample.x.to_le_bytes());
+				buf.extend_from_slice(&sample.y.to_le_bytes());
+				let pressure = (sample.pressure.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16;
+				buf.extend_from_slice(&pressure.to_le_bytes());
+				buf.extend_from_slice(&sample.time.to_le_bytes());
+			}
+		}
+		buf
+	}
+
+	/// Encode as human-readable RON, for debugging.
+	pub fn to_ron(&self) -> Result<String, ron::Error> {
+		ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+	}
+
+	/// Decode either the binary format or RON.
+	pub fn from_bytes(data: &[u8]) -> Result<Self, StrokesError> {
+		let Some(data) = data.strip_prefix(&MAGIC) else {
+			let text = std::str::from_utf8(data).map_err(|_| StrokesError::NotStrokes)?;
+			return ron::from_str(text).map_err(StrokesError::Ron);
+		};
+		let mut reader = Reader(data);
+		let version = reader.u8()?;
+		if version != VERSION {
+			return Err(StrokesError::UnsupportedVersion(version));
+		}
+		let count = reader.u32()?;
+		// Don't trust the count enough to allocate for it up front.
+		let mut strokes = Vec::new();
+		for _ in 0..count {
+			let tool = reader.u8()?;
+			let tool = Tool::from_byte(tool).ok_or(StrokesError::UnknownTool(tool))?;
+			let color = reader.take()?;
+			let width = f32::from_le_bytes(reader.take()?);
+			let started = u64::from_le_bytes(reader.take()?);
+			let count = reader.u32()?;
+			let mut samples = Vec::new();
+			for _ in 0..count {
+				samples.push(StrokeSample {
+					x: f32::from_le_bytes(reader.take()?),
+					y: f32::from_le_bytes(reader.take()?),
+					pressure: u16::from_le_bytes(reader.take()?) as f32 / u16::MAX as f32,
+					time: reader.u32()?,
+				});
+			}
+			strokes.push(Stroke { tool, color, width, started, samples });
+		}
+		Ok(Self { strokes })
+	}
+}
+
+impl Piece {
+	/// Take the strokes drawn in See Augmented out of the piece, as the strokes of each page by
+	/// page index, so that they can be drawn and changed separately from the other layers. Pages
+	/// whose strokes can't be read have none, which is logged.
+	pub fn take_strokes(&mut self) -> Vec<Strokes> {
+		let mut pages = vec![Strokes::default(); self.pages.len()];
+		let Some(l) = self.strokes_layer() else { return pages };
+		self.meta.layers.remove(l);
+		let files = self.annotations.remove(l);
+		for (page, file) in files.into_iter().enumerate().take(pages.len()) {
+			let Some(file) = file else { continue };
+			let strokes = file.to_encoded().map_err(|e| e.to_string());
+			match strokes.and_then(|data| Strokes::from_bytes(&data).map_err(|e| e.to_string())) {
+				Ok(strokes) => pages[page] = strokes,
+				Err(e) => warn!("couldn't read the strokes on page {}: {e}", page + 1),
+			}
+		}
+		pages
+	}
+
+	/// Replace the strokes drawn in See Augmented with `pages`, the strokes of each page by page
+	/// index. Pages without any strokes get no file.
+	pub fn set_strokes(&mut self, pages: &[Strokes]) {
+		if let Some(l) = self.strokes_layer() {
+			self.meta.layers.remove(l);
+			self.annotations.remove(l);
+		}
+		if pages.iter().all(|page| page.strokes.is_empty()) {
+			return;
+		}
+		let (files, data) = (0..self.pages.len())
+			.map(|page| match pages.get(page).filter(|strokes| !strokes.strokes.is_empty()) {
+				Some(strokes) => (
+					Some(PathBuf::from(format!("strokes-{}.sast", page + 1))),
+					Some(ImageState::Encoded(strokes.to_bytes())),
+				),
+				None => (None, None),
+			})
+			.unzip();
+		let layer = AnnotationLayer { name: LAYER_NAME.into(), format: AnnotationFormat::Strokes, files };
+		self.meta.annotations = AnnotationFormat::Strokes;
+		self.meta.layers.push(layer);
+		self.annotations.push(data);
+	}
+
+	fn strokes_layer(&self) -> Option<usize> {
+		self.meta.layers.iter().position(|layer| layer.name == LAYER_NAME && layer.format == AnnotationFormat::Strokes)
+	}
+}
+
+impl Stroke {
+	/// The outline of the stroke, with its width following the pen pressure: one side of it
+	/// followed by the other in reverse, as in the canvas's meshes. Where the pen didn't move, the
+	/// stroke keeps the direction it had before.
+	pub fn outline(&self) -> Vec<[f32; 2]> {
+		let samples = &self.samples;
+		let normals = samples
+			.iter()
+			.enumerate()
+			.map(|(i, sample)| {
+				let prev = if i == 0 { samples.get(1).unwrap_or(sample) } else { &samples[i - 1] };
+				let (dx, dy) = if i == 0 {
+					(prev.x - sample.x, prev.y - sample.y)
+				} else {
+					(sample.x - prev.x, sample.y - prev.y)
+				};
+				let length = dx.hypot(dy);
+				(length > 0.0).then(|| [-dy / length, dx / length])
+			})
+			.collect::<Vec<_>>();
+		// Until the pen first moves, use the direction it moves in then.
+		let mut normal = normals.iter().flatten().next().copied().unwrap_or([0.0, 0.0]);
+		let mut left = Vec::with_capacity(samples.len());
+		let mut right = Vec::with_capacity(samples.len());
+		for (sample, this) in samples.iter().zip(normals) {
+			normal = this.unwrap_or(normal);
+			let [nx, ny] = normal;
+			let offset = sample.pressure * self.width / 2.0;
+			left.push([sample.x + nx * offset, sample.y + ny * offset]);
+			right.push([sample.x - nx * offset, sample.y - ny * offset]);
+		}
+		left.extend(right.into_iter().rev());
+		left
+	}
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+	fn take<const N: usize>(&mut self) -> Result<[u8; N], StrokesError> {
+		let bytes = self.0.get(..N).ok_or(StrokesError::Truncated)?;
+		self.0 = &self.0[N..];
+		Ok(bytes.try_into().unwrap())
+	}
+
+	fn u8(&mut self) -> Result<u8, StrokesError> {
+		self.take::<1>().map(|[byte]| byte)
+	}
+
+	fn u32(&mut self) -> Result<u32, StrokesError> {
+		self.take().map(u32::from_le_bytes)
+	}
+}
+
+#[derive(Debug)]
+pub enum StrokesError {
+	/// The data is neither the binary format nor text.
+	NotStrokes,
+	UnsupportedVersion(u8),
+	/// The data ends in the middle of a stroke.
+	Truncated,
+	UnknownTool(u8),
+	Ron(ron::error::SpannedError),
+}
+
+impl Display for StrokesError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::NotStrokes => f.write_str("not a strokes file"),
+			Self::UnsupportedVersion(version) => write!(f, "unsupported strokes format version {version}"),
+			Self::Truncated => f.write_str("strokes file is truncated"),
+			Self::UnknownTool(tool) => write!(f, "unknown tool {tool}"),
+			Self::Ron(e) => e.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for StrokesError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_log::test;
+
+	fn strokes() -> Strokes {
+		let sample = |x, y, pressure, time| StrokeSample { x, y, pressure, time };
+		Strokes {
+			strokes: vec![
+				Stroke {
+					tool: Tool::Pen,
+					color: [0, 0, 0, 255],
+					width: 12.0,
+					started: 1_700_000_000_000,
+					samples: vec![sample(10.0, 20.0, 0.0, 0), sample(14.5, 21.25, 1.0, 8), sample(30.0, 22.0, 0.5, 17)],
+				},
+				Stroke {
+					tool: Tool::Highlighter,
+					color: [255, 220, 0, 128],
+					width: 30.0,
+					started: 1_700_000_001_000,
+					samples: vec![],
+				},
+			],
+		}
+	}
+
+	fn assert_close(a: &Strokes, b: &Strokes) {
+		assert_eq!(a.strokes.len(), b.strokes.len());
+		for (a, b) in a.strokes.iter().zip(&b.strokes) {
+			assert_eq!(
+				Stroke { samples: vec![], ..a.clone() },
+				Stroke { samples: vec![], ..b.clone() }
+			);
+			assert_eq!(a.samples.len(), b.samples.len());
+			for (a, b) in a.samples.iter().zip(&b.samples) {
+				assert_eq!((a.x, a.y, a.time), (b.x, b.y, b.time));
+				assert!((a.pressure - b.pressure).abs() < 1.0 / u16::MAX as f32, "{a:?} != {b:?}");
+			}
+		}
+	}
+
+	#[test]
+	fn binary_round_trip() {
+		let strokes = strokes();
+		let data = strokes.to_bytes();
+		assert_eq!(data.len(), 9 + 2 * 21 + 3 * 14);
+		assert_close(&Strokes::from_bytes(&data).unwrap(), &strokes);
+	}
+
+	#[test]
+	fn ron_round_trip() {
+		let strokes = strokes();
+		let text = strokes.to_ron().unwrap();
+		assert_eq!(Strokes::from_bytes(text.as_bytes()).unwrap(), strokes);
+	}
+
+	#[test]
+	fn bad_data() {
+		let data = strokes().to_bytes();
+		assert!(matches!(Strokes::from_bytes(&data[..data.len() - 1]), Err(StrokesError::Truncated)));
+		let mut newer = data.clone();
+		newer[4] = VERSION + 1;
+		assert!(matches!(Strokes::from_bytes(&newer), Err(StrokesError::UnsupportedVersion(_))));
+		let mut tool = data;
+		tool[9] = 7;
+		assert!(matches!(Strokes::from_bytes(&tool), Err(StrokesError::UnknownTool(7))));
+		assert!(matches!(Strokes::from_bytes(&[0xff, 0xfe]), Err(StrokesError::NotStrokes)));
+	}
+
+	#[test]
+	fn kept_in_a_piece() {
+		use crate::{Information, Meta, PageFormat, Pages};
+		let mut piece = Piece {
+			meta: Meta {
+				format: PageFormat::Images { files: vec!["1.png".into(), "2.png".into(), "3.png".into()] },
+				annotations: AnnotationFormat::Image,
+				layers: Vec::new(),
+				information: Information::default(),
+				reading_order: Vec::new(),
+				measures: Vec::new(),
+				bookmarks: Vec::new(),
+				musicreader: None,
+			},
+			pages: Pages::Images((0..3).map(|_| ImageState::Encoded(Vec::new())).collect()),
+			annotations: Vec::new(),
+		};
+		assert_eq!(piece.take_strokes(), vec![Strokes::default(); 3]);
+
+		let pages = [Strokes::default(), strokes(), strokes()];
+		piece.set_strokes(&pages);
+		piece.set_strokes(&pages);
+		assert_eq!(piece.meta.layers.len(), 1, "setting strokes again replaces them");
+		assert_eq!(piece.meta.layers[0].files, [None, Some("strokes-2.sast".into()), Some("strokes-3.sast".into())]);
+		assert_eq!(piece.meta.annotations, AnnotationFormat::Strokes);
+
+		piece.annotations[0][2] = Some(ImageState::Encoded(b"SAst".to_vec()));
+		let taken = piece.take_strokes();
+		assert_close(&taken[1], &strokes());
+		assert!(taken[2].strokes.is_empty(), "damaged strokes are left out");
+		assert!(piece.meta.layers.is_empty() && piece.annotations.is_empty());
+
+		piece.set_strokes(&[Strokes::default(), Strokes::default()]);
+		assert!(piece.meta.layers.is_empty());
+	}
+}
@@ -0,0 +1,411 @@
+//! Exchanging [strokes](crate::strokes) with other programs as SVG
+//! ([`AnnotationFormat::Svg`](crate::AnnotationFormat)), so that they can be edited in a vector
+//! editor or shown to people who don't use See Augmented.
+//!
+//! Each stroke is exported as a filled path tracing the outline of the stroke, with its width
+//! following the pen pressure. The outline goes along one side of the stroke and back along the
+//! other, and the path also records the stroke's width, pressures and timing in `data-` attributes,
+//! so that importing it recovers the pen's path and pressure. Other paths, like those drawn in a vector
+//! editor, are imported as strokes along the path at full pressure. Transforms are ignored.
+
+use crate::strokes::{Stroke, StrokeSample, Strokes, Tool};
+use quick_xml::{events::Event, Reader};
+use std::{
+	collections::HashMap,
+	fmt::{Display, Formatter, Write},
+};
+
+/// Draw `strokes` on a page that is `width` by `height` pixels.
+pub fn export(strokes: &Strokes, width: u32, height: u32) -> String {
+	let mut svg = format!(
+		"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+		 <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n"
+	);
+	for stroke in strokes.strokes.iter().filter(|stroke| !stroke.samples.is_empty()) {
+		let outline = stroke.outline();
+		let mut d = String::new();
+		for (i, [x, y]) in outline.iter().enumerate() {
+			let _ = write!(d, "{}{x} {y} ", if i == 0 { 'M' } else { 'L' });
+		}
+		d.push('Z');
+		let [r, g, b, a] = stroke.color;
+		let tool = match stroke.tool {
+			Tool::Pen => "pen",
+			Tool::Highlighter => "highlighter",
+		};
+		let times = stroke.samples.iter().map(|sample| sample.time.to_string()).collect::<Vec<_>>().join(" ");
+		let pressures = stroke.samples.iter().map(|sample| sample.pressure.to_string()).collect::<Vec<_>>().join(" ");
+		let _ = writeln!(
+			svg,
+			"<path d=\"{d}\" fill=\"#{r:02x}{g:02x}{b:02x}\" fill-opacity=\"{}\" data-tool=\"{tool}\" \
+			 data-width=\"{}\" data-started=\"{}\" data-pressures=\"{pressures}\" data-times=\"{times}\"/>",
+			a as f32 / 255.0,
+			stroke.width,
+			stroke.started,
+		);
+	}
+	svg.push_str("</svg>\n");
+	svg
+}
+
+/// Read every path in `svg` as a stroke.
+pub fn import(svg: &str) -> Result<Strokes, SvgError> {
+	let mut reader = Reader::from_str(svg);
+	let mut strokes = Vec::new();
+	loop {
+		match reader.read_event()? {
+			Event::Start(element) | Event::Empty(element) if element.local_name().as_ref() == b"path" => {
+				let mut attributes = HashMap::new();
+				for attribute in element.attributes() {
+					let attribute = attribute.map_err(quick_xml::Error::from)?;
+					let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+					let value = attribute.unescape_value()?.into_owned();
+					if key == "style" {
+						for declaration in value.split(';') {
+							if let Some((key, value)) = declaration.split_once(':') {
+								attributes.insert(key.trim().to_owned(), value.trim().to_owned());
+							}
+						}
+					} else {
+						// Presentation attributes are overridden by the style attribute
+						attributes.entry(key).or_insert(value);
+					}
+				}
+				strokes.extend(path_strokes(&attributes)?);
+			}
+			Event::Eof => break,
+			_ => {}
+		}
+	}
+	Ok(Strokes { strokes })
+}
+
+fn path_strokes(attributes: &HashMap<String, String>) -> Result<Vec<Stroke>, SvgError> {
+	let Some(d) = attributes.get("d") else { return Ok(Vec::new()) };
+	let subpaths = path_points(d)?;
+	let number = |key: &str| attributes.get(key).and_then(|value| value.trim_end_matches("px").parse::<f32>().ok());
+	let tool = match attributes.get("data-tool").map(String::as_str) {
+		Some("highlighter") => Tool::Highlighter,
+		_ => Tool::Pen,
+	};
+	let started = attributes.get("data-started").and_then(|started| started.parse().ok()).unwrap_or(0);
+	let times = attributes
+		.get("data-times")
+		.map(|times| times.split_whitespace().filter_map(|time| time.parse().ok()).collect::<Vec<u32>>())
+		.unwrap_or_default();
+	let time = |i: usize| times.get(i).copied().unwrap_or(0);
+	let pressures = attributes
+		.get("data-pressures")
+		.map(|pressures| pressures.split_whitespace().filter_map(|p| p.parse().ok()).collect::<Vec<f32>>())
+		.unwrap_or_default();
+
+	if let Some(width) = number("data-width") {
+		// An outline exported by `export`
+		let color = color(attributes.get("fill"), number("fill-opacity"));
+		let Some(outline) = subpaths.into_iter().next() else { return Ok(Vec::new()) };
+		let n = outline.len() / 2;
+		let samples = (0..n)
+			.map(|i| {
+				let ([lx, ly], [rx, ry]) = (outline[i], outline[outline.len() - 1 - i]);
+				// Files exported before pressures were recorded only have the outline to go by.
+				let pressure = pressures.get(i).copied().unwrap_or_else(|| {
+					if width > 0.0 { ((lx - rx).hypot(ly - ry) / width).clamp(0.0, 1.0) } else { 1.0 }
+				});
+				StrokeSample { x: (lx + rx) / 2.0, y: (ly + ry) / 2.0, pressure, time: time(i) }
+			})
+			.collect();
+		return Ok(vec![Stroke { tool, color, width, started, samples }]);
+	}
+
+	let stroked = attributes.get("stroke").is_some_and(|stroke| stroke != "none");
+	let color = if stroked {
+		color(attributes.get("stroke"), number("stroke-opacity"))
+	} else {
+		color(attributes.get("fill"), number("fill-opacity"))
+	};
+	let width = if stroked { number("stroke-width").unwrap_or(1.0) } else { 1.0 };
+	Ok(subpaths
+		.into_iter()
+		.map(|points| Stroke {
+			tool,
+			color,
+			width,
+			started,
+			samples: points
+				.into_iter()
+				.enumerate()
+				.map(|(i, [x, y])| StrokeSample { x, y, pressure: 1.0, time: time(i) })
+				.collect(),
+		})
+		.collect())
+}
+
+/// Parse a color like `#ff8000` or `#f80`. Anything else is black.
+fn color(color: Option<&String>, opacity: Option<f32>) -> [u8; 4] {
+	let a = (opacity.unwrap_or(1.0).clamp(0.0, 1.0) * 255.0).round() as u8;
+	let Some(hex) = color.and_then(|color| color.strip_prefix('#')) else { return [0, 0, 0, a] };
+	let digit = |i: usize| u8::from_str_radix(hex.get(i..i + 1)?, 16).ok();
+	let channels = match hex.len() {
+		3 => [digit(0), digit(1), digit(2)].map(|c| c.map(|c| c * 17)),
+		6 => [0, 2, 4].map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()),
+		_ => [None; 3],
+	};
+	match channels {
+		[Some(r), Some(g), Some(b)] => [r, g, b, a],
+		_ => [0, 0, 0, a],
+	}
+}
+
+/// The points of each subpath in SVG path data. Curves and arcs are replaced by straight lines
+/// to their end points.
+fn path_points(d: &str) -> Result<Vec<Vec<[f32; 2]>>, SvgError> {
+	let mut data = PathData(d);
+	let mut subpaths = Vec::<Vec<[f32; 2]>>::new();
+	let mut command = None;
+	let (mut current, mut start) = ([0.0, 0.0], [0.0, 0.0]);
+	while !data.at_end() {
+		if let Some(c) = data.command() {
+			command = Some(c);
+			if c.eq_ignore_ascii_case(&'z') {
+				current = start;
+				continue;
+			}
+		}
+		let c = command.ok_or_else(|| SvgError::Path(d.to_owned()))?;
+		let base = if c.is_ascii_lowercase() { current } else { [0.0, 0.0] };
+		let invalid = || SvgError::Path(d.to_owned());
+		let point = |data: &mut PathData| data.point(base).ok_or_else(invalid);
+		let point = match c.to_ascii_uppercase() {
+			'M' => {
+				let point = point(&mut data)?;
+				subpaths.push(Vec::new());
+				start = point;
+				// Further pairs of numbers are lines
+				command = Some(if c.is_ascii_lowercase() { 'l' } else { 'L' });
+				point
+			}
+			'L' | 'T' => point(&mut data)?,
+			'S' | 'Q' => {
+				point(&mut data)?;
+				point(&mut data)?
+			}
+			'C' => {
+				point(&mut data)?;
+				point(&mut data)?;
+				point(&mut data)?
+			}
+			'H' | 'V' => {
+				let n = data.number().ok_or_else(invalid)?;
+				let mut point = current;
+				let axis = if c.eq_ignore_ascii_case(&'h') { 0 } else { 1 };
+				point[axis] = base[axis] + n;
+				point
+			}
+			'A' => {
+				for _ in 0..5 {
+					data.number().ok_or_else(invalid)?;
+				}
+				point(&mut data)?
+			}
+			_ => return Err(invalid()),
+		};
+		if subpaths.is_empty() {
+			subpaths.push(Vec::new());
+		}
+		subpaths.last_mut().unwrap().push(point);
+		current = point;
+	}
+	Ok(subpaths.into_iter().filter(|points| !points.is_empty()).collect())
+}
+
+struct PathData<'a>(&'a str);
+
+impl PathData<'_> {
+	fn skip_separators(&mut self) {
+		self.0 = self.0.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+	}
+
+	fn at_end(&mut self) -> bool {
+		self.skip_separators();
+		self.0.is_empty()
+	}
+
+	fn command(&mut self) -> Option<char> {
+		self.skip_separators();
+		let c = self.0.chars().next().filter(|c| c.is_ascii_alphabetic() && !matches!(c, 'e' | 'E'))?;
+		self.0 = &self.0[1..];
+		Some(c)
+	}
+
+	/// A pair of coordinates, relative to `base`.
+	fn point(&mut self, base: [f32; 2]) -> Option<[f32; 2]> {
+		Some([base[0] + self.number()?, base[1] + self.number()?])
+	}
+
+	fn number(&mut self) -> Option<f32> {
+		self.skip_separators();
+		let bytes = self.0.as_bytes();
+		let mut end = 0;
+		if matches!(bytes.first(), Some(b'+' | b'-')) {
+			end += 1;
+		}
+		let mut seen_dot = false;
+		while let Some(&b) = bytes.get(end) {
+			match b {
+				b'0'..=b'9' => {}
+				b'.' if !seen_dot => seen_dot = true,
+				_ => break,
+			}
+			end += 1;
+		}
+		if matches!(bytes.get(end), Some(b'e' | b'E')) {
+			let mut exp = end + 1;
+			if matches!(bytes.get(exp), Some(b'+' | b'-')) {
+				exp += 1;
+			}
+			if bytes.get(exp).is_some_and(u8::is_ascii_digit) {
+				end = exp;
+				while bytes.get(end).is_some_and(u8::is_ascii_digit) {
+					end += 1;
+				}
+			}
+		}
+		let number = self.0[..end].parse().ok()?;
+		self.0 = &self.0[end..];
+		Some(number)
+	}
+}
+
+#[derive(Debug)]
+pub enum SvgError {
+	Xml(quick_xml::Error),
+	/// Path data that couldn't be parsed.
+	Path(String),
+}
+
+impl Display for SvgError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Xml(e) => e.fmt(f),
+			Self::Path(d) => write!(f, "invalid path data `{d}`"),
+		}
+	}
+}
+
+impl std::error::Error for SvgError {}
+
+impl From<quick_xml::Error> for SvgError {
+	fn from(e: quick_xml::Error) -> Self {
+		Self::Xml(e)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_log::test;
+
+	fn strokes() -> Strokes {
+		let sample = |x, y, pressure, time| StrokeSample { x, y, pressure, time };
+		Strokes {
+			strokes: vec![
+				Stroke {
+					tool: Tool::Pen,
+					color: [10, 20, 30, 255],
+					width: 12.0,
+					started: 1_700_000_000_000,
+					samples: vec![
+						sample(10.0, 20.0, 0.3, 0),
+						sample(14.5, 21.25, 1.0, 8),
+						sample(30.0, 40.0, 0.5, 17),
+						sample(31.0, 60.0, 0.0, 30),
+					],
+				},
+				Stroke {
+					tool: Tool::Highlighter,
+					color: [255, 220, 0, 128],
+					width: 30.0,
+					started: 1_700_000_001_000,
+					samples: vec![sample(100.0, 100.0, 0.8, 0), sample(200.0, 100.0, 0.8, 50)],
+				},
+			],
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let original = strokes();
+		let svg = export(&original, 1700, 2200);
+		assert!(svg.contains("viewBox=\"0 0 1700 2200\""), "{svg}");
+		let imported = import(&svg).unwrap();
+
+		assert_eq!(imported.strokes.len(), original.strokes.len());
+		for (a, b) in original.strokes.iter().zip(&imported.strokes) {
+			assert_eq!((a.tool, a.width, a.started), (b.tool, b.width, b.started));
+			for (a, b) in a.color.iter().zip(&b.color) {
+				assert!(a.abs_diff(*b) <= 1, "{:?} != {:?}", a, b);
+			}
+			assert_eq!(a.samples.len(), b.samples.len());
+			for (a, b) in a.samples.iter().zip(&b.samples) {
+				assert!((a.x - b.x).abs() < 0.01 && (a.y - b.y).abs() < 0.01, "{a:?} != {b:?}");
+				assert!((a.pressure - b.pressure).abs() < 0.01, "{a:?} != {b:?}");
+				assert_eq!(a.time, b.time);
+			}
+		}
+	}
+
+	#[test]
+	fn repeated_samples() {
+		let sample = |x, pressure, time| StrokeSample { x, y: 5.0, pressure, time };
+		let stroke = Stroke {
+			tool: Tool::Pen,
+			color: [0, 0, 0, 255],
+			width: 10.0,
+			started: 0,
+			samples: vec![sample(0.0, 0.5, 0), sample(0.0, 0.7, 4), sample(20.0, 0.9, 9), sample(20.0, 0.4, 12)],
+		};
+		// Where the pen rests, the outline still has the pressure's width.
+		let outline = stroke.outline();
+		for i in 0..4 {
+			let ([lx, ly], [rx, ry]) = (outline[i], outline[7 - i]);
+			assert!(((lx - rx).hypot(ly - ry) - stroke.samples[i].pressure * 10.0).abs() < 0.01, "{outline:?}");
+		}
+
+		let svg = export(&Strokes { strokes: vec![stroke.clone()] }, 100, 100);
+		let imported = import(&svg).unwrap();
+		assert_eq!(imported.strokes.len(), 1);
+		assert_eq!(imported.strokes[0], stroke);
+
+		// Without the recorded pressures, they are measured from the outline.
+		let start = svg.find(" data-pressures=").unwrap();
+		let end = start + svg[start + 1..].find(" data-").unwrap() + 1;
+		let imported = import(&format!("{}{}", &svg[..start], &svg[end..])).unwrap();
+		for (a, b) in stroke.samples.iter().zip(&imported.strokes[0].samples) {
+			assert!((a.pressure - b.pressure).abs() < 0.01, "{a:?} != {b:?}");
+		}
+	}
+
+	#[test]
+	fn editor_paths() {
+		let svg = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 100 100">
+			<g>
+				<path style="fill:none;stroke:#ff0000;stroke-width:2.5px" d="m 10,10 20,0 v 10 H 5 c 1 1 2 2 3 3 M 50 50 L 60 60 Z"/>
+				<rect x="0" y="0" width="5" height="5"/>
+			</g>
+		</svg>"##;
+		let strokes = import(svg).unwrap();
+		assert_eq!(strokes.strokes.len(), 2);
+		let stroke = &strokes.strokes[0];
+		assert_eq!((stroke.color, stroke.width), ([255, 0, 0, 255], 2.5));
+		let points = stroke.samples.iter().map(|sample| [sample.x, sample.y]).collect::<Vec<_>>();
+		assert_eq!(points, [[10.0, 10.0], [30.0, 10.0], [30.0, 20.0], [5.0, 20.0], [8.0, 23.0]]);
+		assert!(stroke.samples.iter().all(|sample| sample.pressure == 1.0));
+		assert_eq!(strokes.strokes[1].samples.len(), 2);
+	}
+
+	#[test]
+	fn bad_path_data() {
+		let svg = r#"<svg><path d="M 10 L 5 5"/></svg>"#;
+		assert!(matches!(import(svg), Err(SvgError::Path(_))));
+	}
+}
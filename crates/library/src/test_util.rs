@@ -0,0 +1,11 @@
+//! Helpers shared by the tests of several modules.
+
+use std::path::PathBuf;
+
+/// A directory for test `name` to write files in, unique to this process so that test runs going
+/// on at the same time don't write over each other's files.
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("see-aug-library-{}-{name}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
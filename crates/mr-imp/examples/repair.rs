@@ -0,0 +1,19 @@
+//! Write a copy of a MusicReader file that ordinary zip tools can open.
+//!
+//! Usage: `cargo run -p see-aug-mr-imp --example repair -- <in.mrs> <out.mrs>`
+
+use see_aug_mr_imp::header::{repair, Header};
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+	let mut args = std::env::args_os().skip(1);
+	let (Some(path), Some(out)) = (args.next(), args.next()) else {
+		eprintln!("usage: repair <in.mrs> <out.mrs>");
+		std::process::exit(2);
+	};
+	match repair(&path, &out).await? {
+		Header::Zip => println!("header was already standard; copied unchanged"),
+		header => println!("replaced {header:?} header"),
+	}
+	Ok(())
+}
@@ -0,0 +1,190 @@
+use crate::{parse_xml, to_xml, Bookmark, BookmarkLocation, Bookmarks, MRSError, MRSError::*, MRSFile};
+
+/// The `type` MusicReader gives to ordinary bookmarks.
+pub const DEFAULT_BOOKMARK_TYPE: &str = "bookmark";
+
+impl Bookmark {
+	/// A bookmark of the [default type](DEFAULT_BOOKMARK_TYPE) at (`x`, `y`) in page image
+	/// `pageimage`.
+	pub fn new(name: impl Into<String>, pageimage: usize, x: usize, y: usize) -> Self {
+		Self {
+			r#type: DEFAULT_BOOKMARK_TYPE.into(),
+			pageimage,
+			location: BookmarkLocation { x, y },
+			name: name.into(),
+		}
+	}
+}
+
+impl Bookmarks {
+	pub fn new() -> Self {
+		Self { bookmark: None }
+	}
+
+	pub fn as_slice(&self) -> &[Bookmark] {
+		self.bookmark.as_deref().unwrap_or_default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.as_slice().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.as_slice().is_empty()
+	}
+
+	pub fn get(&self, i: usize) -> Option<&Bookmark> {
+		self.as_slice().get(i)
+	}
+
+	pub fn get_mut(&mut self, i: usize) -> Option<&mut Bookmark> {
+		self.bookmark.as_mut()?.get_mut(i)
+	}
+
+	/// Every bookmark along with its index, in the order they appear in the piece: by page image,
+	/// then top to bottom, then left to right.
+	pub fn in_reading_order(&self) -> Vec<(usize, &Bookmark)> {
+		let mut bookmarks = self.as_slice().iter().enumerate().collect::<Vec<_>>();
+		bookmarks.sort_by_key(|(_, b)| (b.pageimage, b.location.y, b.location.x));
+		bookmarks
+	}
+
+	/// Add `bookmark`, returning its index.
+	pub fn add(&mut self, bookmark: Bookmark) -> usize {
+		let bookmarks = self.bookmark.get_or_insert_with(Vec::new);
+		bookmarks.push(bookmark);
+		bookmarks.len() - 1
+	}
+
+	/// Rename bookmark `i`. Returns `false` if there is no such bookmark.
+	pub fn rename(&mut self, i: usize, name: impl Into<String>) -> bool {
+		let Some(bookmark) = self.get_mut(i) else { return false };
+		bookmark.name = name.into();
+		true
+	}
+
+	/// Move bookmark `i` to (`x`, `y`) in page image `pageimage`. Returns `false` if there is no
+	/// such bookmark.
+	pub fn move_to(&mut self, i: usize, pageimage: usize, x: usize, y: usize) -> bool {
+		let Some(bookmark) = self.get_mut(i) else { return false };
+		bookmark.pageimage = pageimage;
+		bookmark.location = BookmarkLocation { x, y };
+		true
+	}
+
+	/// Remove and return bookmark `i`. Indices of later bookmarks shift down by one.
+	pub fn remove(&mut self, i: usize) -> Option<Bookmark> {
+		let bookmarks = self.bookmark.as_mut()?;
+		if i >= bookmarks.len() {
+			return None;
+		}
+		let removed = bookmarks.remove(i);
+		if bookmarks.is_empty() {
+			self.bookmark = None;
+		}
+		Some(removed)
+	}
+
+	/// Parse the contents of `bookmarks.xml`.
+	pub fn from_xml(xml: &str) -> Result<Self, MRSError> {
+		parse_xml(xml.as_bytes())
+	}
+
+	/// Serialize to the contents of `bookmarks.xml`.
+	pub fn to_xml(&self) -> Result<String, MRSError> {
+		to_xml(crate::BOOKMARKS_XML_ROOT, self)
+	}
+}
+
+impl Default for Bookmarks {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl MRSFile {
+	/// Mutable access to the file's bookmarks, starting an empty list if the file had none.
+	///
+	/// Returns the load error instead if `bookmarks.xml` exists but couldn't be read, so that
+	/// editing doesn't silently replace bookmarks that might still be recovered.
+	pub fn bookmarks_mut(&mut self) -> Result<&mut Bookmarks, &MRSError> {
+		if let Err(Missing) = self.bookmarks {
+			self.bookmarks = Ok(Bookmarks::new());
+		}
+		self.bookmarks.as_mut().map_err(|e| &*e)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::tests::{synthetic_file, temp_path};
+	use test_log::test;
+
+	#[test]
+	fn edit() {
+		let mut bookmarks = Bookmarks::new();
+		assert!(bookmarks.is_empty());
+		let a = bookmarks.add(Bookmark::new("A", 2, 10, 300));
+		let b = bookmarks.add(Bookmark::new("Start here", 1, 0, 0));
+		assert!(bookmarks.rename(a, "Letter A"));
+		assert!(bookmarks.move_to(b, 1, 40, 500));
+		assert!(!bookmarks.rename(5, "nope"));
+
+		let order = bookmarks
+			.in_reading_order()
+			.into_iter()
+			.map(|(i, b)| (i, b.name.as_str()))
+			.collect::<Vec<_>>();
+		assert_eq!(order, [(1, "Start here"), (0, "Letter A")]);
+		assert_eq!(bookmarks.get(b).unwrap().location.y, 500);
+
+		assert_eq!(bookmarks.remove(a).unwrap().name, "Letter A");
+		assert_eq!(bookmarks.get(0).unwrap().name, "Start here");
+		assert_eq!(bookmarks.remove(1), None);
+		bookmarks.remove(0).unwrap();
+		assert_eq!(bookmarks.bookmark, None);
+	}
+
+	#[test]
+	fn xml_shape() {
+		let mut bookmarks = Bookmarks::new();
+		bookmarks.add(Bookmark::new("A", 2, 10, 300));
+		let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<bookmarks>
+	<bookmark>
+		<type>bookmark</type>
+		<pageimage>2</pageimage>
+		<location>
+			<x>10</x>
+			<y>300</y>
+		</location>
+		<name>A</name>
+	</bookmark>
+</bookmarks>
+"#;
+		assert_eq!(bookmarks.to_xml().unwrap(), expected);
+		assert_eq!(Bookmarks::from_xml(expected).unwrap(), bookmarks);
+	}
+
+	#[test(tokio::test)]
+	async fn edits_are_saved() {
+		let mut file = synthetic_file();
+		file.bookmarks = Err(Missing);
+		file.bookmarks_mut().unwrap().add(Bookmark::new("B", 1, 5, 6));
+
+		let path = temp_path("bookmarks.mrs");
+		file.save(&path).await.unwrap();
+		let saved = MRSFile::load(&path).await.unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_eq!(saved.bookmarks.unwrap(), *file.bookmarks.as_ref().unwrap());
+	}
+
+	#[test]
+	fn damaged_bookmarks_are_not_replaced() {
+		let mut file = synthetic_file();
+		file.bookmarks = Bookmarks::from_xml("<bookmarks><bookmark><pageimage>x</pageimage>");
+		assert!(file.bookmarks.is_err());
+		assert!(file.bookmarks_mut().is_err());
+	}
+}
@@ -0,0 +1,189 @@
+use crate::{MRSError, MRSError::*, PageImage};
+use image::{DynamicImage, ImageFormat};
+use std::{
+	num::NonZeroUsize,
+	sync::{
+		atomic::{AtomicUsize, Ordering::Relaxed},
+		Arc,
+	},
+};
+use tokio::{
+	sync::{mpsc, Semaphore},
+	task::JoinHandle,
+};
+
+impl PageImage {
+	/// Decode this image, or clone it if it is already decoded.
+	///
+	/// This can take a while for full-size pages, so prefer [`DecodeJob`] over calling it on an
+	/// async or UI thread.
+	pub fn decode(&self) -> Result<DynamicImage, MRSError> {
+		match self {
+			Self::Png(data) => image::load_from_memory_with_format(data, ImageFormat::Png).map_err(ImageErr),
+			Self::DynImg(img) => Ok(img.clone()),
+		}
+	}
+}
+
+/// The result of decoding a single page image with a [`DecodeJob`].
+#[derive(Debug)]
+pub struct Decoded<T = DynamicImage> {
+	/// The key that the image was submitted with, usually the page index.
+	pub index: usize,
+	pub image: Result<T, MRSError>,
+}
+
+/// Decodes a batch of [`PageImage`]s on a pool of blocking worker threads.
+///
+/// Images are started in the order they are submitted, so callers should put the pages that are
+/// currently visible first. Results arrive in the order they finish, through [`next`](Self::next).
+/// Dropping the job cancels any decoding that hasn't started yet.
+///
+/// Each decoded image can also be converted on the worker thread, such as into the form it is
+/// drawn in, with [`spawn_with`](Self::spawn_with).
+#[derive(Debug)]
+pub struct DecodeJob<T = DynamicImage> {
+	rx: mpsc::UnboundedReceiver<Decoded<T>>,
+	done: Arc<AtomicUsize>,
+	total: usize,
+	task: JoinHandle<()>,
+}
+
+impl DecodeJob {
+	/// Start decoding `images` using up to `workers` threads at a time. Must be called from within
+	/// a Tokio runtime.
+	pub fn spawn(images: impl IntoIterator<Item = (usize, PageImage)>, workers: NonZeroUsize) -> Self {
+		Self::spawn_with(images, workers, |image| image)
+	}
+
+	/// Like [`spawn`](Self::spawn), using one worker per available CPU.
+	pub fn spawn_default(images: impl IntoIterator<Item = (usize, PageImage)>) -> Self {
+		Self::spawn(images, default_workers())
+	}
+}
+
+impl<T: Send + 'static> DecodeJob<T> {
+	/// Like [`spawn`](Self::spawn), also passing each decoded image through `convert` on the worker
+	/// thread that decoded it.
+	pub fn spawn_with(
+		images: impl IntoIterator<Item = (usize, PageImage)>,
+		workers: NonZeroUsize,
+		convert: impl Fn(DynamicImage) -> T + Send + Sync + 'static,
+	) -> Self {
+		let convert = Arc::new(convert);
+		let images = images.into_iter().collect::<Vec<_>>();
+		let total = images.len();
+		let done = Arc::new(AtomicUsize::new(0));
+		let (tx, rx) = mpsc::unbounded_channel();
+
+		let task = {
+			let done = done.clone();
+			tokio::spawn(async move {
+				let workers = Arc::new(Semaphore::new(workers.get()));
+				for (index, img) in images {
+					let Ok(permit) = workers.clone().acquire_owned().await else {
+						return;
+					};
+					if tx.is_closed() {
+						return;
+					}
+					let tx = tx.clone();
+					let done = done.clone();
+					let convert = convert.clone();
+					tokio::task::spawn_blocking(move || {
+						let image = img.decode().map(|image| convert(image));
+						done.fetch_add(1, Relaxed);
+						// The receiver may have been dropped, in which case nobody cares anymore.
+						let _ = tx.send(Decoded { index, image });
+						drop(permit);
+					});
+				}
+			})
+		};
+
+		Self { rx, done, total, task }
+	}
+
+	/// Like [`spawn_with`](Self::spawn_with), using one worker per available CPU.
+	pub fn spawn_default_with(
+		images: impl IntoIterator<Item = (usize, PageImage)>,
+		convert: impl Fn(DynamicImage) -> T + Send + Sync + 'static,
+	) -> Self {
+		Self::spawn_with(images, default_workers(), convert)
+	}
+
+	/// Wait for the next image to finish decoding. Returns `None` once every image has been
+	/// delivered.
+	pub async fn next(&mut self) -> Option<Decoded<T>> {
+		self.rx.recv().await
+	}
+
+	/// How many images have finished decoding, and how many were submitted in total.
+	pub fn progress(&self) -> (usize, usize) {
+		(self.done.load(Relaxed), self.total)
+	}
+
+	pub fn is_finished(&self) -> bool {
+		self.done.load(Relaxed) == self.total
+	}
+}
+
+impl<T> Drop for DecodeJob<T> {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+/// One worker per available CPU.
+fn default_workers() -> NonZeroUsize {
+	std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{tests::synthetic_file, PageImages};
+	use test_log::test;
+
+	#[test(tokio::test)]
+	async fn decodes_every_page() {
+		let file = synthetic_file();
+		let images = file.pages.iter().enumerate().map(|(i, PageImages { page, .. })| {
+			let png = page.as_ref().unwrap().to_png().unwrap().into_owned();
+			(i, PageImage::Png(png))
+		});
+		let mut job = DecodeJob::spawn(images, NonZeroUsize::new(2).unwrap());
+		let mut seen = vec![false; file.pages.len()];
+		while let Some(Decoded { index, image }) = job.next().await {
+			let expected = file.pages[index].page.as_ref().unwrap().decode().unwrap();
+			assert_eq!(image.unwrap().to_rgba8(), expected.to_rgba8());
+			seen[index] = true;
+		}
+		assert!(seen.into_iter().all(|seen| seen));
+		assert!(job.is_finished());
+		assert_eq!(job.progress(), (file.pages.len(), file.pages.len()));
+	}
+
+	#[test(tokio::test)]
+	async fn converts_on_workers() {
+		let file = synthetic_file();
+		let images = file.pages.iter().enumerate().map(|(i, PageImages { page, .. })| {
+			(i, PageImage::Png(page.as_ref().unwrap().to_png().unwrap().into_owned()))
+		});
+		let mut job = DecodeJob::spawn_default_with(images, |image| image.to_rgba8());
+		while let Some(Decoded { index, image }) = job.next().await {
+			let expected = file.pages[index].page.as_ref().unwrap().decode().unwrap();
+			assert_eq!(image.unwrap(), expected.to_rgba8());
+		}
+		assert!(job.is_finished());
+	}
+
+	#[test(tokio::test)]
+	async fn reports_errors() {
+		let mut job = DecodeJob::spawn_default([(7, PageImage::Png(b"not a png".to_vec()))]);
+		let Decoded { index, image } = job.next().await.unwrap();
+		assert_eq!(index, 7);
+		assert!(matches!(image, Err(ImageErr(_))));
+		assert!(job.next().await.is_none());
+	}
+}
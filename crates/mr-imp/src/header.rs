@@ -0,0 +1,227 @@
+use crate::{MRSError, MRSError::*};
+use async_mrs::tokio::read::fs::ZipFileReader;
+use std::{
+	io::{self, SeekFrom},
+	path::Path,
+	pin::Pin,
+	task::{Context, Poll},
+};
+use tokio::{
+	fs::{File, OpenOptions},
+	io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncWriteExt, BufReader, ReadBuf},
+};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+
+/// The first two bytes of a standard zip archive.
+pub const ZIP_MAGIC: [u8; 2] = *b"PK";
+
+/// How an archive starts.
+///
+/// MusicReader sometimes writes files whose first two bytes aren't the usual `PK`, which makes
+/// the first entry unreadable by ordinary zip tools. Everything else about these files is a
+/// valid zip archive, so they are read as though the header were `PK` without touching the file
+/// on disk. Use [`repair`] to write a copy that other programs can open.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Header {
+	/// A standard zip local file header.
+	#[default]
+	Zip,
+	/// A zip local file header with MusicReader's own `magic` in place of `PK`.
+	MusicReader { magic: [u8; 2] },
+	/// Not a local file header at all. The archive may still be readable if its first entry
+	/// doesn't start at the beginning of the file.
+	Unknown([u8; 4]),
+}
+
+impl Header {
+	/// Identify the header from the first four bytes of a file.
+	pub fn from_bytes(bytes: [u8; 4]) -> Self {
+		match bytes {
+			[b'P', b'K', 3, 4] => Self::Zip,
+			[a, b, 3, 4] => Self::MusicReader { magic: [a, b] },
+			other => Self::Unknown(other),
+		}
+	}
+
+	/// Identify the header of the file at `path`.
+	pub async fn detect(path: impl AsRef<Path>) -> io::Result<Self> {
+		let mut bytes = [0; 4];
+		File::open(path).await?.read_exact(&mut bytes).await?;
+		Ok(Self::from_bytes(bytes))
+	}
+
+	/// Whether the header must be replaced with [`ZIP_MAGIC`] to read the archive.
+	pub fn needs_repair(&self) -> bool {
+		matches!(self, Self::MusicReader { .. })
+	}
+}
+
+/// Write a copy of the archive at `path` to `out` with a standard zip header, returning the
+/// header the original had. The original file is never modified.
+///
+/// Files that already have a standard header are copied unchanged. Fails without writing
+/// anything if the header isn't recognized or `out` is the same file as `path`.
+pub async fn repair(path: impl AsRef<Path>, out: impl AsRef<Path>) -> io::Result<Header> {
+	let (path, out) = (path.as_ref(), out.as_ref());
+	let header = Header::detect(path).await?;
+	if let Header::Unknown(bytes) = header {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!("`{}` does not start with a zip header: {bytes:02x?}", path.display()),
+		));
+	}
+	if let (Ok(a), Ok(b)) = (tokio::fs::canonicalize(path).await, tokio::fs::canonicalize(out).await) {
+		if a == b {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"refusing to repair a file in place; choose a different output path",
+			));
+		}
+	}
+
+	tokio::fs::copy(path, out).await?;
+	if header.needs_repair() {
+		let mut file = OpenOptions::new().write(true).open(out).await?;
+		file.write_all(&ZIP_MAGIC).await?;
+		file.sync_all().await?;
+	}
+	Ok(header)
+}
+
+/// Read entry `i` of an archive with the given `header` into `buf`.
+pub(crate) async fn read_entry(
+	reader: &ZipFileReader,
+	header: Header,
+	i: usize,
+	buf: &mut Vec<u8>,
+) -> Result<usize, MRSError> {
+	let at_start = reader
+		.file()
+		.entries()
+		.get(i)
+		.is_some_and(|entry| entry.header_offset() == 0);
+	if !(at_start && header.needs_repair()) {
+		return reader
+			.reader_with_entry(i)
+			.await
+			.map_err(ZipErr)?
+			.read_to_end_checked(buf)
+			.await
+			.map_err(ZipErr);
+	}
+
+	let file = File::open(reader.path()).await.map_err(|e| ZipErr(e.into()))?;
+	let patched = BufReader::new(PatchedHeader { inner: file, pos: 0 }).compat();
+	let mut reader = async_mrs::base::read::seek::ZipFileReader::from_raw_parts(patched, reader.file().clone());
+	reader
+		.reader_with_entry(i)
+		.await
+		.map_err(ZipErr)?
+		.read_to_end_checked(buf)
+		.await
+		.map_err(ZipErr)
+}
+
+/// Reads `inner` as though it started with [`ZIP_MAGIC`].
+struct PatchedHeader<R> {
+	inner: R,
+	/// Position in `inner` of the next byte to be read
+	pos: u64,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for PatchedHeader<R> {
+	fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+		let start = buf.filled().len();
+		let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+		if let Poll::Ready(Ok(())) = res {
+			let read = &mut buf.filled_mut()[start..];
+			for (i, byte) in read.iter_mut().enumerate() {
+				match ZIP_MAGIC.get(self.pos as usize + i) {
+					Some(magic) => *byte = *magic,
+					None => break,
+				}
+			}
+			self.pos += read.len() as u64;
+		}
+		res
+	}
+}
+
+impl<R: AsyncSeek + Unpin> AsyncSeek for PatchedHeader<R> {
+	fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+		Pin::new(&mut self.inner).start_seek(position)
+	}
+
+	fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+		let res = Pin::new(&mut self.inner).poll_complete(cx);
+		if let Poll::Ready(Ok(pos)) = res {
+			self.pos = pos;
+		}
+		res
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		lazy::LazyMRSFile,
+		tests::{assert_same_content, synthetic_file, temp_path},
+		MRSFile,
+	};
+	use test_log::test;
+	use tokio::io::AsyncSeekExt;
+
+	#[test]
+	fn identify() {
+		assert_eq!(Header::from_bytes(*b"PK\x03\x04"), Header::Zip);
+		assert_eq!(Header::from_bytes(*b"MR\x03\x04"), Header::MusicReader { magic: *b"MR" });
+		assert_eq!(Header::from_bytes(*b"%PDF"), Header::Unknown(*b"%PDF"));
+	}
+
+	#[test(tokio::test)]
+	async fn patched_reads() {
+		let data = b"MR\x03\x04rest".to_vec();
+		let mut reader = PatchedHeader { inner: std::io::Cursor::new(data), pos: 0 };
+		let mut first = [0; 1];
+		reader.read_exact(&mut first).await.unwrap();
+		let mut rest = Vec::new();
+		reader.read_to_end(&mut rest).await.unwrap();
+		assert_eq!(first, *b"P");
+		assert_eq!(rest, b"K\x03\x04rest");
+
+		reader.seek(SeekFrom::Start(1)).await.unwrap();
+		let mut bytes = [0; 2];
+		reader.read_exact(&mut bytes).await.unwrap();
+		assert_eq!(bytes, *b"K\x03");
+	}
+
+	#[test(tokio::test)]
+	async fn musicreader_header() {
+		let original = synthetic_file();
+		let mut data = original.save_to_writer(Vec::new()).await.unwrap();
+		data[..2].copy_from_slice(b"MR");
+		let path = temp_path("musicreader-header.mrs");
+		tokio::fs::write(&path, &data).await.unwrap();
+
+		let (loaded, report) = MRSFile::load_with_report(&path).await.unwrap();
+		assert_eq!(report.header, Header::MusicReader { magic: *b"MR" });
+		assert!(report.is_clean(), "{:?}", report.diagnostics);
+		assert_same_content(&original, &loaded);
+		assert_eq!(tokio::fs::read(&path).await.unwrap(), data, "original file was modified");
+
+		let lazy = LazyMRSFile::load(&path).await.unwrap();
+		assert_eq!(lazy.info.as_ref().unwrap(), original.info.as_ref().unwrap());
+		assert!(lazy.page(0).await.unwrap().page.is_ok());
+
+		let out = temp_path("musicreader-header-repaired.mrs");
+		assert_eq!(repair(&path, &out).await.unwrap(), Header::MusicReader { magic: *b"MR" });
+		assert!(repair(&path, &path).await.is_err());
+		assert_eq!(Header::detect(&out).await.unwrap(), Header::Zip);
+		let repaired = tokio::fs::read(&out).await.unwrap();
+		assert_eq!(repaired[2..], data[2..]);
+
+		std::fs::remove_file(&path).unwrap();
+		std::fs::remove_file(&out).unwrap();
+	}
+}
@@ -0,0 +1,269 @@
+use crate::{
+	header::{read_entry, Header},
+	parse_info, parse_xml,
+	report::{classify_entry, EntryKind, LoadReport, Severity},
+	Bookmarks, MRSError,
+	MRSError::*,
+	PageImage, PageImages, Piece,
+};
+use async_mrs::tokio::read::fs::ZipFileReader;
+use std::{
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicUsize, Ordering::Relaxed},
+		Arc,
+	},
+};
+use tokio::sync::Mutex;
+
+/// A MusicReader (`.mrs`) file whose page images are only read from the archive when requested.
+///
+/// The archive index, `info.xml` and `bookmarks.xml` are read eagerly since they are small and
+/// needed up front. Each page is fetched the first time [`page`](Self::page) is called for it,
+/// and kept until it is evicted from the cache (see [`with_cache_capacity`](Self::with_cache_capacity)).
+pub struct LazyMRSFile {
+	pub path: PathBuf,
+	pub bookmarks: Result<Bookmarks, MRSError>,
+	pub info: Result<Piece, MRSError>,
+	reader: ZipFileReader,
+	header: Header,
+	/// Archive entry indices for each layer of each page, along with their file stems.
+	index: Vec<Vec<(&'static str, usize)>>,
+	slots: Vec<Mutex<Option<Arc<PageImages>>>>,
+	capacity: Option<usize>,
+	view: AtomicUsize,
+}
+
+impl LazyMRSFile {
+	/// Open the archive at `path`, logging anything unexpected about its contents. See
+	/// [`MRSFile::load`](crate::MRSFile::load).
+	pub async fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+		let (file, report) = Self::load_with_report(path).await?;
+		report.log(file.path.display());
+		Ok(file)
+	}
+
+	/// Open the archive at `path`, along with a report of every entry that was skipped or couldn't
+	/// be read. Problems with page images are only found once their names are checked here, not
+	/// when they are read later.
+	pub async fn load_with_report(path: impl AsRef<Path>) -> std::io::Result<(Self, LoadReport)> {
+		let mut index = Vec::<Vec<_>>::new();
+		let mut bookmarks = Err(Missing);
+		let mut info = Err(Missing);
+		let mut report = LoadReport::default();
+
+		let path = PathBuf::from(path.as_ref());
+		let header = Header::detect(&path).await?;
+		report.header = header;
+		let reader = ZipFileReader::new(&path)
+			.await
+			.map_err(std::io::Error::other)?;
+
+		let mut buf = Vec::new();
+		for i in 0..reader.file().entries().len() {
+			let entry_path = PathBuf::from(
+				String::from_utf8_lossy(reader.file().entries()[i].filename().as_bytes()).as_ref(),
+			);
+			let entry_filename = entry_path.display().to_string();
+
+			let kind = match classify_entry(&entry_path) {
+				Ok(kind) => kind,
+				Err((severity, e)) => {
+					report.push(i, entry_filename, severity, e);
+					continue;
+				}
+			};
+			if let EntryKind::Image { layer, page_num } = kind {
+				index.resize_with(usize::max(page_num, index.len()), Vec::new);
+				index[page_num - 1].push((layer, i));
+				continue;
+			}
+
+			buf.clear();
+			if let Err(e) = read_entry(&reader, header, i, &mut buf).await {
+				report.push(i, entry_filename, Severity::Error, e);
+				continue;
+			}
+			let parsed = match kind {
+				EntryKind::Info => {
+					info = parse_info(&buf);
+					info.as_ref().err()
+				}
+				EntryKind::Bookmarks => {
+					bookmarks = parse_xml(&buf);
+					bookmarks.as_ref().err()
+				}
+				EntryKind::Image { .. } => unreachable!("images are only indexed"),
+			};
+			if let Some(e) = parsed {
+				report.push(i, entry_filename, Severity::Error, e.summarize());
+			}
+		}
+
+		let slots = index.iter().map(|_| Mutex::new(None)).collect();
+		let file = Self {
+			path,
+			bookmarks,
+			info,
+			reader,
+			header,
+			index,
+			slots,
+			capacity: None,
+			view: AtomicUsize::new(0),
+		};
+		Ok((file, report))
+	}
+
+	/// Keep at most `capacity` pages in memory, evicting those farthest from the current
+	/// [view](Self::set_view) first. `None` (the default) keeps every page once it is loaded.
+	pub fn with_cache_capacity(mut self, capacity: Option<usize>) -> Self {
+		self.capacity = capacity;
+		self
+	}
+
+	/// Number of pages in the archive.
+	pub fn page_count(&self) -> usize {
+		self.index.len()
+	}
+
+	/// Get the images for page index `i` (0-based), reading them from the archive if they are not
+	/// cached. Returns `None` if `i` is out of range.
+	pub async fn page(&self, i: usize) -> Option<Arc<PageImages>> {
+		let slot = self.slots.get(i)?;
+		let images = {
+			let mut slot = slot.lock().await;
+			match &*slot {
+				Some(images) => images.clone(),
+				None => slot.insert(Arc::new(self.read_page(i).await)).clone(),
+			}
+		};
+		self.evict(Some(i));
+		Some(images)
+	}
+
+	/// Set the page index that is currently being viewed. Pages are evicted in order of their
+	/// distance from this page when the cache is over capacity.
+	pub fn set_view(&self, i: usize) {
+		self.view.store(i, Relaxed);
+		self.evict(None);
+	}
+
+	/// Whether page index `i` is currently held in memory.
+	pub fn is_cached(&self, i: usize) -> bool {
+		self.slots
+			.get(i)
+			.and_then(|slot| slot.try_lock().ok())
+			.is_some_and(|slot| slot.is_some())
+	}
+
+	/// Drop every cached page.
+	pub fn clear_cache(&self) {
+		for slot in &self.slots {
+			if let Ok(mut slot) = slot.try_lock() {
+				*slot = None;
+			}
+		}
+	}
+
+	async fn read_page(&self, i: usize) -> PageImages {
+		let mut images = PageImages::new();
+		for &(stem, entry) in &self.index[i] {
+			let mut buf = Vec::new();
+			let img = read_entry(&self.reader, self.header, entry, &mut buf)
+				.await
+				.map(|_| PageImage::Png(buf));
+			if let Some(layer) = images.layer_mut(stem) {
+				*layer = img;
+			}
+		}
+		images
+	}
+
+	/// Drop the pages farthest from the view until the cache is within capacity, apart from page
+	/// `keep`, which was just asked for and would otherwise be read again on every request.
+	fn evict(&self, keep: Option<usize>) {
+		let Some(capacity) = self.capacity else { return };
+		let view = self.view.load(Relaxed);
+		// Pages that are currently being loaded are locked, and thus neither counted nor evicted.
+		let mut cached = self
+			.slots
+			.iter()
+			.enumerate()
+			.filter_map(|(i, slot)| Some((i, slot.try_lock().ok()?)))
+			.filter(|(_, slot)| slot.is_some())
+			.collect::<Vec<_>>();
+		if cached.len() <= capacity {
+			return;
+		}
+		cached.sort_by_key(|&(i, _)| (Some(i) == keep, std::cmp::Reverse(i.abs_diff(view))));
+		let excess = cached.len() - capacity;
+		for (_, slot) in &mut cached[..excess] {
+			**slot = None;
+		}
+	}
+}
+
+impl std::fmt::Debug for LazyMRSFile {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("LazyMRSFile")
+			.field("path", &self.path)
+			.field("bookmarks", &self.bookmarks)
+			.field("info", &self.info)
+			.field("page_count", &self.page_count())
+			.field("capacity", &self.capacity)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::MRSFile;
+	use test_log::test;
+
+	#[test(tokio::test)]
+	async fn lazy_matches_eager() {
+		let eager = MRSFile::load("Bourree_annotated.mrs").await.unwrap();
+		let lazy = LazyMRSFile::load("Bourree_annotated.mrs").await.unwrap();
+		assert_eq!(lazy.page_count(), eager.pages.len());
+		assert_eq!(lazy.info.as_ref().ok(), eager.info.as_ref().ok());
+		assert_eq!(lazy.bookmarks.as_ref().ok(), eager.bookmarks.as_ref().ok());
+		for (i, eager) in eager.pages.iter().enumerate() {
+			assert!(!lazy.is_cached(i));
+			let lazy = lazy.page(i).await.unwrap();
+			for ((stem, a), (_, b)) in lazy.layers().into_iter().zip(eager.layers()) {
+				assert_eq!(
+					a.as_ref().ok().map(|img| img.to_png().unwrap()),
+					b.as_ref().ok().map(|img| img.to_png().unwrap()),
+					"{stem}-{}.png",
+					i + 1,
+				);
+			}
+		}
+		assert!(lazy.page(eager.pages.len()).await.is_none());
+	}
+
+	#[test(tokio::test)]
+	async fn evicts_far_pages() {
+		let path = crate::tests::temp_path("lazy-evict.mrs");
+		crate::tests::synthetic_file().save(&path).await.unwrap();
+		let lazy = LazyMRSFile::load(&path)
+			.await
+			.unwrap()
+			.with_cache_capacity(Some(1));
+		assert_eq!(lazy.page_count(), 2);
+		assert!(lazy.page(0).await.unwrap().page.is_ok());
+		assert!(lazy.is_cached(0));
+		lazy.set_view(1);
+		assert!(lazy.page(1).await.unwrap().annotations_remote.is_ok());
+		assert!(lazy.is_cached(1));
+		assert!(!lazy.is_cached(0));
+
+		// A page away from the view stays cached once it was asked for.
+		assert!(lazy.page(0).await.unwrap().page.is_ok());
+		assert!(lazy.is_cached(0));
+		assert!(!lazy.is_cached(1));
+		std::fs::remove_file(&path).unwrap();
+	}
+}
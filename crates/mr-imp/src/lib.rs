@@ -1,13 +1,29 @@
-use image::DynamicImage;
-use log::error;
-use serde::{Deserialize, Serialize};
+use async_mrs::{Compression, ZipEntryBuilder};
+use image::{DynamicImage, ImageOutputFormat};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-	ffi::OsStr,
+	borrow::Cow,
 	fmt::{Display, Formatter},
+	io::Cursor,
 	path::{Path, PathBuf},
 };
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use header::{read_entry, Header};
+use recordings::{info_to_xml, parse_info};
+use report::{classify_entry, EntryKind, LoadReport, Severity};
 use MRSError::*;
 
+mod bookmarks;
+pub use bookmarks::DEFAULT_BOOKMARK_TYPE;
+pub mod decode;
+pub mod header;
+pub mod lazy;
+mod measures;
+mod recordings;
+pub mod report;
+pub mod thumbnails;
+pub mod turns;
+
 /// A loaded MusicReader (`.mrs`) file
 #[derive(Debug)]
 pub struct MRSFile {
@@ -18,16 +34,30 @@ pub struct MRSFile {
 }
 
 impl MRSFile {
+	/// Load the archive at `path`, logging anything unexpected about its contents.
+	///
+	/// Only fails if the archive itself can't be opened. Use [`load_with_report`](Self::load_with_report)
+	/// to find out about damaged or unexpected entries programmatically.
 	pub async fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+		let (file, report) = Self::load_with_report(path).await?;
+		report.log(file.path.display());
+		Ok(file)
+	}
+
+	/// Load the archive at `path`, along with a report of every entry that was skipped or couldn't
+	/// be read.
+	pub async fn load_with_report(path: impl AsRef<Path>) -> std::io::Result<(Self, LoadReport)> {
 		let mut pages = Vec::new();
 		let mut bookmarks = Err(Missing);
 		let mut info = Err(Missing);
+		let mut report = LoadReport::default();
 
 		let path = PathBuf::from(path.as_ref());
+		let header = Header::detect(&path).await?;
+		report.header = header;
 		let reader = async_mrs::tokio::read::fs::ZipFileReader::new(&path)
 			.await
 			.map_err(|e| std::io::Error::other(e))?;
-		let mrs_filename = path.display();
 		
 		let mut buf = Vec::new();
 		let mut backbuf = Vec::new();
@@ -35,12 +65,7 @@ impl MRSFile {
 		let mut task = {
 			let reader = reader.clone();
 			(reader.file().entries().len() > 0).then(|| tokio::spawn(async move {
-				reader
-					.reader_with_entry(0).await
-					.map_err(ZipErr)?
-					.read_to_end_checked(&mut backbuf)
-					.await
-					.map_err(ZipErr)?;
+				read_entry(&reader, header, 0, &mut backbuf).await?;
 				Result::<_, MRSError>::Ok(backbuf)
 			}))
 		};
@@ -49,12 +74,19 @@ impl MRSFile {
 			let entry_path = PathBuf::from(
 				String::from_utf8_lossy(reader.file().entries()[i].filename().as_bytes()).as_ref(),
 			);
-			let entry_filename = entry_path.display();
+			let entry_filename = entry_path.display().to_string();
 			
 			let mut backbuf = match task.take().unwrap().await.unwrap() {
 				Ok(backbuf) => backbuf,
 				Err(e) => {
-					error!("couldn't load entry {} (`{entry_filename}`) from `{mrs_filename}`: {e}", i);
+					report.push(i, entry_filename, Severity::Error, e);
+					// The failed read took the buffer with it
+					let reader = reader.clone();
+					task = (reader.file().entries().len() > i + 1).then(|| tokio::spawn(async move {
+						let mut backbuf = Vec::new();
+						read_entry(&reader, header, i + 1, &mut backbuf).await?;
+						Ok(backbuf)
+					}));
 					continue;
 				},
 			};
@@ -62,69 +94,119 @@ impl MRSFile {
 			let reader = reader.clone();
 			task = (reader.file().entries().len() > i + 1).then(|| tokio::spawn(async move {
 				backbuf.clear();
-				reader
-					.reader_with_entry(i + 1).await
-					.map_err(ZipErr)?
-					.read_to_end_checked(&mut backbuf)
-					.await
-					.map_err(ZipErr)?;
+				read_entry(&reader, header, i + 1, &mut backbuf).await?;
 				Ok(backbuf)
 			}));
 			
-			
-			let Some(stem) = entry_path.file_stem().and_then(OsStr::to_str) else {
-				error!("archived file `{entry_filename}` is missing file stem (is a sub-directory?)");
-				continue;
-			};
-
-			match entry_path.extension().and_then(OsStr::to_str) {
-				Some("png") => {
-					let Some((stem, page_num)) = stem.rsplit_once("-") else {
-						error!("unexpected archived file name `{entry_filename}`");
-						continue;
-					};
-					let page_num = page_num.parse::<usize>().map_err(std::io::Error::other)?;
+			match classify_entry(&entry_path) {
+				Ok(EntryKind::Image { layer, page_num }) => {
 					pages.resize_with(usize::max(page_num, pages.len()), PageImages::default);
-					let i = page_num - 1;
 					// let img = image::load_from_memory(&*buf).map_err(ImageErr);
 					let img = Ok(PageImage::Png(buf.clone()));
-					match stem {
-						"page" => pages[i].page = img,
-						"thumbnail" => pages[i].thumbnail = img,
-						"annotations-local" => pages[i].annotations_local = img,
-						"annotations-remote" => pages[i].annotations_remote = img,
-						other => error!("unexpected file stem `{other}` in archived file `{entry_filename}`"),
+					if let Some(layer) = pages[page_num - 1].layer_mut(layer) {
+						*layer = img;
 					}
 				}
-				Some("xml") => {
-					let buf = std::str::from_utf8(&buf)
-						.map_err(|e| XmlErr(quick_xml::de::DeError::Custom(e.to_string())));
-					match stem {
-						"info" => {
-							info = buf.and_then(|s| quick_xml::de::from_str(s).map_err(XmlErr))
-						}
-						"bookmarks" => {
-							bookmarks =
-								buf.and_then(|s| quick_xml::de::from_str(s).map_err(XmlErr))
-						}
-						other => error!("unexpected xml file: `{other}`"),
+				Ok(EntryKind::Info) => {
+					info = parse_info(&buf);
+					if let Err(e) = &info {
+						report.push(i, entry_filename, Severity::Error, e.summarize());
 					}
 				}
-				Some(other) => error!("unknown extension `{other}`"),
-				None => error!("`{entry_filename}` is missing extension"),
+				Ok(EntryKind::Bookmarks) => {
+					bookmarks = parse_xml(&buf);
+					if let Err(e) = &bookmarks {
+						report.push(i, entry_filename, Severity::Error, e.summarize());
+					}
+				}
+				Err((severity, e)) => report.push(i, entry_filename, severity, e),
 			}
 		}
 
-		Ok(Self {
-			path,
-			pages,
-			bookmarks,
-			info,
-		})
+		Ok((
+			Self {
+				path,
+				pages,
+				bookmarks,
+				info,
+			},
+			report,
+		))
+	}
+
+	/// Write this file back out as a MusicReader archive at `path`, replacing any existing file.
+	///
+	/// Only the parts that are present are written. Entries that failed to load (e.g. broken XML)
+	/// are skipped, since there is nothing meaningful to write for them.
+	pub async fn save(&self, path: impl AsRef<Path>) -> Result<(), MRSError> {
+		let file = tokio::fs::File::create(path.as_ref()).await.map_err(IoErr)?;
+		let mut file = self.save_to_writer(file).await?;
+		file.flush().await.map_err(IoErr)?;
+		Ok(())
+	}
+
+	/// Write this file as a MusicReader archive to `writer`, returning the writer once the archive
+	/// is finished.
+	pub async fn save_to_writer<W: AsyncWrite + Unpin>(&self, writer: W) -> Result<W, MRSError> {
+		let mut writer = async_mrs::tokio::write::ZipFileWriter::with_tokio(writer);
+
+		if let Ok(info) = &self.info {
+			let xml = info_to_xml(info)?;
+			write_entry(&mut writer, "info.xml", xml.as_bytes(), Compression::Deflate).await?;
+		}
+		if let Ok(bookmarks) = &self.bookmarks {
+			let xml = to_xml(BOOKMARKS_XML_ROOT, bookmarks)?;
+			write_entry(&mut writer, "bookmarks.xml", xml.as_bytes(), Compression::Deflate).await?;
+		}
+
+		for (i, images) in self.pages.iter().enumerate() {
+			let page_num = i + 1;
+			for (stem, img) in images.layers() {
+				let Ok(img) = img else { continue };
+				let png = img.to_png()?;
+				// PNG data is already compressed, so deflating it again only costs time.
+				write_entry(&mut writer, &format!("{stem}-{page_num}.png"), &png, Compression::Stored).await?;
+			}
+		}
+
+		Ok(writer.close().await.map_err(ZipErr)?.into_inner())
 	}
 }
 
-type ImageResult = Result<PageImage, MRSError>;
+fn parse_xml<T: DeserializeOwned>(buf: &[u8]) -> Result<T, MRSError> {
+	let buf = std::str::from_utf8(buf)
+		.map_err(|e| XmlErr(quick_xml::de::DeError::Custom(e.to_string())))?;
+	quick_xml::de::from_str(buf).map_err(XmlErr)
+}
+
+/// Root element of `info.xml`
+pub(crate) const INFO_XML_ROOT: &str = "piece";
+/// Root element of `bookmarks.xml`
+pub(crate) const BOOKMARKS_XML_ROOT: &str = "bookmarks";
+/// Element of `info.xml` holding [`Parts`]
+const PARTS_ELEMENT: &str = "parts";
+
+fn to_xml(root: &str, value: &impl Serialize) -> Result<String, MRSError> {
+	let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+	xml.push('\n');
+	let mut ser = quick_xml::se::Serializer::with_root(&mut xml, Some(root)).map_err(XmlErr)?;
+	ser.indent('\t', 1);
+	value.serialize(ser).map_err(XmlErr)?;
+	xml.push('\n');
+	Ok(xml)
+}
+
+async fn write_entry<W: AsyncWrite + Unpin>(
+	writer: &mut async_mrs::tokio::write::ZipFileWriter<W>,
+	filename: &str,
+	data: &[u8],
+	compression: Compression,
+) -> Result<(), MRSError> {
+	let entry = ZipEntryBuilder::new(filename.to_owned().into(), compression);
+	writer.write_entry_whole(entry, data).await.map_err(ZipErr)
+}
+
+pub type ImageResult = Result<PageImage, MRSError>;
 
 #[derive(Default, Debug)]
 pub enum MRSError {
@@ -134,6 +216,32 @@ pub enum MRSError {
 	ImageErr(image::ImageError),
 	XmlErr(quick_xml::DeError),
 	IoErr(std::io::Error),
+	/// A page image's file name doesn't end in a valid page number
+	PageNumErr(std::num::ParseIntError),
+	/// An archive entry that doesn't belong in a MusicReader file
+	UnexpectedEntry(String),
+	/// An entry couldn't be read from the archive. A [summarized](MRSError::summarize) [`ZipErr`].
+	ReadErr(String),
+	/// An image couldn't be decoded. A [summarized](MRSError::summarize) [`ImageErr`].
+	DecodeErr(String),
+}
+
+impl MRSError {
+	/// A copy of this error that keeps its variant where possible. Wrapped errors that can't be
+	/// cloned are replaced by their message.
+	pub fn summarize(&self) -> Self {
+		match self {
+			Missing => Missing,
+			XmlErr(e) => XmlErr(quick_xml::DeError::Custom(e.to_string())),
+			IoErr(e) => IoErr(std::io::Error::new(e.kind(), e.to_string())),
+			PageNumErr(e) => PageNumErr(e.clone()),
+			UnexpectedEntry(msg) => UnexpectedEntry(msg.clone()),
+			ZipErr(e) => ReadErr(e.to_string()),
+			ReadErr(msg) => ReadErr(msg.clone()),
+			ImageErr(e) => DecodeErr(e.to_string()),
+			DecodeErr(msg) => DecodeErr(msg.clone()),
+		}
+	}
 }
 
 impl Display for MRSError {
@@ -144,6 +252,10 @@ impl Display for MRSError {
 			ImageErr(e) => e.fmt(f),
 			XmlErr(e) => e.fmt(f),
 			IoErr(e) => e.fmt(f),
+			PageNumErr(e) => write!(f, "invalid page number: {e}"),
+			UnexpectedEntry(msg) => f.write_str(msg),
+			ReadErr(msg) => write!(f, "couldn't read entry: {msg}"),
+			DecodeErr(msg) => write!(f, "couldn't decode image: {msg}"),
 		}
 	}
 }
@@ -204,6 +316,43 @@ impl PageImages {
 			annotations_remote: Err(Missing),
 		}
 	}
+
+	/// Each image along with the file stem it is stored under in the archive.
+	pub fn layers(&self) -> [(&'static str, &ImageResult); 4] {
+		[
+			("page", &self.page),
+			("thumbnail", &self.thumbnail),
+			("annotations-local", &self.annotations_local),
+			("annotations-remote", &self.annotations_remote),
+		]
+	}
+
+	/// Mutable access to the image stored under file stem `stem` in the archive, if it is one of
+	/// the known [layers](Self::layers).
+	pub fn layer_mut(&mut self, stem: &str) -> Option<&mut ImageResult> {
+		match stem {
+			"page" => Some(&mut self.page),
+			"thumbnail" => Some(&mut self.thumbnail),
+			"annotations-local" => Some(&mut self.annotations_local),
+			"annotations-remote" => Some(&mut self.annotations_remote),
+			_ => None,
+		}
+	}
+}
+
+impl PageImage {
+	/// Get the PNG-encoded bytes of this image, encoding it if it has already been decoded.
+	pub fn to_png(&self) -> Result<Cow<'_, [u8]>, MRSError> {
+		match self {
+			Self::Png(data) => Ok(Cow::Borrowed(data)),
+			Self::DynImg(img) => {
+				let mut data = Vec::new();
+				img.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+					.map_err(ImageErr)?;
+				Ok(Cow::Owned(data))
+			}
+		}
+	}
 }
 
 impl std::fmt::Debug for PageImage {
@@ -237,52 +386,63 @@ pub enum PageImage {
 	DynImg(DynamicImage),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Piece {
 	pub information: Information,
 	pub pages: Pages,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub measures: Option<Measures>,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub parts: Option<Parts>,
+	/// Read and written separately from the rest of `info.xml`, see [`Recordings`].
+	#[serde(skip)]
 	pub recordings: Option<Recordings>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Information {
 	pub identifier: String,
 	pub title: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub creator: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub description: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub subject: Option<Vec<String>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub publisher: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub copyright: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Pages {
 	pub page: Vec<Page>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Page {
 	pub number: usize,
 	pub image: usize,
 	pub pageturn: PageTurn,
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub halfpage: Option<f32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum PageTurn {
 	Whole,
 	Half,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Measures {
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub measure: Option<Vec<Measure>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Measure {
 	pub number: usize,
@@ -295,17 +455,34 @@ pub struct Measure {
 	pub y_bottom: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Parts {
 	pub image: Vec<ImageInfo>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ImageInfo {
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub part: Option<Vec<Part>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Parts {
+	/// Read a `<parts>` section written by [`to_xml`](Self::to_xml).
+	pub fn from_xml(xml: &str) -> Result<Self, MRSError> {
+		parse_xml(xml.as_bytes())
+	}
+
+	/// The `<parts>` section of `info.xml` holding these parts, without an XML declaration.
+	pub fn to_xml(&self) -> Result<String, MRSError> {
+		let mut xml = String::new();
+		let mut ser = quick_xml::se::Serializer::with_root(&mut xml, Some(PARTS_ELEMENT)).map_err(XmlErr)?;
+		ser.indent('\t', 1);
+		self.serialize(ser).map_err(XmlErr)?;
+		Ok(xml)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Part {
 	pub x: usize,
 	pub y: usize,
@@ -313,17 +490,20 @@ pub struct Part {
 	pub height: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The `<recordings>` section of `info.xml`, which links audio recordings to the piece. It is kept
+/// as the XML it was read from and written back unchanged.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Recordings {
-	// TODO: Figure out what fields Recordings should have
+	xml: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Bookmarks {
+	#[serde(skip_serializing_if = "Option::is_none")]
 	pub bookmark: Option<Vec<Bookmark>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Bookmark {
 	pub r#type: String,
 	pub pageimage: usize,
@@ -331,7 +511,7 @@ pub struct Bookmark {
 	pub name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BookmarkLocation {
 	pub x: usize,
 	pub y: usize,
@@ -340,7 +520,7 @@ pub struct BookmarkLocation {
 #[cfg(test)]
 mod tests {
 	use test_log::test;
-	use log::warn;
+	use log::{error, warn};
 	use tokio::task::JoinSet;
 	use super::*;
 
@@ -421,6 +601,127 @@ mod tests {
 	}
 	
 	
+	pub(crate) fn temp_path(name: &str) -> PathBuf {
+		std::env::temp_dir().join(format!("see-aug-mr-imp-{}-{name}", std::process::id()))
+	}
+
+	pub(crate) fn assert_same_content(a: &MRSFile, b: &MRSFile) {
+		assert_eq!(a.pages.len(), b.pages.len());
+		for (i, (a, b)) in a.pages.iter().zip(&b.pages).enumerate() {
+			for ((stem, a), (_, b)) in a.layers().into_iter().zip(b.layers()) {
+				match (a, b) {
+					(Ok(a), Ok(b)) => assert_eq!(
+						a.to_png().unwrap(),
+						b.to_png().unwrap(),
+						"{stem}-{}.png",
+						i + 1
+					),
+					(Err(Missing), Err(Missing)) => {}
+					(a, b) => panic!("{stem}-{}.png: {a:?} != {b:?}", i + 1),
+				}
+			}
+		}
+		assert_eq!(a.info.as_ref().ok(), b.info.as_ref().ok());
+		assert_eq!(a.bookmarks.as_ref().ok(), b.bookmarks.as_ref().ok());
+	}
+
+	#[test(tokio::test)]
+	async fn save_round_trip() {
+		let path = temp_path("Bourree_annotated.mrs");
+		let original = MRSFile::load("Bourree_annotated.mrs").await.unwrap();
+		original.save(&path).await.unwrap();
+		let saved = MRSFile::load(&path).await.unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_same_content(&original, &saved);
+	}
+
+	/// A small two-page file that doesn't depend on any fixtures.
+	pub(crate) fn synthetic_file() -> MRSFile {
+		let img = || {
+			let mut img = image::RgbaImage::new(8, 4);
+			img.put_pixel(3, 2, image::Rgba([255, 0, 0, 255]));
+			Ok(PageImage::DynImg(DynamicImage::ImageRgba8(img)))
+		};
+		MRSFile {
+			path: PathBuf::new(),
+			pages: vec![
+				PageImages { page: img(), thumbnail: img(), ..PageImages::new() },
+				PageImages { page: img(), annotations_local: img(), annotations_remote: img(), ..PageImages::new() },
+			],
+			bookmarks: Ok(Bookmarks {
+				bookmark: Some(vec![Bookmark {
+					r#type: "bookmark".into(),
+					pageimage: 2,
+					location: BookmarkLocation { x: 1, y: 3 },
+					name: "A".into(),
+				}]),
+			}),
+			info: Ok(Piece {
+				information: Information {
+					identifier: "MR0".into(),
+					title: "Synthetic".into(),
+					creator: Some(vec!["Nobody [composer]".into()]),
+					description: None,
+					subject: Some(vec!["Test".into(), "Fixture".into()]),
+					publisher: None,
+					copyright: None,
+				},
+				pages: Pages {
+					page: vec![
+						Page { number: 1, image: 1, pageturn: PageTurn::Half, halfpage: Some(0.5) },
+						Page { number: 2, image: 2, pageturn: PageTurn::Whole, halfpage: None },
+					],
+				},
+				measures: None,
+				parts: None,
+				recordings: Some(Recordings::from_xml(r#"
+					<recordings>
+						<recording>
+							<name>Synthetic take</name>
+							<file>synthetic.ogg</file>
+							<offset>0.5</offset>
+							<measure><number>1</number><movement>1</movement><time>0</time></measure>
+							<page><number>2</number><time>12</time></page>
+							<unmodeled>kept anyway</unmodeled>
+						</recording>
+					</recordings>
+				"#).unwrap()),
+			}),
+		}
+	}
+
+	#[test]
+	fn summaries_keep_their_kind() {
+		let image = ImageErr(image::load_from_memory(b"not an image").unwrap_err());
+		assert!(matches!(image.summarize(), DecodeErr(msg) if msg == image.to_string()));
+		let zip = ZipErr(async_mrs::error::ZipError::EntryIndexOutOfBounds);
+		assert!(matches!(zip.summarize(), ReadErr(msg) if msg == zip.to_string()));
+		assert!(matches!(UnexpectedEntry("a".into()).summarize(), UnexpectedEntry(_)));
+	}
+
+	#[test(tokio::test)]
+	async fn save_synthetic() {
+		let original = synthetic_file();
+		let path = temp_path("synthetic.mrs");
+		original.save(&path).await.unwrap();
+		let saved = MRSFile::load(&path).await.unwrap();
+		std::fs::remove_file(&path).unwrap();
+		assert_same_content(&original, &saved);
+	}
+
+	#[test(tokio::test)]
+	async fn recordings_round_trip() {
+		let original = synthetic_file();
+		let path = temp_path("synthetic_recordings.mrs");
+		original.save(&path).await.unwrap();
+		let saved = MRSFile::load(&path).await.unwrap();
+		std::fs::remove_file(&path).unwrap();
+		let recordings = saved.info.unwrap().recordings.unwrap();
+		let original = original.info.unwrap().recordings.unwrap();
+		assert_eq!(recordings.as_xml().as_bytes(), original.as_xml().as_bytes());
+		assert!(recordings.as_xml().contains("<unmodeled>kept anyway</unmodeled>"));
+	}
+
 	#[ignore]
 	#[test(tokio::test)]
 	async fn parse_dir() {
@@ -0,0 +1,208 @@
+use crate::{Measure, Measures, Piece};
+use std::ops::RangeInclusive;
+
+impl Measure {
+	/// The measure numbers covered by this box. Usually just `number`, but a multi-measure rest
+	/// covers `number_of_measures` consecutive measures.
+	pub fn numbers(&self) -> RangeInclusive<usize> {
+		self.number..=self.number + self.number_of_measures.max(1) - 1
+	}
+
+	/// Whether this box covers measure `number` of `movement`.
+	pub fn covers(&self, movement: usize, number: usize) -> bool {
+		self.movement == movement && self.numbers().contains(&number)
+	}
+
+	/// Whether the pixel at (`x`, `y`) in the page image lies inside this measure.
+	pub fn contains(&self, x: usize, y: usize) -> bool {
+		(self.x_left..=self.x_right).contains(&x) && (self.y_top..=self.y_bottom).contains(&y)
+	}
+
+	pub fn width(&self) -> usize {
+		self.x_right.saturating_sub(self.x_left)
+	}
+
+	pub fn height(&self) -> usize {
+		self.y_bottom.saturating_sub(self.y_top)
+	}
+
+	/// Center of the bounding box, in page image pixels.
+	pub fn center(&self) -> (usize, usize) {
+		((self.x_left + self.x_right) / 2, (self.y_top + self.y_bottom) / 2)
+	}
+}
+
+impl Measures {
+	pub fn as_slice(&self) -> &[Measure] {
+		self.measure.as_deref().unwrap_or_default()
+	}
+}
+
+impl Piece {
+	/// Every measure in the piece, in the order they are stored in `info.xml`.
+	pub fn all_measures(&self) -> &[Measure] {
+		self.measures.as_ref().map(Measures::as_slice).unwrap_or_default()
+	}
+
+	/// The box containing measure `number` of `movement`. If the measure is part of a multi-measure
+	/// rest, this is the box for the whole rest.
+	pub fn measure(&self, movement: usize, number: usize) -> Option<&Measure> {
+		self.all_measures().iter().find(|m| m.covers(movement, number))
+	}
+
+	/// The measure containing the pixel at (`x`, `y`) in page image `image` (as in [`Measure::image`]).
+	pub fn measure_at(&self, image: usize, x: usize, y: usize) -> Option<&Measure> {
+		self.all_measures()
+			.iter()
+			.find(|m| m.image == image && m.contains(x, y))
+	}
+
+	/// Every measure on page image `image` (as in [`Measure::image`]), in reading order.
+	pub fn measures_on_image(&self, image: usize) -> Vec<&Measure> {
+		self.measures_in_reading_order()
+			.into_iter()
+			.filter(|m| m.image == image)
+			.collect()
+	}
+
+	/// Every measure in the piece in reading order: by page image, then by system from top to
+	/// bottom, then from left to right within each system.
+	///
+	/// Measures in the same system rarely have exactly the same `y_top`, so a measure is
+	/// considered part of a system if its vertical center falls within the first measure of it.
+	pub fn measures_in_reading_order(&self) -> Vec<&Measure> {
+		let mut measures = self.all_measures().iter().collect::<Vec<_>>();
+		measures.sort_by_key(|m| (m.image, m.y_top, m.x_left));
+
+		let mut ordered = Vec::with_capacity(measures.len());
+		let mut system = Vec::<&Measure>::new();
+		for m in measures {
+			if let Some(first) = system.first() {
+				if first.image != m.image || !(first.y_top..=first.y_bottom).contains(&m.center().1) {
+					system.sort_by_key(|m| m.x_left);
+					ordered.append(&mut system);
+				}
+			}
+			system.push(m);
+		}
+		system.sort_by_key(|m| m.x_left);
+		ordered.append(&mut system);
+		ordered
+	}
+
+	/// The measure that follows `current` in reading order.
+	pub fn next_measure(&self, current: &Measure) -> Option<&Measure> {
+		let order = self.measures_in_reading_order();
+		let i = order.iter().position(|m| *m == current)?;
+		order.get(i + 1).copied()
+	}
+
+	/// The measure that precedes `current` in reading order.
+	pub fn previous_measure(&self, current: &Measure) -> Option<&Measure> {
+		let order = self.measures_in_reading_order();
+		let i = order.iter().position(|m| *m == current)?;
+		order.get(i.checked_sub(1)?).copied()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Information, Pages};
+
+	fn measure(movement: usize, number: usize, image: usize, [x_left, y_top, x_right, y_bottom]: [usize; 4]) -> Measure {
+		Measure {
+			number,
+			number_of_measures: 1,
+			movement,
+			image,
+			x_left,
+			x_right,
+			y_top,
+			y_bottom,
+		}
+	}
+
+	fn piece(measures: Vec<Measure>) -> Piece {
+		Piece {
+			information: Information {
+				identifier: "MR0".into(),
+				title: "Measures".into(),
+				creator: None,
+				description: None,
+				subject: None,
+				publisher: None,
+				copyright: None,
+			},
+			pages: Pages { page: vec![] },
+			measures: Some(Measures { measure: Some(measures) }),
+			parts: None,
+			recordings: None,
+		}
+	}
+
+	/// Two systems on page 1 with slightly ragged tops, a multi-measure rest, and a second movement
+	/// on page 2. Stored out of order on purpose.
+	fn example() -> Piece {
+		piece(vec![
+			measure(1, 4, 1, [300, 210, 500, 400]),
+			measure(1, 1, 1, [0, 0, 200, 180]),
+			Measure { number_of_measures: 8, ..measure(1, 5, 1, [500, 205, 700, 400]) },
+			measure(1, 3, 1, [0, 200, 300, 400]),
+			measure(1, 2, 1, [200, 8, 400, 190]),
+			measure(2, 1, 2, [0, 0, 300, 200]),
+		])
+	}
+
+	#[test]
+	fn multi_measure_rest() {
+		let piece = example();
+		let rest = piece.measure(1, 9).unwrap();
+		assert_eq!(rest.number, 5);
+		assert_eq!(rest.numbers(), 5..=12);
+		assert_eq!(piece.measure(1, 12), Some(rest));
+		assert_eq!(piece.measure(1, 13), None);
+	}
+
+	#[test]
+	fn lookup_by_number() {
+		let piece = example();
+		assert_eq!(piece.measure(1, 3).map(|m| m.x_right), Some(300));
+		assert_eq!(piece.measure(2, 1).map(|m| m.image), Some(2));
+		assert_eq!(piece.measure(3, 1), None);
+	}
+
+	#[test]
+	fn lookup_by_point() {
+		let piece = example();
+		assert_eq!(piece.measure_at(1, 250, 100).map(|m| m.number), Some(2));
+		assert_eq!(piece.measure_at(1, 600, 300).map(|m| m.number), Some(5));
+		assert_eq!(piece.measure_at(2, 250, 100).map(|m| m.movement), Some(2));
+		assert_eq!(piece.measure_at(1, 900, 100), None);
+	}
+
+	#[test]
+	fn reading_order() {
+		let piece = example();
+		let order = piece
+			.measures_in_reading_order()
+			.into_iter()
+			.map(|m| (m.movement, m.number))
+			.collect::<Vec<_>>();
+		assert_eq!(order, [(1, 1), (1, 2), (1, 3), (1, 4), (1, 5), (2, 1)]);
+
+		let first = piece.measure(1, 1).unwrap();
+		assert_eq!(piece.previous_measure(first), None);
+		assert_eq!(piece.next_measure(first).map(|m| m.number), Some(2));
+		let rest = piece.measure(1, 5).unwrap();
+		assert_eq!(piece.next_measure(rest).map(|m| m.movement), Some(2));
+		assert_eq!(piece.measures_on_image(2).len(), 1);
+	}
+
+	#[test]
+	fn no_measures() {
+		let piece = Piece { measures: None, ..example() };
+		assert!(piece.measures_in_reading_order().is_empty());
+		assert_eq!(piece.measure(1, 1), None);
+	}
+}
@@ -0,0 +1,126 @@
+use crate::{parse_xml, to_xml, MRSError, MRSError::*, Piece, Recordings, INFO_XML_ROOT};
+use quick_xml::{events::Event, DeError, Reader};
+use std::ops::Range;
+
+/// Element holding every recording in `info.xml`
+const RECORDINGS_ELEMENT: &str = "recordings";
+
+impl Recordings {
+	/// Keep `xml`, which must be a single `<recordings>` element, as it is.
+	pub fn from_xml(xml: impl Into<String>) -> Result<Self, MRSError> {
+		let xml = xml.into().trim().to_owned();
+		match find_element(&xml, 0, RECORDINGS_ELEMENT)? {
+			Some(span) if span == (0..xml.len()) => Ok(Self { xml }),
+			_ => Err(XmlErr(DeError::Custom(format!("expected a single <{RECORDINGS_ELEMENT}> element")))),
+		}
+	}
+
+	/// The section exactly as it was read.
+	pub fn as_xml(&self) -> &str {
+		&self.xml
+	}
+}
+
+/// Parse `info.xml`, keeping its `<recordings>` section as it is.
+pub(crate) fn parse_info(buf: &[u8]) -> Result<Piece, MRSError> {
+	let mut piece = parse_xml::<Piece>(buf)?;
+	// `parse_xml` has already checked that it is UTF-8.
+	let xml = std::str::from_utf8(buf).map_err(|e| XmlErr(DeError::Custom(e.to_string())))?;
+	piece.recordings = find_element(xml, 1, RECORDINGS_ELEMENT)?.map(|span| Recordings { xml: xml[span].to_owned() });
+	Ok(piece)
+}
+
+/// Write `info.xml`, with its `<recordings>` section exactly as it was read.
+pub(crate) fn info_to_xml(piece: &Piece) -> Result<String, MRSError> {
+	let mut xml = to_xml(INFO_XML_ROOT, piece)?;
+	if let Some(Recordings { xml: recordings }) = &piece.recordings {
+		let end = xml
+			.rfind(&format!("</{INFO_XML_ROOT}>"))
+			.ok_or_else(|| XmlErr(DeError::Custom(format!("no </{INFO_XML_ROOT}> to put recordings before"))))?;
+		xml.insert_str(end, &format!("\t{recordings}\n"));
+	}
+	Ok(xml)
+}
+
+/// Where the first `name` element nested `depth` elements deep in `xml` is, including its tags.
+fn find_element(xml: &str, depth: usize, name: &str) -> Result<Option<Range<usize>>, MRSError> {
+	let mut reader = Reader::from_str(xml);
+	let mut current = 0;
+	loop {
+		let start = reader.buffer_position();
+		match reader.read_event().map_err(|e| XmlErr(DeError::InvalidXml(e)))? {
+			Event::Start(e) if current == depth && e.name().as_ref() == name.as_bytes() => {
+				reader.read_to_end(e.name()).map_err(|e| XmlErr(DeError::InvalidXml(e)))?;
+				return Ok(Some(start..reader.buffer_position()));
+			}
+			Event::Empty(e) if current == depth && e.name().as_ref() == name.as_bytes() => {
+				return Ok(Some(start..reader.buffer_position()));
+			}
+			Event::Start(_) => current += 1,
+			Event::End(_) => current = current.saturating_sub(1),
+			Event::Eof => return Ok(None),
+			_ => {}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use test_log::test;
+
+	const INFO: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<piece>
+	<information>
+		<identifier>MR0</identifier>
+		<title>Recorded</title>
+	</information>
+	<pages>
+		<page><number>1</number><image>1</image><pageturn>whole</pageturn></page>
+	</pages>
+	<recordings>
+		<recording kind="rehearsal">
+			<name>Rehearsal 2024-01-14</name>
+			<file>file:///music/bourree.m4a</file>
+			<offset>2.5</offset>
+			<measure><number>1</number><movement>1</movement><time>0</time></measure>
+			<measure><number>2</number><movement>1</movement><time>1.75</time></measure>
+			<measure><number>3</number><time>3.5</time></measure>
+			<page><number>1</number><time>0</time></page>
+			<waveform samples="3">0.1 0.4 0.2</waveform>
+		</recording>
+		<recording>
+			<file>bourree-slow.mp3</file>
+		</recording>
+	</recordings>
+</piece>
+"#;
+
+	#[test]
+	fn kept_as_read() {
+		let piece = parse_info(INFO.as_bytes()).unwrap();
+		let recordings = piece.recordings.as_ref().unwrap();
+		assert!(recordings.as_xml().starts_with("<recordings>"));
+		assert!(recordings.as_xml().ends_with("</recordings>"));
+
+		let xml = info_to_xml(&piece).unwrap();
+		let read = &INFO[INFO.find("<recordings>").unwrap()..INFO.find("</piece>").unwrap()];
+		assert_eq!(recordings.as_xml(), read.trim_end());
+		assert!(xml.contains(recordings.as_xml()), "{xml}");
+		assert!(xml.contains(r#"<waveform samples="3">0.1 0.4 0.2</waveform>"#));
+		assert!(xml.contains(r#"<recording kind="rehearsal">"#));
+		assert_eq!(parse_info(xml.as_bytes()).unwrap(), piece);
+	}
+
+	#[test]
+	fn empty_and_missing() {
+		assert_eq!(Recordings::from_xml(" <recordings/>\n").unwrap().as_xml(), "<recordings/>");
+		assert!(Recordings::from_xml("<recording/>").is_err());
+		assert!(Recordings::from_xml("<recordings/><recordings/>").is_err());
+
+		let without = INFO.replace(&INFO[INFO.find("\t<recordings>").unwrap()..INFO.find("</piece>").unwrap()], "");
+		let piece = parse_info(without.as_bytes()).unwrap();
+		assert_eq!(piece.recordings, None);
+		assert!(!info_to_xml(&piece).unwrap().contains("recordings"));
+	}
+}
@@ -0,0 +1,207 @@
+use crate::{header::Header, MRSError, MRSError::*, PageImages};
+use log::{error, info, warn};
+use std::{
+	ffi::OsStr,
+	fmt::{Display, Formatter},
+	path::Path,
+};
+
+/// Everything unexpected that was found while loading an archive.
+///
+/// Loading never stops at a damaged entry, so an archive with problems can still be opened.
+/// This report is how callers find out what was skipped or couldn't be read.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+	/// How the archive started. Anything but [`Header::Zip`] means other programs may not be able
+	/// to open the file until it is [repaired](crate::header::repair).
+	pub header: Header,
+	pub diagnostics: Vec<Diagnostic>,
+}
+
+impl LoadReport {
+	/// Whether nothing unexpected was found.
+	pub fn is_clean(&self) -> bool {
+		self.diagnostics.is_empty()
+	}
+
+	/// Whether any data could not be loaded.
+	pub fn has_errors(&self) -> bool {
+		self.errors().next().is_some()
+	}
+
+	pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+		self.diagnostics.iter().filter(|d| d.severity == Severity::Error)
+	}
+
+	pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+		self.diagnostics.iter().filter(|d| d.severity == Severity::Warning)
+	}
+
+	pub(crate) fn push(&mut self, index: usize, entry: impl Into<String>, severity: Severity, error: MRSError) {
+		self.diagnostics.push(Diagnostic {
+			index,
+			entry: entry.into(),
+			severity,
+			error,
+		});
+	}
+
+	/// Log every diagnostic, for callers that don't otherwise handle them.
+	pub fn log(&self, mrs_filename: impl Display) {
+		if self.header.needs_repair() {
+			info!("`{mrs_filename}` has a MusicReader header ({:?}); it was read as a zip archive", self.header);
+		}
+		for d in &self.diagnostics {
+			match d.severity {
+				Severity::Warning => warn!("{d} in `{mrs_filename}`"),
+				Severity::Error => error!("{d} in `{mrs_filename}`"),
+			}
+		}
+	}
+}
+
+/// Something unexpected about a single archive entry.
+#[derive(Debug)]
+pub struct Diagnostic {
+	/// Index of the entry in the archive
+	pub index: usize,
+	/// Name of the entry in the archive
+	pub entry: String,
+	pub severity: Severity,
+	pub error: MRSError,
+}
+
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: entry {} (`{}`): {}", self.severity, self.index, self.entry, self.error)
+	}
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	/// The entry was ignored, but nothing MusicReader uses was lost.
+	Warning,
+	/// Some of the piece's data could not be loaded.
+	Error,
+}
+
+impl Display for Severity {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Self::Warning => "warning",
+			Self::Error => "error",
+		})
+	}
+}
+
+/// What an archive entry holds, according to its name.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum EntryKind {
+	/// One of the [layers](PageImages::layers) of a page, which is numbered from 1.
+	Image { layer: &'static str, page_num: usize },
+	Info,
+	Bookmarks,
+}
+
+/// Work out what an archive entry holds from its name, or why it can't be used.
+pub(crate) fn classify_entry(entry_path: &Path) -> Result<EntryKind, (Severity, MRSError)> {
+	let unexpected = |severity, msg: String| Err((severity, UnexpectedEntry(msg)));
+
+	let Some(stem) = entry_path.file_stem().and_then(OsStr::to_str) else {
+		return unexpected(Severity::Warning, "missing file stem (is a sub-directory?)".into());
+	};
+
+	match entry_path.extension().and_then(OsStr::to_str) {
+		Some("png") => {
+			let Some((stem, page_num)) = stem.rsplit_once('-') else {
+				return unexpected(Severity::Warning, "unexpected image file name".into());
+			};
+			let page_num = page_num
+				.parse::<usize>()
+				.map_err(|e| (Severity::Error, PageNumErr(e)))?;
+			if page_num == 0 {
+				return unexpected(Severity::Error, "page numbers start at 1".into());
+			}
+			match PageImages::new().layers().into_iter().find(|(layer, _)| *layer == stem) {
+				Some((layer, _)) => Ok(EntryKind::Image { layer, page_num }),
+				None => unexpected(Severity::Warning, format!("unexpected file stem `{stem}`")),
+			}
+		}
+		Some("xml") => match stem {
+			"info" => Ok(EntryKind::Info),
+			"bookmarks" => Ok(EntryKind::Bookmarks),
+			other => unexpected(Severity::Warning, format!("unexpected xml file `{other}`")),
+		},
+		Some(other) => unexpected(Severity::Warning, format!("unknown extension `{other}`")),
+		None => unexpected(Severity::Warning, "missing extension".into()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{tests::temp_path, MRSFile};
+	use async_mrs::{Compression, ZipEntryBuilder};
+	use test_log::test;
+
+	#[test]
+	fn classify() {
+		let classify = |name: &str| classify_entry(Path::new(name));
+		assert_eq!(classify("page-3.png").ok(), Some(EntryKind::Image { layer: "page", page_num: 3 }));
+		assert_eq!(
+			classify("annotations-local-12.png").ok(),
+			Some(EntryKind::Image { layer: "annotations-local", page_num: 12 })
+		);
+		assert_eq!(classify("info.xml").ok(), Some(EntryKind::Info));
+		assert!(matches!(classify("page-x.png"), Err((Severity::Error, PageNumErr(_)))));
+		assert!(matches!(classify("page-0.png"), Err((Severity::Error, UnexpectedEntry(_)))));
+		assert!(matches!(classify("cover-1.png"), Err((Severity::Warning, UnexpectedEntry(_)))));
+		assert!(matches!(classify("notes.txt"), Err((Severity::Warning, UnexpectedEntry(_)))));
+		assert!(matches!(classify("README"), Err((Severity::Warning, UnexpectedEntry(_)))));
+	}
+
+	#[test(tokio::test)]
+	async fn damaged_entries_are_reported() {
+		let path = temp_path("damaged.mrs");
+		let file = tokio::fs::File::create(&path).await.unwrap();
+		let mut writer = async_mrs::tokio::write::ZipFileWriter::with_tokio(file);
+		let entries: [(&str, &[u8]); 5] = [
+			("info.xml", b"<piece><information>"),
+			("page-1.png", b"not checked until decoding"),
+			("page-two.png", b""),
+			("thumbnail-1.png", b""),
+			("notes.txt", b"hello"),
+		];
+		for (name, data) in entries {
+			let entry = ZipEntryBuilder::new(name.to_owned().into(), Compression::Stored);
+			writer.write_entry_whole(entry, data).await.unwrap();
+		}
+		writer.close().await.unwrap();
+
+		let (file, report) = MRSFile::load_with_report(&path).await.unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		assert_eq!(file.pages.len(), 1);
+		assert!(file.pages[0].page.is_ok());
+		assert!(file.pages[0].thumbnail.is_ok());
+		assert!(matches!(file.info, Err(XmlErr(_))));
+		assert!(matches!(file.bookmarks, Err(Missing)));
+
+		let summary = report
+			.diagnostics
+			.iter()
+			.map(|d| (d.index, d.entry.as_str(), d.severity))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			summary,
+			[
+				(0, "info.xml", Severity::Error),
+				(2, "page-two.png", Severity::Error),
+				(4, "notes.txt", Severity::Warning),
+			]
+		);
+		assert!(matches!(report.diagnostics[1].error, PageNumErr(_)));
+		assert!(report.has_errors());
+		assert_eq!(report.warnings().count(), 1);
+	}
+}
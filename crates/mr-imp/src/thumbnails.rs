@@ -0,0 +1,182 @@
+use crate::{MRSError, MRSFile, PageImage, PageImages};
+use image::{io::Reader, GenericImageView, ImageOutputFormat};
+use std::io::Cursor;
+
+/// Width of the thumbnails made for files that have none of their own to [match](thumbnail_size).
+pub const THUMBNAIL_WIDTH: u32 = 150;
+
+/// The box that the thumbnails already in `pages` were scaled to fit, as `[width, height]`, so
+/// that regenerated thumbnails match them. If none of them can be read, the box is
+/// [`THUMBNAIL_WIDTH`] wide and as tall as the tallest page needs at that width.
+///
+/// Only the image headers are read, so this is cheap even for files with many pages.
+pub fn thumbnail_size(pages: &[PageImages]) -> [u32; 2] {
+	let dimensions = |image: &PageImage| match image {
+		PageImage::Png(data) => Reader::new(Cursor::new(data)).with_guessed_format().ok()?.into_dimensions().ok(),
+		PageImage::DynImg(img) => Some(img.dimensions()),
+	};
+	let existing = pages
+		.iter()
+		.filter_map(|images| dimensions(images.thumbnail.as_ref().ok()?))
+		.reduce(|(w1, h1), (w2, h2)| (w1.max(w2), h1.max(h2)));
+	if let Some((width, height)) = existing {
+		return [width, height];
+	}
+	let height = pages
+		.iter()
+		.filter_map(|images| dimensions(images.page.as_ref().ok()?))
+		.filter(|&(width, _)| width > 0)
+		.map(|(width, height)| (u64::from(THUMBNAIL_WIDTH) * u64::from(height)).div_ceil(u64::from(width)))
+		.max()
+		.map_or(THUMBNAIL_WIDTH, |height| height.clamp(1, u64::from(u32::MAX)) as u32);
+	[THUMBNAIL_WIDTH, height]
+}
+
+impl PageImages {
+	/// Whether the thumbnail is missing or can't be decoded.
+	pub fn thumbnail_needs_regenerating(&self) -> bool {
+		match &self.thumbnail {
+			Ok(thumbnail) => thumbnail.decode().is_err(),
+			Err(_) => true,
+		}
+	}
+
+	/// Make a thumbnail from the page image, scaled to fit within `size` (usually the
+	/// [file's](thumbnail_size)).
+	pub fn generate_thumbnail(&self, [width, height]: [u32; 2]) -> Result<PageImage, MRSError> {
+		let page = self.page.as_ref().map_err(MRSError::summarize)?.decode()?;
+		let mut data = Vec::new();
+		page.thumbnail(width, height)
+			.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+			.map_err(MRSError::ImageErr)?;
+		Ok(PageImage::Png(data))
+	}
+
+	/// Replace the thumbnail with one [generated](Self::generate_thumbnail) from the page image if
+	/// it is [missing or corrupt](Self::thumbnail_needs_regenerating), so that it is written when
+	/// the file is saved. Returns whether it was replaced. On failure the old thumbnail is left as
+	/// it was.
+	///
+	/// Decoding the page can take a while, so avoid calling this on an async or UI thread.
+	pub fn regenerate_thumbnail(&mut self, size: [u32; 2]) -> Result<bool, MRSError> {
+		if !self.thumbnail_needs_regenerating() {
+			return Ok(false);
+		}
+		self.thumbnail = Ok(self.generate_thumbnail(size)?);
+		Ok(true)
+	}
+}
+
+impl MRSFile {
+	/// [Regenerate](PageImages::regenerate_thumbnail) every missing or corrupt thumbnail, to match
+	/// the file's other thumbnails. They are written when the file is saved; use
+	/// [`missing_thumbnails`](Self::missing_thumbnails) to show them without changing the file.
+	///
+	/// Returns the index of each page whose thumbnail needed regenerating, along with why it
+	/// couldn't be if that failed (usually because the page image itself is missing or corrupt).
+	pub fn regenerate_thumbnails(&mut self) -> Vec<(usize, Result<(), MRSError>)> {
+		let size = thumbnail_size(&self.pages);
+		self.pages
+			.iter_mut()
+			.enumerate()
+			.filter_map(|(i, images)| match images.regenerate_thumbnail(size) {
+				Ok(false) => None,
+				Ok(true) => Some((i, Ok(()))),
+				Err(e) => Some((i, Err(e))),
+			})
+			.collect()
+	}
+
+	/// [Generate](PageImages::generate_thumbnail) a thumbnail for every page whose thumbnail is
+	/// missing or corrupt, leaving the file as it is so that they aren't saved.
+	pub fn missing_thumbnails(&self) -> Vec<(usize, Result<PageImage, MRSError>)> {
+		let size = thumbnail_size(&self.pages);
+		self.pages
+			.iter()
+			.enumerate()
+			.filter(|(_, images)| images.thumbnail_needs_regenerating())
+			.map(|(i, images)| (i, images.generate_thumbnail(size)))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		tests::{synthetic_file, temp_path},
+		MRSError::*,
+	};
+	use image::{DynamicImage, GenericImageView, RgbaImage};
+	use test_log::test;
+
+	fn page(width: u32, height: u32) -> PageImages {
+		let page = DynamicImage::ImageRgba8(RgbaImage::new(width, height));
+		PageImages { page: Ok(PageImage::DynImg(page)), ..PageImages::new() }
+	}
+
+	#[test]
+	fn keeps_aspect_ratio() {
+		let cases = [(page(1700, 2200), [150, 195], (150, 194)), (page(2200, 1700), [150, 116], (150, 116))];
+		for (page, size, dimensions) in cases {
+			assert_eq!(thumbnail_size(std::slice::from_ref(&page)), size);
+			let thumbnail = page.generate_thumbnail(size).unwrap().decode().unwrap();
+			assert_eq!(thumbnail.dimensions(), dimensions);
+		}
+		// The box fits the tallest page, so that every thumbnail is as wide.
+		assert_eq!(thumbnail_size(&[page(2200, 1700), page(1700, 2200)]), [150, 195]);
+		assert_eq!(thumbnail_size(&[PageImages::new()]), [THUMBNAIL_WIDTH, THUMBNAIL_WIDTH]);
+	}
+
+	#[test]
+	fn matches_existing_thumbnails() {
+		let mut pages = vec![page(1700, 2200), page(1700, 2200), page(2200, 1700)];
+		pages[0].thumbnail = pages[0].generate_thumbnail([100, 300]);
+		pages[1].thumbnail = Ok(PageImage::Png(b"not a png".to_vec()));
+		pages[2].thumbnail = pages[2].generate_thumbnail([300, 100]);
+		// 100×129 and 129×100, so the box is at least 129×129
+		assert_eq!(thumbnail_size(&pages), [129, 129]);
+
+		let mut file = MRSFile { pages, ..synthetic_file() };
+		file.regenerate_thumbnails();
+		assert_eq!(file.pages[1].thumbnail.as_ref().unwrap().decode().unwrap().dimensions(), (100, 129));
+	}
+
+	#[test]
+	fn only_bad_thumbnails_are_replaced() {
+		let mut file = synthetic_file();
+		file.pages[0].thumbnail = Ok(PageImage::Png(b"not a png".to_vec()));
+		file.pages.push(PageImages::new());
+
+		let results = file.regenerate_thumbnails();
+		let results = results.iter().map(|(i, res)| (*i, res.is_ok())).collect::<Vec<_>>();
+		assert_eq!(results, [(0, true), (1, true), (2, false)]);
+		assert!(matches!(file.pages[0].thumbnail, Ok(PageImage::Png(_))));
+		assert!(!file.pages[0].thumbnail_needs_regenerating());
+		assert!(matches!(file.pages[2].thumbnail, Err(Missing)));
+
+		let size = thumbnail_size(&file.pages);
+		assert!(!file.pages[0].regenerate_thumbnail(size).unwrap());
+	}
+
+	#[test(tokio::test)]
+	async fn only_regenerated_thumbnails_are_saved() {
+		let mut file = synthetic_file();
+		let missing = file.missing_thumbnails();
+		assert!(matches!(&missing[..], [(1, Ok(_))]));
+		assert!(file.pages[1].thumbnail.is_err());
+
+		for regenerate in [false, true] {
+			if regenerate {
+				file.regenerate_thumbnails();
+			}
+			let path = temp_path(&format!("thumbnails-{regenerate}.mrs"));
+			file.save(&path).await.unwrap();
+			let saved = MRSFile::load(&path).await.unwrap();
+			std::fs::remove_file(&path).unwrap();
+			assert_eq!(saved.pages[1].thumbnail.is_ok(), regenerate);
+			// Thumbnails that were loaded are always kept
+			assert!(saved.pages[0].thumbnail.is_ok());
+		}
+	}
+}
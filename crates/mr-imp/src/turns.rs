@@ -0,0 +1,140 @@
+use crate::{Page, PageTurn, Piece};
+
+/// What is on screen at one step of a paged presentation. Image numbers are as in [`Page::image`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PageView {
+	/// A single page image, shown in full.
+	Whole { image: usize },
+	/// Halfway through a half-page turn: the part of the next page above `split` has replaced the
+	/// part of the current page above `split`, while the rest of the current page is still shown
+	/// below it.
+	Split {
+		/// The next page, whose top is shown
+		top: usize,
+		/// The current page, whose bottom is shown
+		bottom: usize,
+		/// Fraction of the page height at which the two images meet
+		split: f32,
+	},
+}
+
+impl PageView {
+	/// The image the reader is (or is about to be) reading from.
+	pub fn reading_image(&self) -> usize {
+		match *self {
+			Self::Whole { image } => image,
+			Self::Split { bottom, .. } => bottom,
+		}
+	}
+}
+
+/// Where a half-page turn splits the page if `halfpage` isn't specified.
+pub const DEFAULT_HALFPAGE: f32 = 0.5;
+
+impl Page {
+	/// Where a half-page turn away from this page splits it, as a fraction of the page height.
+	pub fn split(&self) -> f32 {
+		self.halfpage.unwrap_or(DEFAULT_HALFPAGE).clamp(0.0, 1.0)
+	}
+}
+
+impl Piece {
+	/// The pages in display order.
+	pub fn pages_in_order(&self) -> Vec<&Page> {
+		let mut pages = self.pages.page.iter().collect::<Vec<_>>();
+		pages.sort_by_key(|page| page.number);
+		pages
+	}
+
+	/// Every step of turning through the piece one page at a time.
+	///
+	/// A page's `pageturn` describes the turn *away* from it: after a [`PageTurn::Whole`] page the
+	/// next page is shown in full, while after a [`PageTurn::Half`] page there is an intermediate
+	/// [`PageView::Split`] step at the page's `halfpage` split point. The last page's turn is ignored.
+	pub fn page_views(&self) -> Vec<PageView> {
+		let pages = self.pages_in_order();
+		let mut views = Vec::with_capacity(pages.len() * 2);
+		for (i, page) in pages.iter().enumerate() {
+			views.push(PageView::Whole { image: page.image });
+			let Some(next) = pages.get(i + 1) else { break };
+			if page.pageturn == PageTurn::Half {
+				views.push(PageView::Split {
+					top: next.image,
+					bottom: page.image,
+					split: page.split(),
+				});
+			}
+		}
+		views
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Information, Pages};
+
+	fn piece(pages: Vec<Page>) -> Piece {
+		Piece {
+			information: Information {
+				identifier: "MR0".into(),
+				title: "Turns".into(),
+				creator: None,
+				description: None,
+				subject: None,
+				publisher: None,
+				copyright: None,
+			},
+			pages: Pages { page: pages },
+			measures: None,
+			parts: None,
+			recordings: None,
+		}
+	}
+
+	fn page(number: usize, pageturn: PageTurn, halfpage: Option<f32>) -> Page {
+		Page {
+			number,
+			image: number,
+			pageturn,
+			halfpage,
+		}
+	}
+
+	#[test]
+	fn whole_turns() {
+		let piece = piece(vec![page(1, PageTurn::Whole, None), page(2, PageTurn::Whole, None)]);
+		assert_eq!(piece.page_views(), [PageView::Whole { image: 1 }, PageView::Whole { image: 2 }]);
+	}
+
+	#[test]
+	fn half_turns() {
+		let piece = piece(vec![
+			page(3, PageTurn::Half, None),
+			page(1, PageTurn::Half, Some(0.4)),
+			page(2, PageTurn::Whole, Some(0.7)),
+		]);
+		assert_eq!(
+			piece.page_views(),
+			[
+				PageView::Whole { image: 1 },
+				PageView::Split { top: 2, bottom: 1, split: 0.4 },
+				PageView::Whole { image: 2 },
+				PageView::Whole { image: 3 },
+			]
+		);
+		assert_eq!(piece.page_views()[1].reading_image(), 1);
+	}
+
+	#[test]
+	fn last_page_has_no_turn() {
+		let piece = piece(vec![page(1, PageTurn::Half, None)]);
+		assert_eq!(piece.page_views(), [PageView::Whole { image: 1 }]);
+	}
+
+	#[test]
+	fn split_is_clamped() {
+		assert_eq!(page(1, PageTurn::Half, Some(1.5)).split(), 1.0);
+		assert_eq!(page(1, PageTurn::Half, None).split(), DEFAULT_HALFPAGE);
+	}
+}
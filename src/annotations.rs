@@ -4,6 +4,7 @@ use dioxus::{
 	hooks::{use_memo, use_ref, use_state},
 };
 use freya::prelude::{mouse::MouseButton, pointer::PointerType, touch::TouchPhase, *};
+use library::strokes::{Stroke, StrokeSample, Strokes, Tool};
 use log::error;
 use skia_safe::{vertices, vertices::VertexMode, wrapper::PointerWrapper, BlendMode, Canvas, Color, Paint, Point, Vertices,};
 use std::{
@@ -11,7 +12,7 @@ use std::{
 		atomic::{AtomicBool, AtomicU64, Ordering::Relaxed},
 		Arc, Mutex,
 	},
-	time::{Duration, Instant},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 type Boxcar<T> = boxcar::Vec<T>;
@@ -28,8 +29,24 @@ static VARY_WIDTH: AtomicBool = AtomicBool::new(true);
 static VARY_ALPHA: AtomicBool = AtomicBool::new(false);
 static DRAW_INTERVAL_MILLIS: AtomicU64 = AtomicU64::new(1000 / 240);
 
+/// The strokes drawn on a page, shared between an [`AnnotationCanvas`] and whatever saves them.
+pub type SharedStrokes = Arc<Mutex<Strokes>>;
+
+/// Width of the pen on screen at full pressure, in pixels.
+const PEN_WIDTH: f64 = 12.0;
+
+/// Lets strokes be drawn over `children`.
+///
+/// If `strokes` is given, the canvas starts out showing them and adds every stroke drawn to
+/// them. Strokes are kept in page coordinates, where the canvas is `page_width` wide (its width
+/// in pixels by default) so that they line up with the page however large it is shown.
 #[component]
-pub fn AnnotationCanvas<'a>(cx: Scope<'a>, children: Element<'a>) -> Element {
+pub fn AnnotationCanvas<'a>(
+	cx: Scope<'a>,
+	children: Element<'a>,
+	strokes: Option<SharedStrokes>,
+	page_width: Option<f32>,
+) -> Element {
 	let last_update = use_state(cx, || Instant::now());
 
 	let pen_down = use_state(cx, || false);
@@ -37,12 +54,19 @@ pub fn AnnotationCanvas<'a>(cx: Scope<'a>, children: Element<'a>) -> Element {
 	let pipeline = use_ref(cx, || Arc::new(StrokePipeline::new()));
 	let dirty = use_state(cx, || true);
 
-	let canvas = use_canvas(cx, dirty, |dirty| {
+	use_memo(cx, &strokes.as_ref().map(|strokes| Arc::as_ptr(strokes) as usize), |_| {
+		pipeline.read().load(strokes.clone().unwrap_or_default());
+		dirty.set(true);
+	});
+
+	let canvas = use_canvas(cx, (dirty, page_width), |(dirty, page_width)| {
 		let pipeline = pipeline.read().clone();
 		last_update.set(Instant::now());
 		if *dirty.get() { dirty.set(false); }
-		Box::new(move |canvas, _fonts, _area| {
-			pipeline.draw(canvas);
+		Box::new(move |canvas, _fonts, area| {
+			let scale = page_width.map_or(1.0, |page_width| area.width() as f64 / page_width as f64);
+			let origin = CursorPoint::new(area.min_x() as f64, area.min_y() as f64);
+			pipeline.draw(canvas, PageTransform { origin, scale });
 		})
 	});
 
@@ -173,12 +197,48 @@ pub fn AnnotationCanvas<'a>(cx: Scope<'a>, children: Element<'a>) -> Element {
 
 #[derive(Debug, Copy, Clone)]
 struct Sample {
-	/// Position
+	/// Position in page coordinates
 	pos: CursorPoint,
 	/// Pen force
 	f: f32,
 }
 
+/// Maps page coordinates to the screen.
+#[derive(Debug, Copy, Clone)]
+struct PageTransform {
+	/// Where the top left of the page is on the screen
+	origin: CursorPoint,
+	/// Screen pixels per unit of page coordinates
+	scale: f64,
+}
+
+impl Default for PageTransform {
+	fn default() -> Self {
+		Self { origin: CursorPoint::new(0.0, 0.0), scale: 1.0 }
+	}
+}
+
+impl PageTransform {
+	fn to_page(&self, pos: CursorPoint) -> CursorPoint {
+		let offset = (pos - self.origin) / self.scale;
+		CursorPoint::new(offset.x, offset.y)
+	}
+}
+
+/// How a stroke looks, whatever shape it is.
+#[derive(Debug, Copy, Clone)]
+struct Pen {
+	/// In page coordinates
+	width: f32,
+	color: [u8; 4],
+}
+
+impl From<&Stroke> for Pen {
+	fn from(stroke: &Stroke) -> Self {
+		Self { width: stroke.width, color: stroke.color }
+	}
+}
+
 #[derive(Clone, Debug)]
 #[repr(transparent)]
 struct RefCntVerts(Vertices);
@@ -202,20 +262,49 @@ unsafe impl RefCnt for RefCntVerts {
 #[derive(Default, Debug)]
 struct StrokePipeline {
 	paint: Paint,
-	rendered: Arc<Mutex<Vec<Vertices>>>,
+	/// Multiplies with the page, for [highlighters](Tool::Highlighter).
+	highlighter_paint: Paint,
+	/// Meshes of finished strokes, along with the tool that drew each.
+	rendered: Arc<Mutex<Vec<(Vertices, Tool)>>>,
 	pending_quads: ArcSwapOption<Boxcar<Vertices>>,
 	in_progress_points: ArcSwapOption<Boxcar<Point>>,
 	in_progress_colors: ArcSwapOption<Boxcar<Color>>,
+	/// The strokes that `rendered` was built from, which finished strokes are added to.
+	strokes: Mutex<SharedStrokes>,
+	/// The stroke being drawn, and when it was started.
+	current: Mutex<Option<(Stroke, Instant)>>,
+	/// How the page was last drawn, to put incoming points in page coordinates.
+	transform: Mutex<PageTransform>,
 }
 
 impl StrokePipeline {
 	fn new() -> Self {
-		Self::default()
+		let mut highlighter_paint = Paint::default();
+		highlighter_paint.set_blend_mode(BlendMode::Multiply);
+		Self { highlighter_paint, ..Self::default() }
 	}
 
-	fn draw(&self, canvas: &Canvas) {
-		for stroke in &**self.rendered.lock().unwrap() {
-			canvas.draw_vertices(stroke, BlendMode::Modulate, &self.paint);
+	/// Replace everything drawn with `strokes`, rebuilding their meshes.
+	fn load(&self, strokes: SharedStrokes) {
+		let meshes = strokes
+			.lock()
+			.unwrap()
+			.strokes
+			.iter()
+			.filter_map(|stroke| Some((Self::mesh_for(stroke)?, stroke.tool)))
+			.collect();
+		*self.rendered.lock().unwrap() = meshes;
+		*self.strokes.lock().unwrap() = strokes;
+	}
+
+	fn draw(&self, canvas: &Canvas, transform: PageTransform) {
+		*self.transform.lock().unwrap() = transform;
+		canvas.save();
+		canvas.translate((transform.origin.x as f32, transform.origin.y as f32));
+		canvas.scale((transform.scale as f32, transform.scale as f32));
+		for (stroke, tool) in &**self.rendered.lock().unwrap() {
+			let paint = if *tool == Tool::Highlighter { &self.highlighter_paint } else { &self.paint };
+			canvas.draw_vertices(stroke, BlendMode::Modulate, paint);
 		}
 		for (_, stroke) in self
 			.pending_quads
@@ -225,6 +314,7 @@ impl StrokePipeline {
 		{
 			canvas.draw_vertices(stroke, BlendMode::Modulate, &self.paint);
 		}
+		canvas.restore();
 	}
 
 	/// Merge all pending quads and already-merged progress into a new `Vertices` object. Don't call too often,
@@ -269,7 +359,8 @@ impl StrokePipeline {
 				}
 				colors[i] = *color;
 			}
-			self.rendered.lock().unwrap().push(builder.detach());
+			// Only pens are drawn live so far.
+			self.rendered.lock().unwrap().push((builder.detach(), Tool::Pen));
 			self.pending_quads.store(None);
 			break;
 		}
@@ -279,15 +370,29 @@ impl StrokePipeline {
 		static CAME_FROM: ArcSwapOption<Sample> = ArcSwapOption::const_empty();
 		static LAST_SAMPLE: ArcSwapOption<Sample> = ArcSwapOption::const_empty();
 
+		let transform = *self.transform.lock().unwrap();
 		match msg {
 			PathMsg::Start(pos, force) => {
-				CAME_FROM.store(None);
-				LAST_SAMPLE.store(Some(Arc::new(Sample {
-					pos,
+				let sample = Sample {
+					pos: transform.to_page(pos),
 					f: force as f32,
-				})));
+				};
+				CAME_FROM.store(None);
+				LAST_SAMPLE.store(Some(Arc::new(sample)));
 				self.in_progress_points.store(Some(Arc::new(Boxcar::new())));
 				self.in_progress_colors.store(Some(Arc::new(Boxcar::new())));
+				let started = SystemTime::now()
+					.duration_since(UNIX_EPOCH)
+					.map_or(0, |since| since.as_millis() as u64);
+				let stroke = Stroke {
+					tool: Tool::Pen,
+					color: [0, 0, 0, 255],
+					width: (PEN_WIDTH / transform.scale) as f32,
+					started,
+					samples: Vec::new(),
+				};
+				*self.current.lock().unwrap() = Some((stroke, Instant::now()));
+				self.record(sample);
 			}
 			PathMsg::Move(pos, force) => {
 				let last = LAST_SAMPLE.load();
@@ -295,19 +400,22 @@ impl StrokePipeline {
 					error!("trying to continue path that is not started");
 					return;
 				};
+				let Some(pen) = self.pen() else {
+					error!("trying to continue path with no stroke");
+					return;
+				};
+				let pos = transform.to_page(pos);
 				let dir = pos - last.pos;
-				let mut normal = Point::new(-dir.y as f32, dir.x as f32);
-				normal.normalize();
+				let normal = Self::normal(dir.x, dir.y);
 
 				let prev_dir = if let Some(came_from) = CAME_FROM.load().as_ref() {
 					last.pos - came_from.pos
 				} else {
 					dir
 				};
-				let mut prev_normal = Point::new(-prev_dir.y as f32, prev_dir.x as f32);
-				prev_normal.normalize();
+				let prev_normal = Self::normal(prev_dir.x, prev_dir.y);
 
-				let ([p1, p2], c1) = Self::verts_for(prev_normal, **last);
+				let ([p1, p2], c1) = Self::verts_for(prev_normal, **last, pen);
 
 				let Some(in_progress_points) = self.in_progress_points.load_full() else {
 					error!("trying to continue path with missing points");
@@ -321,14 +429,15 @@ impl StrokePipeline {
 					pos,
 					f: force as f32,
 				};
-				let ([p3, p4], c2) = Self::verts_for(normal, sample);
-				if dir.length() > 2.0 {
+				let ([p3, p4], c2) = Self::verts_for(normal, sample, pen);
+				if dir.length() * transform.scale > 2.0 {
 					// don't draw lines too short
 					self.push_verts(p3, p4, c2);
 				} else {
 					return;
 				}
 				self.gen_quad([p1, p2, p3, p4], [c1, c1, c2, c2]);
+				self.record(sample);
 				CAME_FROM.store(Some(last.clone()));
 				LAST_SAMPLE.store(Some(Arc::new(sample)));
 			}
@@ -341,14 +450,23 @@ impl StrokePipeline {
 					error!("trying to continue path with missing points");
 					return;
 				};
+				let Some(pen) = self.pen() else {
+					error!("trying to end path with no stroke");
+					return;
+				};
+				let pos = transform.to_page(pos);
 				let dir = pos - last.pos;
-				let mut normal = Point::new(-dir.y as f32, dir.x as f32);
-				normal.normalize();
+				let normal = Self::normal(dir.x, dir.y);
 
-				let ([p1, p2], color1) = Self::verts_for(normal, *last);
-				let ([p3, p4], color2) = Self::verts_for(normal, Sample { pos, f: 0.0 });
+				let sample = Sample { pos, f: 0.0 };
+				let ([p1, p2], color1) = Self::verts_for(normal, *last, pen);
+				let ([p3, p4], color2) = Self::verts_for(normal, sample, pen);
 				self.push_verts(p3, p4, color2);
 				self.gen_quad([p1, p2, p3, p4], [color1, color1, color2, color2]);
+				self.record(sample);
+				if let Some((stroke, _)) = self.current.lock().unwrap().take() {
+					self.strokes.lock().unwrap().lock().unwrap().strokes.push(stroke);
+				}
 				let this = self.clone();
 				tokio::spawn(async move {
 					this.finalize_stroke();
@@ -357,12 +475,62 @@ impl StrokePipeline {
 		}
 	}
 
-	fn verts_for(normal: Point, Sample { pos, f }: Sample) -> ([Point; 2], Color) {
+	fn pen(&self) -> Option<Pen> {
+		self.current.lock().unwrap().as_ref().map(|(stroke, _)| Pen::from(stroke))
+	}
+
+	/// Add a sample to the stroke being drawn.
+	fn record(&self, Sample { pos, f }: Sample) {
+		let mut current = self.current.lock().unwrap();
+		let Some((stroke, started)) = current.as_mut() else { return };
+		stroke.samples.push(StrokeSample {
+			x: pos.x as f32,
+			y: pos.y as f32,
+			pressure: f,
+			time: started.elapsed().as_millis() as u32,
+		});
+	}
+
+	/// Build the mesh of a finished stroke, the same way it was built while it was drawn.
+	fn mesh_for(stroke: &Stroke) -> Option<Vertices> {
+		let pen = Pen::from(stroke);
+		let samples = stroke
+			.samples
+			.iter()
+			.map(|sample| Sample {
+				pos: CursorPoint::new(sample.x as f64, sample.y as f64),
+				f: sample.pressure,
+			})
+			.collect::<Vec<_>>();
+		if samples.len() < 2 {
+			return None;
+		}
+		let mut points = Vec::with_capacity(samples.len() * 2);
+		let mut colors = Vec::with_capacity(samples.len() * 2);
+		for (i, sample) in samples.iter().enumerate() {
+			// Each sample's vertices are perpendicular to the segment leading to it, apart from the
+			// first which has no segment before it.
+			let dir = if i == 0 { samples[1].pos - sample.pos } else { sample.pos - samples[i - 1].pos };
+			let ([p1, p2], color) = Self::verts_for(Self::normal(dir.x, dir.y), *sample, pen);
+			points.extend([p1, p2]);
+			colors.extend([color, color]);
+		}
+		Some(Self::strip(&points, &colors))
+	}
+
+	fn normal(dx: f64, dy: f64) -> Point {
+		let mut normal = Point::new(-dy as f32, dx as f32);
+		normal.normalize();
+		normal
+	}
+
+	fn verts_for(normal: Point, Sample { pos, f }: Sample, pen: Pen) -> ([Point; 2], Color) {
 		let pos = Point::new(pos.x as f32, pos.y as f32);
 		let width_percent = if VARY_WIDTH.load(Relaxed) { f } else { 1.0 };
 		let alpha_percent = if VARY_ALPHA.load(Relaxed) { f } else { 1.0 };
-		let offset = normal * width_percent * 6.0;
-		let color = Color::from_argb((255.0 * alpha_percent) as u8, 0, 0, 0);
+		let offset = normal * width_percent * pen.width / 2.0;
+		let [r, g, b, a] = pen.color;
+		let color = Color::from_argb((a as f32 * alpha_percent) as u8, r, g, b);
 		([pos + offset, pos - offset], color)
 	}
 
@@ -377,19 +545,23 @@ impl StrokePipeline {
 		colors.push(color);
 	}
 
-	fn gen_quad(&self, points: [Point; 4], colors: [Color; 4]) {
+	fn strip(points: &[Point], colors: &[Color]) -> Vertices {
 		let mut builder = vertices::Builder::new(
 			VertexMode::TriangleStrip,
-			4,
+			points.len(),
 			0,
 			vertices::BuilderFlags::HAS_COLORS,
 		);
-		builder.positions().clone_from_slice(&points);
+		builder.positions().clone_from_slice(points);
 		builder
 			.colors()
 			.expect("colors should exist since we passed `BuilderFlags::HAS_COLORS`")
-			.clone_from_slice(&colors);
-		let verts = builder.detach();
+			.clone_from_slice(colors);
+		builder.detach()
+	}
+
+	fn gen_quad(&self, points: [Point; 4], colors: [Color; 4]) {
+		let verts = Self::strip(&points, &colors);
 		// CaS seems to not be possible here unless I figure out the missing trait bounds
 		match self.pending_quads.load().as_ref() {
 			None => self
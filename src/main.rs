@@ -3,18 +3,20 @@
 	windows_subsystem = "windows"
 )]
 
-use std::sync::Arc;
+use std::path::PathBuf;
 use freya::prelude::*;
-use tokio::sync::oneshot::error::TryRecvError;
 use tracing_subscriber::{filter, fmt, reload, prelude::*, Registry};
 use winit::platform::x11::WindowBuilderExtX11;
 use freya::events::keyboard::Code;
-use mr_imp::MRSFile;
-use crate::page_rendering::{OpenPiece, PieceView};
+use library::catalog::Library;
+use log::error;
+use crate::page_rendering::PieceView;
+use crate::search::{load_library, open, save_strokes, SearchScreen};
 use crate::settings::Settings;
 
 mod annotations;
 mod page_rendering;
+mod search;
 mod settings;
 
 type ReloadHandle = reload::Handle<filter::Filtered<fmt::Layer<Registry>, filter::LevelFilter, Registry>, Registry>;
@@ -60,26 +62,64 @@ fn app(cx: Scope) -> Element {
 		async move { state.log_reload_handle.modify(|layer| *layer.filter_mut() = filter).unwrap_or_else(|e| eprintln!("Failed to update log level: {e}")); }
 	});
 	
-	let rx = use_ref(cx, || {
-		let (tx, rx) = tokio::sync::oneshot::channel();
-		cx.spawn(async {
-			let file = MRSFile::load("crates/mr-imp/Menuet.mrs").await.unwrap();
-			tx.send(file).unwrap();
-		});
-		rx
+	let library = use_ref(cx, || None::<Library>);
+	use_effect(cx, (), |_| {
+		let (index, roots) = settings.with(|settings| (settings.library_index.clone(), settings.library_roots.clone()));
+		to_owned![library];
+		async move {
+			load_library(index, roots, |loaded| {
+				library.with_mut(|library| {
+					// Keep tags added on the search screen while the library was being refreshed,
+					// so that they are saved with it too.
+					for entry in library.iter().flat_map(Library::entries) {
+						loaded.set_tags(&entry.path, entry.tags.clone());
+					}
+					*library = Some(loaded.clone());
+				})
+			})
+			.await
+		}
 	});
 	
 	let open_piece = use_ref(cx, || None);
-	match rx.write_silent().try_recv() {
-		Ok(file) => { open_piece.set(Some(OpenPiece(Arc::new(file)))); },
-		Err(TryRecvError::Closed) => { if open_piece.read().is_none() { panic!("failed to open file") }}
-		Err(TryRecvError::Empty) => cx.needs_update(),
-	}
+	let searching = use_state(cx, || true);
 	if let Some(piece) = open_piece.read().as_ref() {
 		cx.provide_root_context(piece.clone());
 	}
 	
+	let onopen = move |path: PathBuf| {
+		searching.set(false);
+		let previous = open_piece.read().clone();
+		to_owned![open_piece, searching];
+		cx.spawn(async move {
+			if let Some(previous) = previous {
+				save_strokes(&previous).await;
+			}
+			match open(&path).await {
+				Ok(piece) => open_piece.set(Some(piece)),
+				Err(e) => {
+					error!("couldn't open `{}`: {e}", path.display());
+					searching.set(true);
+				}
+			}
+		});
+	};
+	// Open whatever was given on the command line straight away.
+	use_effect(cx, (), |_| {
+		if let Some(path) = settings.read().open.clone() {
+			onopen(path);
+		}
+		async {}
+	});
+	
 	let onkey = |e: Event<KeyboardData>| {
+		if e.code == Code::Slash && !*searching.get() {
+			searching.set(true);
+			// Keep what was drawn, in case the app is closed from the search screen.
+			if let Some(piece) = open_piece.read().clone() {
+				cx.spawn(async move { save_strokes(&piece).await });
+			}
+		}
 		// TODO: Change this to `Code::Backqoute` when event bubbling works.
 		// Or better yet, make a settings window.
 		if e.code == Code::Escape {
@@ -95,9 +135,20 @@ fn app(cx: Scope) -> Element {
 	render! {
 		rect {
 			onkeyup: onkey,
-			PieceView {
-				width: "100%",
-				height: "100%",
+			if *searching.get() {
+				rsx!(
+					SearchScreen {
+						library: library.clone(),
+						onopen: onopen,
+					}
+				)
+			} else {
+				rsx!(
+					PieceView {
+						width: "100%",
+						height: "100%",
+					}
+				)
 			}
 		}
 	}
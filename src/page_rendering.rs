@@ -1,80 +1,970 @@
 #![allow(non_snake_case)]
 
-use std::ops::Deref;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use freya::prelude::*;
-use image::GenericImageView;
-use mr_imp::{MRSFile, PageImage,};
-use crate::annotations::AnnotationCanvas;
+use freya::events::{keyboard::{Code, Key}, touch::TouchPhase};
+use log::{error, warn};
+use mr_imp::{Bookmark, MRSError::Missing, MRSFile, Measure, PageImage, PageImages, decode::{DecodeJob, Decoded}, turns::PageView};
+use skia_safe::{canvas::SrcRectConstraint, Canvas, Color, Paint, PaintStyle, Rect};
+use library::{
+	cache::{ImageCache, Stage},
+	pdf::DEFAULT_WIDTH,
+	strokes::Strokes,
+	ImageRef, Pages, PieceError,
+};
+use crate::annotations::{AnnotationCanvas, SharedStrokes};
+
+pub type SkiaImage = skia_safe::Image;
 
 #[component]
 pub fn PieceView<'a>(cx: Scope<'a>, width: &'a str, height: &'a str) -> Element {
 	let piece = cx.consume_context::<OpenPiece>();
 	
-	let image_datas = use_ref(cx, || vec![]);
+	let decoded = use_ref(cx, Vec::<Option<RenderedPage>>::new);
+	let annotations = use_ref(cx, Vec::<[Option<RenderedPage>; 2]>::new);
+	let page_views = use_ref(cx, Vec::<PageView>::new);
+	// Indices of the pages with a divider above them, announcing a piece of a setlist.
+	let dividers = use_ref(cx, Vec::<usize>::new);
+	let progress = use_state(cx, || (0, 0));
 	
-	piece.as_ref().map(|file| {
-		if image_datas.with(Vec::len) != file.pages.len() {
-			image_datas.write().resize(file.pages.len(), None);
-		}
-		for (i, images) in file.pages.iter().enumerate() {
-			if image_datas.with(|image_datas| image_datas[i].is_none()) {
-				image_datas.with_mut(|image_datas| {
-					image_datas[i] = Some(images.page.as_ref()
-						.map(|img| {
-							match img {
-								PageImage::Png(img) => img.clone(),
-								PageImage::DynImg(img) => img.pixels().flat_map(|(_, _, pixel)| pixel.0).collect::<Vec<_>>(),
-							}
-						})
-						.unwrap());
+	let (view_ref, view_size) = use_node(cx);
+	// Pages of a PDF are rasterized at the width they are shown at, and again once every page has
+	// been loaded if it changed. It is rounded up so that resizing the window doesn't rasterize
+	// them at every step.
+	let pdf_width = (view_size.area.width() / PDF_WIDTH_STEP).ceil().max(1.0) as u32 * PDF_WIDTH_STEP as u32;
+	
+	use_effect(cx, &piece.as_ref().map(OpenPiece::id), |_| {
+		to_owned![piece, decoded, annotations, page_views, dividers, progress];
+		async move {
+			let Some(piece) = piece else { return };
+			decoded.set(vec![None; piece.image_count()]);
+			annotations.set(vec![[None, None]; piece.image_count()]);
+			page_views.set(piece.page_views());
+			dividers.set(piece.dividers());
+			// Every page image before any annotations, so that the pages show up as soon as possible.
+			// Each image is keyed by its index among the shown pages and its index in `SHOWN_LAYERS`.
+			let images = SHOWN_LAYERS.iter().enumerate().flat_map(|(layer, stem)| {
+				let piece = &piece;
+				piece.page_images().enumerate().filter_map(move |(i, images)| {
+					let (_, image) = images.layers().into_iter().find(|(s, _)| s == stem)?;
+					let key = i * SHOWN_LAYERS.len() + layer;
+					match image {
+						Ok(PageImage::Png(data)) => Some((key, PageImage::Png(data.clone()))),
+						Ok(PageImage::DynImg(img)) => Some((key, PageImage::DynImg(img.clone()))),
+						// Most pages aren't annotated, and pages read through a cache are loaded below.
+						Err(Missing) if layer > 0 || piece.is_cached(i + 1) => None,
+						Err(e) => {
+							error!("missing {stem} image for {}: {e}", piece.describe(i + 1));
+							None
+						}
+					}
 				})
+			}).collect::<Vec<_>>();
+			// Copying into Skia images is done by the workers too, since full-size pages take a while.
+			let mut job = DecodeJob::spawn_default_with(images, |img| library::dynamic_to_rendered(&img));
+			let cached = piece.cached_pages();
+			let report = |(done, total): (usize, usize), loaded: usize| progress.set((done + loaded, total + cached.len()));
+			report(job.progress(), 0);
+			// Pages read through a cache are loaded one at a time while the others are decoded.
+			for (loaded, (image, cache, page)) in cached.iter().cloned().enumerate() {
+				match load_cached(cache, page, pdf_width).await {
+					Ok(img) => decoded.with_mut(|decoded| decoded[image - 1] = Some(RenderedPage(img))),
+					Err(e) => error!("failed to load {}: {e}", piece.describe(image)),
+				}
+				report(job.progress(), loaded + 1);
+			}
+			while let Some(Decoded { index, image }) = job.next().await {
+				let (i, layer) = (index / SHOWN_LAYERS.len(), index % SHOWN_LAYERS.len());
+				let stem = SHOWN_LAYERS[layer];
+				match image {
+					Ok(Some(img)) if layer == 0 => decoded.with_mut(|decoded| decoded[i] = Some(RenderedPage(img))),
+					Ok(Some(img)) => annotations.with_mut(|annotations| annotations[i][layer - 1] = Some(RenderedPage(img))),
+					Ok(None) => error!("failed to upload {stem} image for {}", piece.describe(i + 1)),
+					Err(e) => error!("failed to decode {stem} image for {}: {e}", piece.describe(i + 1)),
+				}
+				report(job.progress(), cached.len());
+			}
+		}
+	});
+	
+	let all_decoded = matches!(*progress.get(), (done, total) if total > 0 && done == total);
+	use_effect(cx, (piece.as_ref().map(OpenPiece::id), pdf_width, all_decoded), |(_, width, all_decoded)| {
+		to_owned![piece, decoded];
+		async move {
+			let Some(piece) = piece.filter(|_| all_decoded) else { return };
+			for (image, cache, page) in piece.pdf_pages() {
+				let shown = decoded.with(|decoded| Some(decoded.get(image - 1)?.as_ref()?.0.width()));
+				if shown == Some(width as i32) {
+					continue;
+				}
+				match load_cached(cache, page, width).await {
+					Ok(img) => decoded.with_mut(|decoded| {
+						if let Some(shown) = decoded.get_mut(image - 1) {
+							*shown = Some(RenderedPage(img));
+						}
+					}),
+					Err(e) => error!("failed to rasterize {}: {e}", piece.describe(image)),
+				}
 			}
 		}
 	});
+	let scroll_y = use_state(cx, || 0.0f32);
+	let goto_input = use_state(cx, || None::<String>);
+	// The measure last gone to, along with the index of the piece it is in.
+	let current_measure = use_ref(cx, || None::<(usize, Measure)>);
+	// The highlighted measure, along with the number of the shown page image it is on.
+	let highlight = use_state(cx, || None::<(usize, Measure, Instant)>);
+	let paged = use_state(cx, || false);
+	let turn = use_state(cx, || 0usize);
+	let bookmarks_open = use_state(cx, || false);
+	let layers_open = use_state(cx, || false);
+	let layer_styles = use_state(cx, || [LayerStyle::default(); 2]);
+	
+	let (view_width, view_height) = (view_size.area.width(), view_size.area.height());
+	let layout = dividers.with(|dividers| decoded.with(|pages| PageLayout::new(view_width, pages, dividers)));
+	let max_scroll = (layout.total_height() - view_height).max(0.0);
+	
+	// The page image being read, which decides which piece of a setlist measures and bookmarks are
+	// looked up in.
+	let viewed_image = if *paged.get() {
+		page_views.with(|views| views.get(*turn.get()).or(views.last()).map(turned_to))
+	} else {
+		layout.image_at(*scroll_y.get() + view_height / 2.0)
+	};
+	let viewed_part = viewed_image.and_then(|image| piece.as_ref()?.locate(image)).map_or(0, |(part, _)| part);
+	
+	// Bring row `y` (in image pixels) of shown page image `image` into view, returning whether it could be found.
+	let show = move |image: usize, y: usize| {
+		if *paged.get() {
+			let Some(i) = page_views.with(|views| views.iter().position(|view| *view == PageView::Whole { image })) else {
+				return false;
+			};
+			turn.set(i);
+		} else {
+			let pos = dividers.with(|dividers| {
+				decoded.with(|pages| PageLayout::new(view_width, pages, dividers).image_y(image, y, pages))
+			});
+			let Some(pos) = pos else {
+				return false;
+			};
+			scroll_y.set((pos - view_height / 2.0).clamp(0.0, max_scroll));
+		}
+		true
+	};
+	
+	// Go to `measure` of piece `part`, which is on shown page image `image`.
+	let go_to = move |part: usize, measure: &Measure, image: usize| {
+		if !show(image, measure.center().1) {
+			warn!("can't go to measure {} of movement {} on page image {}", measure.number, measure.movement, measure.image);
+			return;
+		}
+		current_measure.set(Some((part, measure.clone())));
+		let shown = (image, measure.clone(), Instant::now());
+		highlight.set(Some(shown.clone()));
+		to_owned![highlight];
+		cx.spawn(async move {
+			tokio::time::sleep(HIGHLIGHT_DURATION).await;
+			if *highlight.current() == Some(shown) {
+				highlight.set(None);
+			}
+		});
+	};
+	
+	let onkeydown = {
+		to_owned![piece];
+		move |e: KeyboardEvent| {
+			let part = piece.as_ref().and_then(|piece| piece.pieces().get(viewed_part));
+			let info = part.and_then(SetPiece::info);
+			// Only carry on from the last measure gone to while still reading the same piece.
+			let current = current_measure.read().clone().filter(|(part, _)| *part == viewed_part).map(|(_, m)| m);
+			let go_to_measure = |part: usize, measure: &Measure| {
+				match piece.as_ref().and_then(|piece| piece.global(part, measure.image)) {
+					Some(image) => go_to(part, measure, image),
+					None => warn!("measure {} of movement {} is on a page that isn't shown", measure.number, measure.movement),
+				}
+			};
+			let first_measure = |part: usize| {
+				let info = piece.as_ref()?.pieces().get(part)?.info()?;
+				info.measures_in_reading_order().first().map(|&measure| (part, measure.clone()))
+			};
+			match (e.code, goto_input.get()) {
+				(Code::KeyG, None) => goto_input.set(Some(String::new())),
+				(Code::KeyG, Some(_)) => goto_input.set(None),
+				(Code::Backspace, Some(input)) => {
+					let mut input = input.clone();
+					input.pop();
+					goto_input.set(Some(input));
+				}
+				(Code::Enter | Code::NumpadEnter, Some(input)) => {
+					goto_input.set(None);
+					let Some(info) = info else { return };
+					let current_movement = current.as_ref().map_or(1, |m| m.movement);
+					match parse_measure(input, current_movement) {
+						Some((movement, number)) => match info.measure(movement, number) {
+							Some(measure) => go_to_measure(viewed_part, measure),
+							None => warn!("no measure {number} in movement {movement}"),
+						},
+						None => warn!("`{input}` is not a measure number"),
+					}
+				}
+				(_, Some(input)) => {
+					if let Key::Character(c) = &e.key {
+						if c.chars().all(|c| c.is_ascii_digit() || c == '.') {
+							goto_input.set(Some(format!("{input}{c}")));
+						}
+					}
+				}
+				(Code::BracketRight, None) => {
+					let next = match (&current, info) {
+						(Some(current), Some(info)) => info.next_measure(current).map(|next| (viewed_part, next.clone())),
+						_ => first_measure(viewed_part),
+					};
+					// Carry on into the next piece of a setlist after the last measure of this one.
+					if let Some((part, next)) = next.or_else(|| first_measure(viewed_part + 1)) {
+						go_to_measure(part, &next)
+					}
+				}
+				(Code::BracketLeft, None) => {
+					let Some(info) = info else { return };
+					let prev = current.as_ref().and_then(|current| info.previous_measure(current).cloned());
+					if let Some(prev) = prev { go_to_measure(viewed_part, &prev) }
+				}
+				(Code::KeyP, None) => paged.set(!*paged.get()),
+				(Code::KeyB, None) => bookmarks_open.set(!*bookmarks_open.get()),
+				(Code::KeyA, None) => layers_open.set(!*layers_open.get()),
+				(Code::KeyL, None) => layer_styles.with_mut(|styles| styles[0].visible ^= true),
+				(Code::KeyR, None) => layer_styles.with_mut(|styles| styles[1].visible ^= true),
+				(Code::Space | Code::ArrowRight | Code::ArrowDown | Code::PageDown, None) if *paged.get() => {
+					let last = page_views.with(Vec::len).saturating_sub(1);
+					turn.set(usize::min(*turn.get() + 1, last));
+				}
+				(Code::ArrowLeft | Code::ArrowUp | Code::PageUp, None) if *paged.get() => {
+					turn.set(turn.get().saturating_sub(1));
+				}
+				_ => {}
+			}
+		}
+	};
+	
+	let onwheel = move |e: WheelEvent| {
+		scroll_y.set((*scroll_y.get() - e.get_delta_y() as f32).clamp(0.0, max_scroll));
+	};
+	
+	let highlighted = highlight.get().as_ref().map(|(image, measure, _)| (*image, measure));
+	let highlight_on = |image: usize| {
+		highlighted
+			.filter(|(at, _)| *at == image)
+			.map(|(_, m)| [m.x_left, m.y_top, m.x_right, m.y_bottom])
+	};
+	let rendered = |image: usize| decoded.with(|pages| pages.get(image.checked_sub(1)?).cloned().flatten());
+	let styles = *layer_styles.get();
+	let overlays_on = |image: usize| {
+		annotations.with(|annotations| {
+			let Some(layers) = image.checked_sub(1).and_then(|i| annotations.get(i)) else { return Vec::new() };
+			layers
+				.iter()
+				.zip(styles)
+				.filter(|(_, style)| style.visible)
+				.filter_map(|(layer, style)| Some(Overlay { image: layer.clone()?, opacity: style.opacity }))
+				.collect()
+		})
+	};
+	
+	// Announce the piece of a setlist that starts on shown page image `image`.
+	let announce = |image: usize| {
+		let piece = piece.as_ref()?;
+		let (part, _) = piece.locate(image)?;
+		let next = &piece.pieces()[part];
+		let (title, creators, notes) = (next.title(), next.creators(), next.notes.clone());
+		Some(rsx!(
+			NextPiece {
+				title: title,
+				creators: creators,
+				notes: notes,
+				first: part == 0,
+			}
+		))
+	};
 	
 	let mut images = vec![];
-	for data in &*image_datas.read() {
-		let data = data.as_deref().unwrap();
-		let data = bytes_to_data(cx, data);
+	let divider_pages = dividers.read();
+	for (i, image) in decoded.read().iter().enumerate() {
+		if divider_pages.contains(&i) {
+			images.extend(announce(i + 1));
+		}
+		let highlight = highlight_on(i + 1);
+		let overlays = overlays_on(i + 1);
+		let height = layout.heights[i];
+		let strokes = piece.as_ref().and_then(|piece| piece.page_strokes(i + 1));
+		let page_width = piece.as_ref().and_then(|piece| piece.page_width(i + 1, image.as_ref()));
 		images.push(rsx!(
 			Page {
+				strokes: strokes,
+				page_width: page_width,
 				System {
-					image {
-						width: "100%",
-						height: "283",
-						image_data: data,
+					PageImageView {
+						image: image.clone(),
+						overlays: overlays,
+						height: height,
+						highlight: highlight,
 					},
 				},
 			},
 			rect {
 				width: "100%",
-				height: "3",
+				height: "{PAGE_GAP}",
 				background: "transparent",
 			}
 		));
 	}
 	
-	render! {
+	let content = if *paged.get() {
+		let view = page_views.with(|views| views.get(*turn.get()).or(views.last()).copied());
+		let (top, bottom, split) = match &view {
+			Some(PageView::Whole { image }) => (*image, *image, 1.0),
+			Some(PageView::Split { top, bottom, split }) => (*top, *bottom, *split),
+			None => (0, 0, 1.0),
+		};
+		let whole = (top == bottom).then_some(top);
+		let highlight = whole.and_then(highlight_on);
+		// Strokes can only be drawn on whole pages, since they are kept per page.
+		let strokes = whole.and_then(|image| piece.as_ref()?.page_strokes(image));
+		let (top_overlays, bottom_overlays) = (overlays_on(top), overlays_on(bottom));
+		let (top, bottom) = (rendered(top), rendered(bottom));
+		let page_width = whole.and_then(|image| piece.as_ref()?.page_width(image, top.as_ref()));
+		// Say what comes next on the last page of each piece of a setlist.
+		let upcoming = page_views
+			.with(|views| views.get(*turn.get() + 1).map(turned_to))
+			.filter(|&next| {
+				let next_part = piece.as_ref().and_then(|piece| piece.locate(next)).map(|(part, _)| part);
+				next_part.is_some_and(|part| part != viewed_part)
+			})
+			.and_then(announce);
+		let turn_height = if upcoming.is_some() {
+			format!("calc(100% - {DIVIDER_HEIGHT})")
+		} else {
+			"100%".into()
+		};
+		// Fit the annotation canvas to the page, which is centered, so that strokes line up with it.
+		let available_height = view_height - if upcoming.is_some() { DIVIDER_HEIGHT } else { 0.0 };
+		let (margin, width) = match &page_width {
+			Some(_) if top.is_some() => {
+				let RenderedPage(img) = top.as_ref().unwrap();
+				let width = view_width.min(available_height * img.width() as f32 / img.height() as f32);
+				((view_width - width) / 2.0, format!("{width}"))
+			}
+			_ => (0.0, "100%".into()),
+		};
+		rsx!(
+			rect {
+				width: "100%",
+				height: "{turn_height}",
+				direction: "horizontal",
+				rect {
+					width: "{margin}",
+					height: "100%",
+				}
+				rect {
+					width: "{width}",
+					height: "100%",
+					Page {
+						strokes: strokes,
+						page_width: page_width,
+						TurnView {
+							top: top,
+							top_overlays: top_overlays,
+							bottom: bottom,
+							bottom_overlays: bottom_overlays,
+							split: split,
+							highlight: highlight,
+						},
+					}
+				}
+			}
+			upcoming,
+		)
+	} else {
+		let offset = -*scroll_y.get();
+		rsx!(
+			rect {
+				width: "100%",
+				offset_y: "{offset}",
+				images.into_iter(),
+			}
+		)
+	};
+	
+	let (done, total) = *progress.get();
+	let goto_prompt = goto_input.get().as_ref().map(|input| rsx!(
 		rect {
-			width: *width,
-			height: *height,
-			background: "rgb(20, 20, 20)",
+			layer: "-20",
+			padding: "16",
+			background: "black",
+			border: "4 solid rgb(255, 220, 0)",
+			label {
+				color: "white",
+				font_size: "48",
+				"Go to measure: {input}_"
+			}
+		}
+	));
+	
+	let bookmarks_panel = bookmarks_open.get().then(|| {
+		// Bookmarks of the piece being read, leaving out any on pages a setlist skips.
+		let bookmarks = piece.as_ref()
+			.and_then(|piece| {
+				let bookmarks = piece.pieces().get(viewed_part)?.file.bookmarks.as_ref().ok()?;
+				let shown = bookmarks.in_reading_order().into_iter().filter_map(|(_, b)| {
+					Some((piece.global(viewed_part, b.pageimage)?, b.clone()))
+				});
+				Some(shown.collect::<Vec<_>>())
+			})
+			.unwrap_or_default();
+		let entries = bookmarks.into_iter().map(move |(image, Bookmark { name, pageimage, location, .. })| rsx!(
+			rect {
+				width: "100%",
+				padding: "12",
+				onclick: move |_| {
+					if !show(image, location.y) {
+						warn!("can't show bookmark on page image {pageimage}");
+					}
+				},
+				label {
+					color: "white",
+					font_size: "32",
+					"{name}"
+				}
+				label {
+					color: "rgb(200, 200, 200)",
+					font_size: "20",
+					"Page {pageimage}"
+				}
+			}
+		));
+		rsx!(
 			ScrollView {
 				theme: theme_with!(ScrollViewTheme {
-					width: "100%".into(),
+					width: SIDE_PANEL_WIDTH.into(),
 					height: "100%".into(),
 				}),
-				images.into_iter(),
-			},
+				label {
+					color: "white",
+					font_size: "40",
+					"Bookmarks"
+				}
+				entries,
+			}
+		)
+	});
+	let layers_panel = layers_open.get().then(|| {
+		let entries = ANNOTATION_LAYER_NAMES.iter().zip(styles).enumerate().map(move |(layer, (name, style))| {
+			let shown = if style.visible { "Shown" } else { "Hidden" };
+			let percent = (style.opacity * 100.0).round();
+			rsx!(
+				rect {
+					width: "100%",
+					padding: "12",
+					label {
+						color: "white",
+						font_size: "32",
+						"{name}"
+					}
+					rect {
+						direction: "horizontal",
+						rect {
+							padding: "8",
+							background: "rgb(60, 60, 60)",
+							onclick: move |_| layer_styles.with_mut(|styles| styles[layer].visible ^= true),
+							label {
+								color: "white",
+								font_size: "28",
+								"{shown}"
+							}
+						}
+						rect {
+							padding: "8",
+							onclick: move |_| layer_styles.with_mut(|styles| styles[layer].adjust_opacity(-OPACITY_STEP)),
+							label {
+								color: "white",
+								font_size: "28",
+								"−"
+							}
+						}
+						label {
+							color: "white",
+							font_size: "28",
+							"{percent}%"
+						}
+						rect {
+							padding: "8",
+							onclick: move |_| layer_styles.with_mut(|styles| styles[layer].adjust_opacity(OPACITY_STEP)),
+							label {
+								color: "white",
+								font_size: "28",
+								"+"
+							}
+						}
+					}
+				}
+			)
+		});
+		rsx!(
+			rect {
+				width: "{SIDE_PANEL_WIDTH}",
+				height: "100%",
+				label {
+					color: "white",
+					font_size: "40",
+					"Annotations"
+				}
+				entries,
+			}
+		)
+	});
+	let scrollbar = (!*paged.get()).then(|| rsx!(
+		PageScrollbar {
+			scroll_y: *scroll_y.get(),
+			view_height: view_height,
+			content_height: layout.total_height(),
+			onscroll: move |y: f32| scroll_y.set(y.clamp(0.0, max_scroll)),
+		}
+	));
+	let panels_open = usize::from(*bookmarks_open.get()) + usize::from(*layers_open.get());
+	let pages_width = match (panels_open, scrollbar.is_some()) {
+		(0, false) => "100%".into(),
+		(0, true) => format!("calc(100% - {SCROLLBAR_WIDTH})"),
+		(_, false) => format!("calc(100% - {SIDE_PANEL_WIDTH} * {panels_open})"),
+		(_, true) => format!("calc(100% - {SIDE_PANEL_WIDTH} * {panels_open} - {SCROLLBAR_WIDTH})"),
+	};
+	
+	render! {
+		rect {
+			width: *width,
+			height: *height,
+			direction: "horizontal",
+			background: "rgb(20, 20, 20)",
+			onkeydown: onkeydown,
+			bookmarks_panel,
+			rect {
+				reference: view_ref,
+				width: "{pages_width}",
+				height: "100%",
+				overflow: "clip",
+				onwheel: onwheel,
+				content,
+				if done < total {
+					rsx!(
+						label {
+							color: "white",
+							layer: "-10",
+							"Loading pages... {done}/{total}"
+						}
+					)
+				}
+				goto_prompt,
+			}
+			scrollbar,
+			layers_panel,
+		}
+	}
+}
+
+/// Width of each side panel, in logical pixels.
+const SIDE_PANEL_WIDTH: &str = "320";
+/// Width of the scrollbar beside the pages, in logical pixels. Wide enough to grab with a finger.
+const SCROLLBAR_WIDTH: f32 = 24.0;
+/// The scrollbar's thumb never gets shorter than this, however long the piece.
+const MIN_THUMB_HEIGHT: f32 = 48.0;
+/// The images of each page that are shown, by file stem. The page itself comes first, followed
+/// by the annotation layers in the order of [`ANNOTATION_LAYER_NAMES`].
+const SHOWN_LAYERS: [&str; 3] = ["page", "annotations-local", "annotations-remote"];
+/// Names of MusicReader's annotation layers, as shown to the user.
+const ANNOTATION_LAYER_NAMES: [&str; 2] = ["My annotations", "Shared annotations"];
+/// How much the opacity of an annotation layer changes with each click.
+const OPACITY_STEP: f32 = 0.25;
+
+/// Space between pages, in logical pixels.
+const PAGE_GAP: f32 = 3.0;
+/// Height of the divider announcing each piece of a setlist, in logical pixels.
+const DIVIDER_HEIGHT: f32 = 200.0;
+/// Height / width of pages that haven't been decoded yet. US Letter is as good a guess as any.
+const DEFAULT_ASPECT: f32 = 11.0 / 8.5;
+/// PDF pages are rasterized at a multiple of this many pixels wide.
+const PDF_WIDTH_STEP: f32 = 256.0;
+/// Bytes of decoded pages that each piece's [`ImageCache`] may hold. [`PieceView`] keeps the pages
+/// it shows itself, so the cache only needs the one being loaded.
+const PAGE_CACHE_BUDGET: usize = 0;
+/// How long a measure stays highlighted after jumping to it.
+const HIGHLIGHT_DURATION: Duration = Duration::from_secs(3);
+
+/// How one of MusicReader's annotation layers is drawn over the pages.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct LayerStyle {
+	visible: bool,
+	opacity: f32,
+}
+
+impl LayerStyle {
+	fn adjust_opacity(&mut self, by: f32) {
+		self.opacity = (self.opacity + by).clamp(OPACITY_STEP, 1.0);
+	}
+}
+
+impl Default for LayerStyle {
+	fn default() -> Self {
+		Self { visible: true, opacity: 1.0 }
+	}
+}
+
+/// Vertical positions of the pages in a [`PieceView`], in logical pixels.
+struct PageLayout {
+	width: f32,
+	tops: Vec<f32>,
+	heights: Vec<f32>,
+}
+
+impl PageLayout {
+	/// Lay out `pages` one after another, with a divider above each of those whose index is in
+	/// `dividers`.
+	fn new(width: f32, pages: &[Option<RenderedPage>], dividers: &[usize]) -> Self {
+		let mut top = 0.0;
+		let mut tops = Vec::with_capacity(pages.len());
+		let mut heights = Vec::with_capacity(pages.len());
+		for (i, page) in pages.iter().enumerate() {
+			if dividers.contains(&i) {
+				top += DIVIDER_HEIGHT;
+			}
+			let aspect = page.as_ref().map_or(DEFAULT_ASPECT, |RenderedPage(img)| {
+				img.height() as f32 / img.width() as f32
+			});
+			tops.push(top);
+			heights.push(width * aspect);
+			top += width * aspect + PAGE_GAP;
+		}
+		Self { width, tops, heights }
+	}
+	
+	fn total_height(&self) -> f32 {
+		self.tops.last().zip(self.heights.last()).map_or(0.0, |(top, height)| top + height)
+	}
+	
+	/// The page image displayed at `y`, or the one above it if `y` falls between pages.
+	fn image_at(&self, y: f32) -> Option<usize> {
+		(!self.tops.is_empty()).then(|| self.tops.iter().rposition(|&top| top <= y).map_or(1, |i| i + 1))
+	}
+	
+	/// Where row `y` (in image pixels) of page image `image` is displayed, relative to the top of
+	/// the first page.
+	fn image_y(&self, image: usize, y: usize, pages: &[Option<RenderedPage>]) -> Option<f32> {
+		let i = image.checked_sub(1)?;
+		let RenderedPage(img) = pages.get(i)?.as_ref()?;
+		let scale = self.width / img.width() as f32;
+		Some(self.tops[i] + y as f32 * scale)
+	}
+}
+
+/// Load page `page` of `cache` on a blocking thread to be shown, rasterizing it at `width` if it
+/// is a page of a PDF.
+async fn load_cached(cache: Arc<Mutex<ImageCache>>, page: usize, width: u32) -> Result<SkiaImage, PieceError> {
+	let rendered = tokio::task::spawn_blocking(move || {
+		let mut cache = cache.lock().unwrap();
+		cache.set_pdf_width(width);
+		match cache.load(page, Stage::Rendered)? {
+			ImageRef::Rendered(img) => Ok(img),
+			_ => Err(PieceError::Unrenderable),
+		}
+	});
+	rendered.await.map_err(std::io::Error::from)?
+}
+
+/// The page image a step of a paged presentation has turned to: the whole page, or the next
+/// page whose top is being shown.
+fn turned_to(view: &PageView) -> usize {
+	match view {
+		PageView::Whole { image } => *image,
+		PageView::Split { top, .. } => *top,
+	}
+}
+
+/// Parse "12" as measure 12 of `current_movement`, or "2.12" as measure 12 of movement 2.
+fn parse_measure(input: &str, current_movement: usize) -> Option<(usize, usize)> {
+	match input.split_once('.') {
+		Some((movement, number)) => Some((movement.parse().ok()?, number.parse().ok()?)),
+		None => Some((current_movement, input.parse().ok()?)),
+	}
+}
+
+/// A scrollbar for the pages. Freya's `ScrollView` keeps its scroll position to itself, so
+/// [`PieceView`] scrolls the pages on its own to be able to bring measures into view, and this
+/// stands in for the scrollbar `ScrollView` would have shown.
+///
+/// The thumb can be dragged with a mouse, pen or finger, and pressing above or below it scrolls by
+/// a screen.
+#[component]
+fn PageScrollbar<'a>(
+	cx: Scope<'a>,
+	scroll_y: f32,
+	view_height: f32,
+	content_height: f32,
+	onscroll: EventHandler<'a, f32>,
+) -> Element {
+	let (bar_ref, bar_size) = use_node(cx);
+	// How far below the top of the thumb it was grabbed, while it is being dragged.
+	let grab = use_state(cx, || None::<f32>);
+	
+	let (scroll_y, view_height) = (*scroll_y, *view_height);
+	let max_scroll = (content_height - view_height).max(0.0);
+	let thumb_height = if *content_height > view_height {
+		(view_height * view_height / content_height).max(MIN_THUMB_HEIGHT).min(view_height)
+	} else {
+		view_height
+	};
+	let travel = view_height - thumb_height;
+	let thumb_top = if max_scroll > 0.0 { scroll_y / max_scroll * travel } else { 0.0 };
+	let top = bar_size.area.min_y();
+	
+	// `y` is in screen coordinates.
+	let press = move |y: f64| {
+		let y = y as f32 - top;
+		if (thumb_top..thumb_top + thumb_height).contains(&y) {
+			grab.set(Some(y - thumb_top));
+		} else if y < thumb_top {
+			onscroll.call(scroll_y - view_height);
+		} else {
+			onscroll.call(scroll_y + view_height);
+		}
+	};
+	let drag = move |y: f64| {
+		if let (Some(grab), true) = (*grab.get(), travel > 0.0) {
+			let thumb_top = (y as f32 - top - grab).clamp(0.0, travel);
+			onscroll.call(thumb_top / travel * max_scroll);
+		}
+	};
+	let ontouch = move |e: TouchEvent| match e.phase {
+		TouchPhase::Started => press(e.screen_coordinates.y),
+		TouchPhase::Moved => drag(e.screen_coordinates.y),
+		TouchPhase::Ended | TouchPhase::Cancelled => grab.set(None),
+	};
+	let thumb_color = if grab.get().is_some() { "rgb(255, 220, 0)" } else { "rgb(140, 140, 140)" };
+	
+	render! {
+		rect {
+			reference: bar_ref,
+			width: "{SCROLLBAR_WIDTH}",
+			height: "100%",
+			background: "rgb(40, 40, 40)",
+			onmousedown: move |e: MouseEvent| press(e.screen_coordinates.y),
+			onglobalmouseover: move |e: MouseEvent| drag(e.screen_coordinates.y),
+			onglobalclick: move |_| grab.set(None),
+			ontouchstart: ontouch,
+			ontouchmove: ontouch,
+			ontouchend: ontouch,
+			rect {
+				width: "100%",
+				height: "{thumb_height}",
+				offset_y: "{thumb_top}",
+				background: "{thumb_color}",
+			}
+		}
+	}
+}
+
+/// Draws a decoded page image scaled to the available width with `overlays` on top of it, or
+/// nothing if it isn't ready yet.
+///
+/// `highlight` is a rectangle to outline on top of the page, as `[left, top, right, bottom]` in
+/// page image pixels.
+#[component]
+pub fn PageImageView(
+	cx: Scope,
+	image: Option<RenderedPage>,
+	overlays: Vec<Overlay>,
+	height: f32,
+	highlight: Option<[usize; 4]>,
+) -> Element {
+	let canvas = use_canvas(cx, &(image.clone(), overlays.clone(), *highlight), |(image, overlays, highlight)| {
+		Box::new(move |canvas, _fonts, area| {
+			let Some(page) = &image else { return };
+			let RenderedPage(image) = page;
+			let scale = f32::min(area.width() / image.width() as f32, area.height() / image.height() as f32);
+			let (w, h) = (image.width() as f32 * scale, image.height() as f32 * scale);
+			let (x, y) = (area.min_x() + (area.width() - w) / 2.0, area.min_y());
+			draw_page_part(canvas, page, &overlays, 0.0, 1.0, Rect::from_xywh(x, y, w, h));
+	
+			if let Some(highlight) = highlight {
+				draw_highlight(canvas, (x, y), scale, highlight);
+			}
+		})
+	});
+	
+	render! {
+		rect {
+			width: "100%",
+			height: "{height}",
+			canvas_reference: canvas.attribute(cx),
+		}
+	}
+}
+
+/// Shows one step of a paged presentation, fitted to the available space: the part of `top`
+/// above `split` (a fraction of the page height) followed by the part of `bottom` below it.
+///
+/// For a whole page, pass the same image as both `top` and `bottom`.
+#[component]
+pub fn TurnView(
+	cx: Scope,
+	top: Option<RenderedPage>,
+	top_overlays: Vec<Overlay>,
+	bottom: Option<RenderedPage>,
+	bottom_overlays: Vec<Overlay>,
+	split: f32,
+	highlight: Option<[usize; 4]>,
+) -> Element {
+	let deps = (top.clone(), top_overlays.clone(), bottom.clone(), bottom_overlays.clone(), *split, *highlight);
+	let canvas = use_canvas(cx, &deps, |(top, top_overlays, bottom, bottom_overlays, split, highlight)| {
+		Box::new(move |canvas, _fonts, area| {
+			let Some(RenderedPage(size_ref)) = bottom.as_ref().or(top.as_ref()) else { return };
+			let scale = f32::min(area.width() / size_ref.width() as f32, area.height() / size_ref.height() as f32);
+			let (w, h) = (size_ref.width() as f32 * scale, size_ref.height() as f32 * scale);
+			let (x, y) = (area.min_x() + (area.width() - w) / 2.0, area.min_y());
+			let split_y = y + h * split;
+			
+			if let Some(top) = &top {
+				draw_page_part(canvas, top, &top_overlays, 0.0, split, Rect::new(x, y, x + w, split_y));
+			}
+			if let Some(bottom) = &bottom {
+				draw_page_part(canvas, bottom, &bottom_overlays, split, 1.0, Rect::new(x, split_y, x + w, y + h));
+			}
+			
+			if top != bottom && split > 0.0 && split < 1.0 {
+				let mut paint = Paint::default();
+				paint.set_color(Color::from_rgb(255, 220, 0));
+				paint.set_stroke_width(4.0);
+				canvas.draw_line((x, split_y), (x + w, split_y), &paint);
+			}
+			
+			if let Some(highlight) = highlight {
+				draw_highlight(canvas, (x, y), scale, highlight);
+			}
+		})
+	});
+	
+	render! {
+		rect {
+			width: "100%",
+			height: "100%",
+			canvas_reference: canvas.attribute(cx),
 		}
 	}
 }
 
+/// Draw the horizontal band of `page` between `from` and `to` (fractions of its height) into
+/// `dst`, with the same band of each of `overlays` on top.
+///
+/// Each image is scaled independently, so overlays line up with the page even if they weren't
+/// saved at the same resolution, and pages that aren't all the same size still fill `dst`.
+fn draw_page_part(canvas: &Canvas, page: &RenderedPage, overlays: &[Overlay], from: f32, to: f32, dst: Rect) {
+	let draw = |image: &SkiaImage, paint: &Paint| {
+		let (iw, ih) = (image.width() as f32, image.height() as f32);
+		let src = Rect::new(0.0, ih * from, iw, ih * to);
+		canvas.draw_image_rect(image, Some((&src, SrcRectConstraint::Strict)), dst, paint);
+	};
+	draw(&page.0, &Paint::default());
+	for Overlay { image: RenderedPage(image), opacity } in overlays {
+		let mut paint = Paint::default();
+		paint.set_alpha_f(*opacity);
+		draw(image, &paint);
+	}
+}
+
+/// Outline `[left, top, right, bottom]` (in page image pixels) of a page drawn at `origin` and
+/// `scale`.
+fn draw_highlight(canvas: &Canvas, (x, y): (f32, f32), scale: f32, [left, top, right, bottom]: [usize; 4]) {
+	let rect = Rect::new(
+		x + left as f32 * scale,
+		y + top as f32 * scale,
+		x + right as f32 * scale,
+		y + bottom as f32 * scale,
+	);
+	let mut paint = Paint::default();
+	paint.set_anti_alias(true);
+	paint.set_color(Color::from_argb(64, 255, 220, 0));
+	canvas.draw_rect(rect, &paint);
+	// Dark outline around a bright one, so it stands out on both paper and ink.
+	paint.set_style(PaintStyle::Stroke);
+	paint.set_stroke_width(10.0);
+	paint.set_color(Color::BLACK);
+	canvas.draw_rect(rect, &paint);
+	paint.set_stroke_width(5.0);
+	paint.set_color(Color::from_rgb(255, 220, 0));
+	canvas.draw_rect(rect, &paint);
+}
+
+/// A decoded page image, ready to be drawn. Compared by identity so that it can be passed as a prop.
+#[derive(Clone)]
+pub struct RenderedPage(pub SkiaImage);
+
+impl PartialEq for RenderedPage {
+	fn eq(&self, other: &Self) -> bool {
+		self.0.unique_id() == other.0.unique_id()
+	}
+}
+
+/// An annotation layer to draw over a page.
+#[derive(Clone, PartialEq)]
+pub struct Overlay {
+	pub image: RenderedPage,
+	pub opacity: f32,
+}
+
+/// Announces a piece of a setlist in large type, so that what comes next can be seen at a glance
+/// while playing. `first` is for the piece the setlist starts with, which doesn't come next.
 #[component]
-pub fn Page<'a>(cx: Scope<'a>, children: Element<'a>) -> Element {
+pub fn NextPiece(cx: Scope, title: String, creators: String, notes: Option<String>, first: bool) -> Element {
+	let heading = if *first { title.clone() } else { format!("Next: {title}") };
+	let notes = notes.as_ref().map(|notes| rsx!(
+		label {
+			color: "rgb(255, 220, 0)",
+			font_size: "32",
+			"{notes}"
+		}
+	));
+	render! {
+		rect {
+			width: "100%",
+			height: "{DIVIDER_HEIGHT}",
+			padding: "16",
+			background: "black",
+			border: "4 solid rgb(255, 220, 0)",
+			label {
+				color: "white",
+				font_size: "56",
+				"{heading}"
+			}
+			label {
+				color: "rgb(200, 200, 200)",
+				font_size: "32",
+				"{creators}"
+			}
+			notes,
+		}
+	}
+}
+
+/// A page that strokes can be drawn on. `strokes` and `page_width` are passed on to
+/// [`AnnotationCanvas`]: the strokes on the page, if they are kept, and the width of its image.
+#[component]
+pub fn Page<'a>(
+	cx: Scope<'a>,
+	children: Element<'a>,
+	strokes: Option<SharedStrokes>,
+	page_width: Option<f32>,
+) -> Element {
 	render! {
 		AnnotationCanvas {
+			strokes: strokes.clone(),
+			page_width: *page_width,
 			rect {
 				width: "100%",
 				children
@@ -93,13 +983,220 @@ pub fn System<'a>(cx: Scope<'a>, children: Element<'a>) -> Element {
 	}
 }
 
+/// The pieces being viewed, one after another: a single piece, or the pieces of a setlist.
+///
+/// Their shown page images are numbered from 1 across all of them, in the order they are shown,
+/// which is what [`PieceView`] works with. Measures and bookmarks still refer to the page images
+/// of their own piece, which [`global`](Self::global) and [`locate`](Self::locate) convert between.
 #[derive(Clone)]
-pub struct OpenPiece(pub Arc<MRSFile>);
+pub struct OpenPiece(Arc<[SetPiece]>);
+
+/// One of the pieces in an [`OpenPiece`].
+pub struct SetPiece {
+	pub file: MRSFile,
+	/// Indices of the page images that are shown, in the order they are shown, for playing only
+	/// part of the piece.
+	pub pages: Vec<usize>,
+	/// Shown when the piece is announced.
+	pub notes: Option<String>,
+	/// The strokes on each of the file's page images, for pieces that keep them. Empty otherwise,
+	/// in which case strokes drawn are lost when the piece is closed.
+	strokes: Vec<SharedStrokes>,
+	/// The strokes as they were when loaded or last saved.
+	saved_strokes: Mutex<Vec<Strokes>>,
+	/// The pages of the piece, if the file was made without them, to be read as they are shown.
+	cache: Option<Arc<Mutex<ImageCache>>>,
+	/// Whether the pages in `cache` are those of a PDF, which are rasterized again at the width
+	/// they are shown at.
+	pdf: bool,
+}
+
+impl OpenPiece {
+	/// All of `piece`, on its own.
+	pub fn single(piece: SetPiece) -> Self {
+		Self::sequence(vec![piece])
+	}
+	
+	pub fn sequence(pieces: Vec<SetPiece>) -> Self {
+		Self(pieces.into())
+	}
+	
+	/// Identifies which pieces are open, so that effects can re-run when they change.
+	pub fn id(&self) -> usize {
+		Arc::as_ptr(&self.0) as *const () as usize
+	}
+	
+	pub fn pieces(&self) -> &[SetPiece] {
+		&self.0
+	}
+	
+	/// How many page images are shown, over all of the pieces.
+	pub fn image_count(&self) -> usize {
+		self.0.iter().map(|part| part.pages.len()).sum()
+	}
+	
+	/// Every shown page image, in order.
+	pub fn page_images(&self) -> impl Iterator<Item = &PageImages> {
+		self.0.iter().flat_map(|part| part.pages.iter().map(|&image| &part.file.pages[image]))
+	}
+	
+	/// The index of the piece that shown page image `image` belongs to, and its number among
+	/// that piece's own page images.
+	pub fn locate(&self, image: usize) -> Option<(usize, usize)> {
+		let mut i = image.checked_sub(1)?;
+		for (part, SetPiece { pages, .. }) in self.0.iter().enumerate() {
+			if i < pages.len() {
+				return Some((part, pages[i] + 1));
+			}
+			i -= pages.len();
+		}
+		None
+	}
+	
+	/// The number of page image `image` of piece `part` among the shown page images, if it is shown.
+	/// A page image that is shown more than once is numbered where it is first shown.
+	pub fn global(&self, part: usize, image: usize) -> Option<usize> {
+		let i = image.checked_sub(1)?;
+		let shown = self.0.get(part)?.pages.iter().position(|&page| page == i)?;
+		let before = self.0[..part].iter().map(|part| part.pages.len()).sum::<usize>();
+		Some(before + shown + 1)
+	}
+	
+	/// The strokes on shown page image `image`, if its piece keeps them.
+	pub fn page_strokes(&self, image: usize) -> Option<SharedStrokes> {
+		let (part, image) = self.locate(image)?;
+		self.0[part].strokes.get(image - 1).cloned()
+	}
+	
+	/// The width of shown page image `image` in the coordinates its strokes are kept in, which are
+	/// pixels of the page image. PDF pages are measured as rasterized at the [default
+	/// width](DEFAULT_WIDTH), whatever width they are shown at. `rendered` is the image as shown.
+	pub fn page_width(&self, image: usize, rendered: Option<&RenderedPage>) -> Option<f32> {
+		let (part, _) = self.locate(image)?;
+		match self.0[part].pdf {
+			true => Some(DEFAULT_WIDTH as f32),
+			false => rendered.map(|RenderedPage(img)| img.width() as f32),
+		}
+	}
+	
+	/// Whether shown page image `image` is read through the cache of its piece rather than taken
+	/// from its file.
+	pub fn is_cached(&self, image: usize) -> bool {
+		self.locate(image).is_some_and(|(part, _)| self.0[part].cache.is_some())
+	}
+	
+	/// Every shown page image that is read through the cache of its piece, along with the cache
+	/// and its index in it.
+	pub fn cached_pages(&self) -> Vec<(usize, Arc<Mutex<ImageCache>>, usize)> {
+		self.cached_pages_where(|_| true)
+	}
+	
+	/// Every shown page image that is a page of a PDF, along with the cache of its PDF and its
+	/// index in it.
+	pub fn pdf_pages(&self) -> Vec<(usize, Arc<Mutex<ImageCache>>, usize)> {
+		self.cached_pages_where(|part| part.pdf)
+	}
+	
+	fn cached_pages_where(&self, keep: impl Fn(&SetPiece) -> bool) -> Vec<(usize, Arc<Mutex<ImageCache>>, usize)> {
+		(1..=self.image_count())
+			.filter_map(|image| {
+				let (part, page) = self.locate(image)?;
+				let part = &self.0[part];
+				Some((image, part.cache.clone().filter(|_| keep(part))?, page - 1))
+			})
+			.collect()
+	}
+	
+	/// Shown page image `image` and the file it is from, for messages.
+	pub fn describe(&self, image: usize) -> String {
+		match self.locate(image) {
+			Some((part, image)) => format!("page {image} of `{}`", self.0[part].file.path.display()),
+			None => format!("page {image}"),
+		}
+	}
+	
+	/// Every step of turning through the pieces one page at a time. The last page of each piece
+	/// turns straight to the first shown page of the next.
+	pub fn page_views(&self) -> Vec<PageView> {
+		let mut views = Vec::new();
+		for (part, SetPiece { file, .. }) in self.0.iter().enumerate() {
+			let part_views = match &file.info {
+				Ok(info) => info.page_views(),
+				Err(_) => (1..=file.pages.len()).map(|image| PageView::Whole { image }).collect(),
+			};
+			// Steps involving pages that aren't shown are left out.
+			views.extend(part_views.into_iter().filter_map(|view| match view {
+				PageView::Whole { image } => Some(PageView::Whole { image: self.global(part, image)? }),
+				PageView::Split { top, bottom, split } => Some(PageView::Split {
+					top: self.global(part, top)?,
+					bottom: self.global(part, bottom)?,
+					split,
+				}),
+			}));
+		}
+		views
+	}
+	
+	/// Indices of the shown pages that start a piece and should be announced. A single piece
+	/// isn't announced.
+	pub fn dividers(&self) -> Vec<usize> {
+		if self.0.len() < 2 {
+			return Vec::new();
+		}
+		self.0
+			.iter()
+			.scan(0, |start, part| {
+				let this = *start;
+				*start += part.pages.len();
+				Some(this)
+			})
+			.collect()
+	}
+}
 
-impl Deref for OpenPiece {
-	type Target = MRSFile;
+impl SetPiece {
+	/// All of `file`. `strokes` are those on each of its page images, if it keeps them.
+	pub fn new(file: MRSFile, strokes: Option<Vec<Strokes>>) -> Self {
+		let pages = (0..file.pages.len()).collect();
+		let saved_strokes = strokes.unwrap_or_default();
+		let strokes = saved_strokes.iter().map(|strokes| Arc::new(Mutex::new(strokes.clone()))).collect();
+		Self { file, pages, notes: None, strokes, saved_strokes: Mutex::new(saved_strokes), cache: None, pdf: false }
+	}
+	
+	/// Read the file's page images from `pages` as they are shown, for files made without them.
+	/// Pages of a PDF are rasterized at the width they are shown at.
+	pub fn with_pages(self, pages: Pages) -> Self {
+		let pdf = matches!(pages, Pages::Pdf(_));
+		let cache = ImageCache::from_pages(pages, PAGE_CACHE_BUDGET);
+		Self { cache: Some(Arc::new(Mutex::new(cache))), pdf, ..self }
+	}
+	
+	/// The strokes on each page image, if any were drawn or erased since they were last saved.
+	pub fn unsaved_strokes(&self) -> Option<Vec<Strokes>> {
+		let strokes = self.strokes.iter().map(|strokes| strokes.lock().unwrap().clone()).collect::<Vec<_>>();
+		(strokes != *self.saved_strokes.lock().unwrap()).then_some(strokes)
+	}
+	
+	/// Note that `strokes`, from [`unsaved_strokes`](Self::unsaved_strokes), have been saved.
+	pub fn strokes_saved(&self, strokes: Vec<Strokes>) {
+		*self.saved_strokes.lock().unwrap() = strokes;
+	}
+	
+	pub fn info(&self) -> Option<&mr_imp::Piece> {
+		self.file.info.as_ref().ok()
+	}
+	
+	/// The piece's title, or its file name if it has none.
+	pub fn title(&self) -> String {
+		self.info()
+			.map(|info| info.information.title.clone())
+			.filter(|title| !title.trim().is_empty())
+			.unwrap_or_else(|| self.file.path.file_stem().unwrap_or_default().to_string_lossy().into_owned())
+	}
 	
-	fn deref(&self) -> &Self::Target {
-		&*self.0
+	/// Who wrote the piece, as one line.
+	pub fn creators(&self) -> String {
+		let creators = self.info().and_then(|info| info.information.creator.as_ref());
+		creators.map_or_else(String::new, |creators| creators.join("; "))
 	}
 }
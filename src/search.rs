@@ -0,0 +1,288 @@
+#![allow(non_snake_case)]
+
+use std::path::{Path, PathBuf};
+use freya::prelude::*;
+use freya::events::keyboard::{Code, Key};
+use library::{
+	catalog::{Entry, Library, PieceKind},
+	mrs,
+	search::Query,
+	setlist::Setlist,
+	Piece,
+};
+use log::{error, warn};
+use mr_imp::MRSFile;
+use crate::page_rendering::{OpenPiece, SetPiece};
+
+/// How many of the best matches are listed.
+const MAX_RESULTS: usize = 100;
+/// How many results fit on the screen at once.
+const SHOWN_RESULTS: usize = 6;
+
+/// Finds pieces in the library as their title, composer, subject or tags are typed, in large
+/// type and entirely from the keyboard: arrow keys choose a piece and Enter opens it. Tab tags the
+/// chosen piece with what is typed, or takes the tag away if it already has it, and saves the
+/// library.
+#[component]
+pub fn SearchScreen<'a>(cx: Scope<'a>, library: UseRef<Option<Library>>, onopen: EventHandler<'a, PathBuf>) -> Element {
+	let input = use_state(cx, String::new);
+	let selected = use_state(cx, || 0usize);
+
+	let query = Query::parse(input.get());
+	let (hits, matches, total, has_roots) = library.with(|library| match library {
+		Some(library) => {
+			let hits = library.search(&query);
+			let matches = hits.len();
+			let hits = hits.into_iter().take(MAX_RESULTS).map(|hit| hit.entry.clone()).collect::<Vec<_>>();
+			(hits, matches, Some(library.len()), !library.roots().is_empty())
+		}
+		None => (Vec::new(), 0, None, false),
+	});
+	let last = hits.len().saturating_sub(1);
+	let current = usize::min(*selected.get(), last);
+
+	let onkeydown = {
+		let paths = hits.iter().map(|entry| entry.path.clone()).collect::<Vec<_>>();
+		move |e: KeyboardEvent| match e.code {
+			Code::ArrowDown => selected.set(usize::min(current + 1, last)),
+			Code::ArrowUp => selected.set(current.saturating_sub(1)),
+			Code::PageDown => selected.set(usize::min(current + SHOWN_RESULTS, last)),
+			Code::PageUp => selected.set(current.saturating_sub(SHOWN_RESULTS)),
+			Code::Enter | Code::NumpadEnter => {
+				if let Some(path) = paths.get(current) {
+					onopen.call(path.clone());
+				}
+			}
+			Code::Tab => {
+				let tag = input.get().trim().to_owned();
+				let Some(path) = paths.get(current).filter(|_| !tag.is_empty()) else { return };
+				let tagged = library.with_mut(|library| {
+					let library = library.as_mut()?;
+					let mut tags = library.get(path)?.tags.clone();
+					match tags.iter().position(|t| t.to_lowercase() == tag.to_lowercase()) {
+						Some(i) => {
+							tags.remove(i);
+						}
+						None => tags.push(tag),
+					}
+					library.set_tags(path, tags);
+					Some(library.clone())
+				});
+				if let Some(library) = tagged {
+					cx.spawn(async move {
+						if let Err(e) = library.save().await {
+							error!("couldn't save the library index: {e}");
+						}
+					});
+				}
+			}
+			Code::Backspace => {
+				let mut text = input.get().clone();
+				text.pop();
+				input.set(text);
+				selected.set(0);
+			}
+			_ => {
+				if let Key::Character(c) = &e.key {
+					input.set(format!("{}{c}", input.get()));
+					selected.set(0);
+				}
+			}
+		}
+	};
+
+	let status = match total {
+		None => "Loading the library...".to_owned(),
+		Some(0) if !has_roots => "No pieces yet. Set MR_LIB_DIR to the folders your pieces are in.".to_owned(),
+		Some(total) if query.is_empty() => format!("{total} pieces"),
+		Some(total) => format!("{matches} of {total} pieces"),
+	};
+
+	// Keep the selected piece on screen.
+	let first = (current + 1).saturating_sub(SHOWN_RESULTS);
+	let results = hits.into_iter().enumerate().skip(first).take(SHOWN_RESULTS).map(|(i, entry)| {
+		let Entry { path, kind, information, pages, tags, .. } = entry;
+		let creators = information.creators.join("; ");
+		let mut details = information.subjects.clone();
+		if kind == PieceKind::Setlist {
+			details.push("Setlist".into());
+		}
+		details.extend(tags);
+		if pages > 0 {
+			details.push(format!("{pages} pages"));
+		}
+		let details = details.join(" · ");
+		let (background, border) = if i == current {
+			("rgb(60, 60, 60)", "4 solid rgb(255, 220, 0)")
+		} else {
+			("transparent", "4 solid transparent")
+		};
+		rsx!(
+			rect {
+				width: "100%",
+				padding: "12",
+				background: background,
+				border: border,
+				onclick: move |_| onopen.call(path.clone()),
+				label {
+					color: "white",
+					font_size: "48",
+					"{information.title}"
+				}
+				label {
+					color: "rgb(200, 200, 200)",
+					font_size: "32",
+					"{creators}"
+				}
+				label {
+					color: "rgb(160, 160, 160)",
+					font_size: "24",
+					"{details}"
+				}
+			}
+		)
+	});
+
+	render! {
+		rect {
+			width: "100%",
+			height: "100%",
+			padding: "24",
+			background: "rgb(20, 20, 20)",
+			onkeydown: onkeydown,
+			rect {
+				width: "100%",
+				padding: "16",
+				background: "black",
+				border: "4 solid rgb(255, 220, 0)",
+				label {
+					color: "white",
+					font_size: "56",
+					"Find: {input}_"
+				}
+			}
+			label {
+				color: "rgb(200, 200, 200)",
+				font_size: "24",
+				"{status} — ↑↓ to choose, Enter to open, Tab to tag, composer: or subject: to filter"
+			}
+			results,
+		}
+	}
+}
+
+/// Open the piece at `path` for viewing. Pieces saved by See Augmented are [viewed](mrs::view) as a
+/// MusicReader file on a blocking thread, without their pages, which are read as they are shown.
+/// The strokes drawn on them are kept separately so that they can be drawn on and saved again.
+pub async fn load_piece(path: &Path) -> Result<SetPiece, String> {
+	match PieceKind::of(path) {
+		Some(PieceKind::Native) => {
+			let mut piece = Piece::load(path).await.map_err(|e| e.to_string())?;
+			let strokes = piece.take_strokes();
+			let viewed = tokio::task::spawn_blocking(move || {
+				let viewed = mrs::view(&piece)?;
+				Ok::<_, mrs::ExportError>((viewed, piece.pages))
+			});
+			let ((mut file, dropped), pages) =
+				viewed.await.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+			for dropped in dropped {
+				warn!("not showing part of `{}`: {dropped}", path.display());
+			}
+			file.path = path.to_owned();
+			Ok(SetPiece::new(file, Some(strokes)).with_pages(pages))
+		}
+		Some(PieceKind::Setlist) => Err("a setlist can't be played as a piece".into()),
+		_ => MRSFile::load(path).await.map(|file| SetPiece::new(file, None)).map_err(|e| e.to_string()),
+	}
+}
+
+/// Save the strokes drawn on each piece of `open` since it was opened into the file it came from.
+pub async fn save_strokes(open: &OpenPiece) {
+	for part in open.pieces() {
+		let Some(strokes) = part.unsaved_strokes() else { continue };
+		let path = &part.file.path;
+		let saved = async {
+			let mut piece = Piece::load(path).await?;
+			piece.set_strokes(&strokes);
+			piece.save(path).await
+		};
+		match saved.await {
+			Ok(()) => part.strokes_saved(strokes),
+			Err(e) => error!("couldn't save the strokes drawn on `{}`: {e}", path.display()),
+		}
+	}
+}
+
+/// Open the piece or setlist at `path` for viewing.
+pub async fn open(path: &Path) -> Result<OpenPiece, String> {
+	if PieceKind::of(path) == Some(PieceKind::Setlist) {
+		load_setlist(path).await
+	} else {
+		load_piece(path).await.map(OpenPiece::single)
+	}
+}
+
+/// Open the pieces of the setlist at `path` to be viewed one after another. Pieces that can't be
+/// opened are left out, so that the rest can still be played.
+pub async fn load_setlist(path: &Path) -> Result<OpenPiece, String> {
+	let setlist = Setlist::load(path).await.map_err(|e| e.to_string())?;
+	let mut pieces = Vec::with_capacity(setlist.entries.len());
+	for entry in setlist.entries {
+		let mut part = match load_piece(&entry.piece).await {
+			Ok(part) => part,
+			Err(e) => {
+				error!("leaving `{}` out of setlist `{}`: {e}", entry.piece.display(), path.display());
+				continue;
+			}
+		};
+		let images = part.file.pages.len();
+		// Indices of the page images in the order they are played
+		let order: Vec<usize> = match &part.file.info {
+			Ok(info) => info
+				.pages_in_order()
+				.iter()
+				.filter_map(|page| page.image.checked_sub(1).filter(|&image| image < images))
+				.collect(),
+			Err(_) => (0..images).collect(),
+		};
+		let bookmarks = part.file.bookmarks.as_ref().ok();
+		let pages = entry.pages(&order, |name| {
+			let bookmark = bookmarks?.as_slice().iter().find(|bookmark| bookmark.name == name)?;
+			bookmark.pageimage.checked_sub(1)
+		});
+		if pages.is_empty() {
+			warn!("leaving `{}` out of setlist `{}`: it has no pages", entry.piece.display(), path.display());
+			continue;
+		}
+		part.pages = pages;
+		part.notes = entry.notes;
+		pieces.push(part);
+	}
+	if pieces.is_empty() {
+		return Err("none of its pieces could be opened".into());
+	}
+	Ok(OpenPiece::sequence(pieces))
+}
+
+/// Open the library at `index`, add `roots` to it, and bring it up to date with the files,
+/// calling `loaded` as soon as the saved index has been read and again once it is up to date.
+/// Anything `loaded` changes the second time, such as tags added in the meantime, is saved along
+/// with the updated index.
+pub async fn load_library(index: PathBuf, roots: Vec<PathBuf>, loaded: impl Fn(&mut Library)) {
+	let mut library = match Library::open(&index).await {
+		Ok(library) => library,
+		Err(e) => {
+			warn!("couldn't open the library index `{}`, so it will be rebuilt: {e}", index.display());
+			Library::new(&index)
+		}
+	};
+	for root in roots {
+		library.add_root(root);
+	}
+	loaded(&mut library);
+	library.refresh().await;
+	loaded(&mut library);
+	if let Err(e) = library.save().await {
+		warn!("couldn't save the library index `{}`: {e}", index.display());
+	}
+}
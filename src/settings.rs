@@ -1,13 +1,39 @@
-
+use std::path::PathBuf;
 
 pub struct Settings {
 	pub log_level: tracing_subscriber::filter::LevelFilter,
+	/// Where the index of the music library is kept.
+	pub library_index: PathBuf,
+	/// Folders to search for pieces, on top of those already in the library. Set with the
+	/// `MR_LIB_DIR` environment variable, which may list several folders like `PATH`.
+	pub library_roots: Vec<PathBuf>,
+	/// A piece or setlist to open instead of the search, given on the command line.
+	pub open: Option<PathBuf>,
 }
 
 impl Default for Settings {
 	fn default() -> Self {
 		Self {
 			log_level: tracing_subscriber::filter::LevelFilter::DEBUG,
+			library_index: data_dir().join("library.ron"),
+			library_roots: std::env::var_os("MR_LIB_DIR")
+				.map(|dirs| std::env::split_paths(&dirs).collect())
+				.unwrap_or_default(),
+			open: std::env::args_os().nth(1).map(PathBuf::from),
 		}
 	}
 }
+
+/// Where See Augmented keeps its own files.
+fn data_dir() -> PathBuf {
+	let home = || std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(PathBuf::from);
+	if let Some(data) = std::env::var_os("XDG_DATA_HOME").filter(|data| !data.is_empty()) {
+		PathBuf::from(data).join("see-aug")
+	} else if let Some(data) = std::env::var_os("APPDATA") {
+		PathBuf::from(data).join("see-aug")
+	} else if let Some(home) = home() {
+		home.join(".local/share/see-aug")
+	} else {
+		PathBuf::from(".see-aug")
+	}
+}